@@ -1,14 +1,35 @@
-use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use criterion::measurement::WallTime;
+use criterion::{
+    BenchmarkGroup, BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main,
+};
+use hft_engine::L3OrderBook;
 use hft_engine::OrderBook;
 use hft_engine::core::types::{Price, Quantity};
-use hft_engine::messages::Side;
+use hft_engine::messages::{BBO, DEPTH_10, DEPTH_50, Side};
+
+// Runs `bench` once per depth preset so every group reports BBO, 10 and 50
+// levels side by side.
+macro_rules! for_each_depth {
+    ($group:expr, $bench:ident) => {
+        $bench::<BBO>(&mut $group, "bbo");
+        $bench::<DEPTH_10>(&mut $group, "10");
+        $bench::<DEPTH_50>(&mut $group, "50");
+    };
+}
 
-fn bench_order_book_update(c: &mut Criterion) {
-    let mut group = c.benchmark_group("order_book_update");
-    group.throughput(Throughput::Elements(1));
+// Both sides filled to DEPTH around 100.00 / 101.00.
+fn full_book<const DEPTH: usize>() -> OrderBook<DEPTH> {
+    let mut book = OrderBook::<DEPTH>::new();
+    for i in 0..DEPTH as i64 {
+        book.update_level(Side::Buy, Price::new(100 - i, 0), Quantity::new(10, 0));
+        book.update_level(Side::Sell, Price::new(101 + i, 0), Quantity::new(10, 0));
+    }
+    book
+}
 
-    group.bench_function("update_single_level", |b| {
-        let mut book = OrderBook::new();
+fn update_single_level<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.bench_function(BenchmarkId::new("update_single_level", depth), |b| {
+        let mut book = OrderBook::<DEPTH>::new();
         let mut counter = 0u64;
 
         b.iter(|| {
@@ -18,69 +39,73 @@ fn bench_order_book_update(c: &mut Criterion) {
             counter += 1;
         });
     });
+}
 
-    group.bench_function("update_filled_book", |b| {
-        let mut book = OrderBook::new();
-
-        for i in 0..5 {
-            book.update_level(Side::Buy, Price::new(100 - i, 0), Quantity::new(10, 0));
-            book.update_level(Side::Sell, Price::new(101 + i, 0), Quantity::new(10, 0));
-        }
-
+// Touches a level at the back of a full book, the worst case for the linear
+// position search.
+fn update_filled_book<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.bench_function(BenchmarkId::new("update_filled_book", depth), |b| {
+        let mut book = full_book::<DEPTH>();
+        let deepest = Price::new(101 - DEPTH as i64, 0);
         let mut counter = 0u64;
+
         b.iter(|| {
-            let price = Price::new(100 + (counter % 10) as i64, 0);
-            let qty = Quantity::new(10, 0);
-            book.update_level(black_box(Side::Buy), black_box(price), black_box(qty));
+            let qty = Quantity::new(10 + (counter % 10) as i64, 0);
+            book.update_level(black_box(Side::Buy), black_box(deepest), black_box(qty));
             counter += 1;
         });
     });
-
-    group.finish();
 }
 
-fn bench_order_book_insert(c: &mut Criterion) {
-    let mut group = c.benchmark_group("order_book_insert");
-    group.throughput(Throughput::Elements(1));
-
-    group.bench_function("insert_new_level", |b| {
-        let mut counter = 0u64;
+// Inserts at the top of a full book, shifting every level down one slot.
+fn insert_at_top<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.bench_function(BenchmarkId::new("insert_at_top", depth), |b| {
+        let book = full_book::<DEPTH>();
 
         b.iter(|| {
-            let mut book = OrderBook::new();
-            let price = Price::new(100 + counter as i64, 0);
-            let qty = Quantity::new(10, 0);
-            book.update_level(black_box(Side::Buy), black_box(price), black_box(qty));
-            counter += 1;
+            let mut book = book.clone();
+            book.update_level(
+                black_box(Side::Buy),
+                black_box(Price::new(100, 5000)),
+                black_box(Quantity::new(10, 0)),
+            );
             black_box(book);
         });
     });
-
-    group.finish();
 }
 
-fn bench_order_book_remove(c: &mut Criterion) {
-    let mut group = c.benchmark_group("order_book_remove");
-    group.throughput(Throughput::Elements(1));
-
-    group.bench_function("remove_level", |b| {
-        let mut counter = 0u64;
+fn remove_top<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.bench_function(BenchmarkId::new("remove_top", depth), |b| {
+        let book = full_book::<DEPTH>();
 
         b.iter(|| {
-            let mut book = OrderBook::new();
-            let price = Price::new(100, 0);
-
-            book.update_level(Side::Buy, price, Quantity::new(10, 0));
-
+            let mut book = book.clone();
             book.update_level(
                 black_box(Side::Buy),
-                black_box(price),
+                black_box(Price::new(100, 0)),
                 black_box(Quantity::new(0, 0)),
             );
-            counter += 1;
             black_box(book);
         });
     });
+}
+
+fn bench_order_book_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_book_update");
+    group.throughput(Throughput::Elements(1));
+
+    for_each_depth!(group, update_single_level);
+    for_each_depth!(group, update_filled_book);
+
+    group.finish();
+}
+
+fn bench_order_book_insert_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_book_insert_remove");
+    group.throughput(Throughput::Elements(1));
+
+    for_each_depth!(group, insert_at_top);
+    for_each_depth!(group, remove_top);
 
     group.finish();
 }
@@ -89,7 +114,7 @@ fn bench_order_book_accessors(c: &mut Criterion) {
     let mut group = c.benchmark_group("order_book_accessors");
     group.throughput(Throughput::Elements(1));
 
-    let mut book = OrderBook::new();
+    let mut book = OrderBook::<DEPTH_10>::new();
     book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
     book.update_level(Side::Sell, Price::new(101, 0), Quantity::new(10, 0));
 
@@ -120,43 +145,53 @@ fn bench_order_book_accessors(c: &mut Criterion) {
     group.finish();
 }
 
-fn bench_order_book_full_depth(c: &mut Criterion) {
-    let mut group = c.benchmark_group("order_book_full_depth");
+fn bench_order_book_analytics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_book_analytics");
+    group.throughput(Throughput::Elements(1));
 
-    for depth in [5, 10] {
-        group.throughput(Throughput::Elements(depth as u64));
-        group.bench_with_input(BenchmarkId::from_parameter(depth), &depth, |b, &depth| {
-            b.iter(|| {
-                let mut book = OrderBook::new();
-
-                for i in 0..depth {
-                    book.update_level(
-                        Side::Buy,
-                        Price::new(100 - i as i64, 0),
-                        Quantity::new(10, 0),
-                    );
-                    book.update_level(
-                        Side::Sell,
-                        Price::new(101 + i as i64, 0),
-                        Quantity::new(10, 0),
-                    );
-                }
+    let book = full_book::<DEPTH_10>();
+
+    group.bench_function("microprice", |b| {
+        b.iter(|| black_box(book.microprice()));
+    });
 
-                black_box(book);
-            });
+    group.bench_function("imbalance_5", |b| {
+        b.iter(|| black_box(book.imbalance(black_box(5))));
+    });
+
+    group.bench_function("depth_within_5_ticks", |b| {
+        b.iter(|| {
+            black_box(book.depth_within(Side::Sell, black_box(5), Price::new(1, 0)));
         });
-    }
+    });
+
+    // Eats into the 8th level of 10.
+    group.bench_function("sweep_deep", |b| {
+        b.iter(|| black_box(book.sweep(Side::Buy, black_box(Quantity::new(75, 0)))));
+    });
 
     group.finish();
 }
 
-fn bench_order_book_realistic_updates(c: &mut Criterion) {
-    let mut group = c.benchmark_group("order_book_realistic");
-    group.throughput(Throughput::Elements(100));
+fn fill<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.throughput(Throughput::Elements(2 * DEPTH as u64));
+    group.bench_function(BenchmarkId::from_parameter(depth), |b| {
+        b.iter(|| black_box(full_book::<DEPTH>()));
+    });
+}
+
+fn bench_order_book_full_depth(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_book_full_depth");
+
+    for_each_depth!(group, fill);
 
-    group.bench_function("mixed_operations", |b| {
+    group.finish();
+}
+
+fn mixed_operations<const DEPTH: usize>(group: &mut BenchmarkGroup<WallTime>, depth: &str) {
+    group.bench_function(BenchmarkId::new("mixed_operations", depth), |b| {
         b.iter(|| {
-            let mut book = OrderBook::new();
+            let mut book = OrderBook::<DEPTH>::new();
 
             for i in 0..100 {
                 let op = i % 3;
@@ -181,6 +216,47 @@ fn bench_order_book_realistic_updates(c: &mut Criterion) {
             black_box(book);
         });
     });
+}
+
+fn bench_order_book_realistic_updates(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_book_realistic");
+    group.throughput(Throughput::Elements(100));
+
+    for_each_depth!(group, mixed_operations);
+
+    group.finish();
+}
+
+// Add then cancel one order behind a 100-deep queue at the touch, the common
+// churn on an order-by-order feed.
+fn bench_l3_add_delete(c: &mut Criterion) {
+    let mut group = c.benchmark_group("l3_book");
+    group.throughput(Throughput::Elements(2));
+
+    let mut book = L3OrderBook::new(4096, 256);
+    for id in 0..100 {
+        book.add(id, Side::Buy, Price::new(100, 0), Quantity::new(10, 0))
+            .unwrap();
+    }
+    let mut next_id = 1_000u64;
+
+    group.bench_function("add_delete", |b| {
+        b.iter(|| {
+            book.add(
+                black_box(next_id),
+                Side::Buy,
+                black_box(Price::new(100, 0)),
+                Quantity::new(5, 0),
+            )
+            .unwrap();
+            book.delete(black_box(next_id)).unwrap();
+            next_id += 1;
+        });
+    });
+
+    group.bench_function("queue_position", |b| {
+        b.iter(|| black_box(book.queue_position(black_box(50))));
+    });
 
     group.finish();
 }
@@ -188,10 +264,11 @@ fn bench_order_book_realistic_updates(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_order_book_update,
-    bench_order_book_insert,
-    bench_order_book_remove,
+    bench_order_book_insert_remove,
     bench_order_book_accessors,
+    bench_order_book_analytics,
     bench_order_book_full_depth,
-    bench_order_book_realistic_updates
+    bench_order_book_realistic_updates,
+    bench_l3_add_delete
 );
 criterion_main!(benches);
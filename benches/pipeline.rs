@@ -1,10 +1,11 @@
 use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
-use hft_engine::OrderBook;
-use hft_engine::core::spsc::SpscQueue;
+use hft_engine::Book10;
 use hft_engine::core::thread::pin_to_cpu;
 use hft_engine::core::types::{Price, Quantity, Timestamp};
+use hft_engine::core::{Histogram, TscClock, rdtsc, spsc};
 use hft_engine::messages::{
-    MAX_LEVELS, MarketEvent, Order, PriceLevel, RiskDecision, Side, SignalEvent,
+    BookSnapshot, DEFAULT_DEPTH, MarketEvent, Order, PriceLevel, RiskDecision, Side, SignalEvent,
+    Trace,
 };
 
 use std::sync::Arc;
@@ -12,9 +13,9 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 
-fn copy_levels(levels: &[PriceLevel]) -> [PriceLevel; MAX_LEVELS] {
-    let mut result = [PriceLevel::empty(); MAX_LEVELS];
-    let count = levels.len().min(MAX_LEVELS);
+fn copy_levels(levels: &[PriceLevel]) -> [PriceLevel; DEFAULT_DEPTH] {
+    let mut result = [PriceLevel::empty(); DEFAULT_DEPTH];
+    let count = levels.len().min(DEFAULT_DEPTH);
     result[..count].copy_from_slice(&levels[..count]);
     result
 }
@@ -25,7 +26,7 @@ fn bench_pipeline_single_threaded(c: &mut Criterion) {
 
     group.bench_function("market_to_strategy", |b| {
         b.iter(|| {
-            let mut book = OrderBook::new();
+            let mut book = Book10::new();
             let mut signals = 0;
 
             for i in 0..1000 {
@@ -35,10 +36,10 @@ fn bench_pipeline_single_threaded(c: &mut Criterion) {
                 book.update_level(Side::Buy, bid, Quantity::new(100, 0));
                 book.update_level(Side::Sell, ask, Quantity::new(100, 0));
 
-                if let Some(spread) = book.spread() {
-                    if spread.raw() < 5000 {
-                        signals += 1;
-                    }
+                if let Some(spread) = book.spread()
+                    && spread.raw() < 5000
+                {
+                    signals += 1;
                 }
             }
 
@@ -56,10 +57,9 @@ fn bench_pipeline_two_threads(c: &mut Criterion) {
 
     group.bench_function("market_strategy_10k", |b| {
         b.iter(|| {
-            let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(1024));
+            let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(1024);
             let running = Arc::new(AtomicBool::new(true));
 
-            let m2s = market_to_strategy.clone();
             let run1 = running.clone();
 
             let strategy_handle = thread::spawn(move || {
@@ -67,7 +67,7 @@ fn bench_pipeline_two_threads(c: &mut Criterion) {
                 let mut count = 0;
 
                 while run1.load(Ordering::Relaxed) {
-                    if let Some(event) = m2s.pop() {
+                    if let Some(event) = strategy_rx.pop() {
                         black_box(event);
                         count += 1;
                         if count >= 10000 {
@@ -79,10 +79,9 @@ fn bench_pipeline_two_threads(c: &mut Criterion) {
                 count
             });
 
-            let m2s = market_to_strategy.clone();
             let market_handle = thread::spawn(move || {
                 let _ = pin_to_cpu(0);
-                let mut book = OrderBook::new();
+                let mut book = Book10::new();
 
                 for i in 0..10000 {
                     let bid = Price::new(10000 + (i % 10) as i64, 0);
@@ -91,14 +90,15 @@ fn bench_pipeline_two_threads(c: &mut Criterion) {
                     book.update_level(Side::Buy, bid, Quantity::new(100, 0));
                     book.update_level(Side::Sell, ask, Quantity::new(100, 0));
 
-                    let event = MarketEvent::BookUpdate {
+                    let event = MarketEvent::BookUpdate(BookSnapshot {
                         symbol: 1,
                         timestamp: Timestamp::from_cycles(unsafe { core::arch::x86_64::_rdtsc() }),
                         bids: copy_levels(book.bids()),
                         asks: copy_levels(book.asks()),
-                    };
+                        seq: 0,
+                    });
 
-                    while m2s.push(event).is_err() {
+                    while market_tx.push(event).is_err() {
                         std::hint::spin_loop();
                     }
                 }
@@ -126,19 +126,18 @@ fn bench_pipeline_full(c: &mut Criterion) {
             &num_events,
             |b, &num_events| {
                 b.iter(|| {
-                    let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(1024));
-                    let strategy_to_risk = Arc::new(SpscQueue::<SignalEvent>::new(256));
-                    let risk_to_gateway = Arc::new(SpscQueue::<RiskDecision>::new(256));
+                    let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(1024);
+                    let (mut strategy_tx, mut risk_rx) = spsc::channel::<SignalEvent>(256);
+                    let (mut risk_tx, mut gateway_rx) = spsc::channel::<RiskDecision>(256);
                     let running = Arc::new(AtomicBool::new(true));
 
-                    let r2g = risk_to_gateway.clone();
                     let run3 = running.clone();
                     let gateway_handle = thread::spawn(move || {
                         let _ = pin_to_cpu(3);
                         let mut count = 0;
 
-                        while run3.load(Ordering::Relaxed) || r2g.pop().is_some() {
-                            if let Some(decision) = r2g.pop() {
+                        while run3.load(Ordering::Relaxed) || gateway_rx.pop().is_some() {
+                            if let Some(decision) = gateway_rx.pop() {
                                 black_box(decision);
                                 count += 1;
                                 if count >= num_events {
@@ -149,15 +148,13 @@ fn bench_pipeline_full(c: &mut Criterion) {
                         count
                     });
 
-                    let s2r = strategy_to_risk.clone();
-                    let r2g = risk_to_gateway.clone();
                     let run2 = running.clone();
                     let risk_handle = thread::spawn(move || {
                         let _ = pin_to_cpu(2);
                         let mut count = 0;
 
-                        while run2.load(Ordering::Relaxed) || s2r.pop().is_some() {
-                            if let Some(signal) = s2r.pop() {
+                        while run2.load(Ordering::Relaxed) || risk_rx.pop().is_some() {
+                            if let Some(signal) = risk_rx.pop() {
                                 if let SignalEvent::Cancel { .. } = signal {
                                     continue;
                                 }
@@ -187,7 +184,7 @@ fn bench_pipeline_full(c: &mut Criterion) {
                                     timestamp,
                                 ));
 
-                                while r2g.push(decision).is_err() {
+                                while risk_tx.push(decision).is_err() {
                                     std::hint::spin_loop();
                                 }
                                 count += 1;
@@ -195,43 +192,37 @@ fn bench_pipeline_full(c: &mut Criterion) {
                         }
                     });
 
-                    let m2s = market_to_strategy.clone();
-                    let s2r = strategy_to_risk.clone();
                     let run1 = running.clone();
                     let strategy_handle = thread::spawn(move || {
                         let _ = pin_to_cpu(1);
-                        let mut count = 0;
 
-                        while run1.load(Ordering::Relaxed) || m2s.pop().is_some() {
-                            if let Some(event) = m2s.pop() {
-                                if let MarketEvent::BookUpdate {
+                        while run1.load(Ordering::Relaxed) || strategy_rx.pop().is_some() {
+                            if let Some(event) = strategy_rx.pop()
+                                && let MarketEvent::BookUpdate(BookSnapshot {
                                     bids, timestamp, ..
-                                } = event
-                                {
-                                    if let Some(bid) = bids.first() {
-                                        if bid.qty.raw() > 0 {
-                                            let signal = SignalEvent::Buy {
-                                                symbol: 1,
-                                                timestamp,
-                                                price: bid.price,
-                                                qty: Quantity::new(10, 0),
-                                            };
-
-                                            while s2r.push(signal).is_err() {
-                                                std::hint::spin_loop();
-                                            }
-                                            count += 1;
-                                        }
-                                    }
+                                }) = event
+                                && let Some(bid) = bids.first()
+                                && bid.qty.raw() > 0
+                            {
+                                let signal = SignalEvent::Buy {
+                                    strategy_id: 0,
+                                    symbol: 1,
+                                    timestamp,
+                                    price: bid.price,
+                                    qty: Quantity::new(10, 0),
+                                    trace: Trace::from_origin(timestamp),
+                                };
+
+                                while strategy_tx.push(signal).is_err() {
+                                    std::hint::spin_loop();
                                 }
                             }
                         }
                     });
 
-                    let m2s = market_to_strategy.clone();
                     let market_handle = thread::spawn(move || {
                         let _ = pin_to_cpu(0);
-                        let mut book = OrderBook::new();
+                        let mut book = Book10::new();
 
                         for i in 0..num_events {
                             let bid = Price::new(10000 + (i % 10) as i64, 0);
@@ -240,16 +231,17 @@ fn bench_pipeline_full(c: &mut Criterion) {
                             book.update_level(Side::Buy, bid, Quantity::new(100, 0));
                             book.update_level(Side::Sell, ask, Quantity::new(100, 0));
 
-                            let event = MarketEvent::BookUpdate {
+                            let event = MarketEvent::BookUpdate(BookSnapshot {
                                 symbol: 1,
                                 timestamp: Timestamp::from_cycles(unsafe {
                                     core::arch::x86_64::_rdtsc()
                                 }),
                                 bids: copy_levels(book.bids()),
                                 asks: copy_levels(book.asks()),
-                            };
+                                seq: 0,
+                            });
 
-                            while m2s.push(event).is_err() {
+                            while market_tx.push(event).is_err() {
                                 std::hint::spin_loop();
                             }
                         }
@@ -279,10 +271,9 @@ fn bench_pipeline_throughput(c: &mut Criterion) {
 
     group.bench_function("max_throughput_10s", |b| {
         b.iter_custom(|iters| {
-            let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(4096));
+            let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(4096);
             let running = Arc::new(AtomicBool::new(true));
 
-            let m2s = market_to_strategy.clone();
             let run1 = running.clone();
 
             let consumer_handle = thread::spawn(move || {
@@ -290,47 +281,110 @@ fn bench_pipeline_throughput(c: &mut Criterion) {
                 let mut count = 0u64;
 
                 while run1.load(Ordering::Relaxed) {
-                    if m2s.pop().is_some() {
+                    if strategy_rx.pop().is_some() {
                         count += 1;
                     }
                 }
                 count
             });
 
-            let m2s = market_to_strategy.clone();
             let run0 = running.clone();
             let start = Instant::now();
 
             thread::spawn(move || {
                 let _ = pin_to_cpu(0);
-                let mut book = OrderBook::new();
+                let mut book = Book10::new();
                 let mut i = 0u64;
 
                 while run0.load(Ordering::Relaxed) {
                     let bid = Price::new(10000 + (i % 10) as i64, 0);
                     book.update_level(Side::Buy, bid, Quantity::new(100, 0));
 
-                    let event = MarketEvent::BookUpdate {
+                    let event = MarketEvent::BookUpdate(BookSnapshot {
                         symbol: 1,
                         timestamp: Timestamp::from_cycles(unsafe { core::arch::x86_64::_rdtsc() }),
                         bids: copy_levels(book.bids()),
                         asks: copy_levels(book.asks()),
-                    };
+                        seq: 0,
+                    });
 
-                    if m2s.push(event).is_ok() {
+                    if market_tx.push(event).is_ok() {
                         i += 1;
                     }
                 }
             });
 
-            thread::sleep(Duration::from_millis(100 * iters as u64));
+            thread::sleep(Duration::from_millis(100 * iters));
             running.store(false, Ordering::Relaxed);
 
             let _count = consumer_handle.join().unwrap();
-            let elapsed = start.elapsed();
+
+            start.elapsed()
+        });
+    });
+
+    group.finish();
+}
+
+fn bench_pipeline_hop_latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline_hop_latency");
+    group.sample_size(10);
+
+    group.bench_function("market_strategy_10k", |b| {
+        let mut histogram = Histogram::new();
+
+        b.iter_custom(|iters| {
+            let mut elapsed = Duration::ZERO;
+
+            for _ in 0..iters {
+                let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(1024);
+
+                let consumer_handle = thread::spawn(move || {
+                    let _ = pin_to_cpu(1);
+                    let mut hops = Histogram::new();
+
+                    while hops.count() < 10000 {
+                        if let Some(event) = strategy_rx.pop() {
+                            hops.record(rdtsc() - event.timestamp());
+                        }
+                    }
+                    hops
+                });
+
+                let start = Instant::now();
+
+                for i in 0..10000u64 {
+                    let event = MarketEvent::Tick {
+                        symbol: 1,
+                        price: Price::new(10000 + (i % 10) as i64, 0),
+                        qty: Quantity::new(100, 0),
+                        side: Side::Buy,
+                        timestamp: rdtsc(),
+                    };
+
+                    while market_tx.push(event).is_err() {
+                        std::hint::spin_loop();
+                    }
+                }
+
+                histogram.merge(&consumer_handle.join().unwrap());
+                elapsed += start.elapsed();
+            }
 
             elapsed
         });
+
+        let clock = TscClock::global();
+        let ns = |q: f64| clock.cycles_to_nanos(histogram.percentile(q));
+        println!(
+            "hop latency: p50={}ns p90={}ns p99={}ns p99.9={}ns p99.99={}ns max={}ns",
+            ns(50.0),
+            ns(90.0),
+            ns(99.0),
+            ns(99.9),
+            ns(99.99),
+            clock.cycles_to_nanos(histogram.max())
+        );
     });
 
     group.finish();
@@ -341,6 +395,7 @@ criterion_group!(
     bench_pipeline_single_threaded,
     bench_pipeline_two_threads,
     bench_pipeline_full,
-    bench_pipeline_throughput
+    bench_pipeline_throughput,
+    bench_pipeline_hop_latency
 );
 criterion_main!(benches);
@@ -1,6 +1,11 @@
 use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
-use hft_engine::core::{SpscQueue, rdtsc};
+use crossbeam_utils::CachePadded;
+use hft_engine::core::types::{Price, Quantity, Timestamp};
+use hft_engine::core::{SpscQueue, rdtsc, spsc};
+use hft_engine::messages::{BookSnapshot, DEFAULT_DEPTH, MarketEvent, PriceLevel};
+use std::cell::UnsafeCell;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 
 fn bench_spsc_single_threaded(c: &mut Criterion) {
@@ -80,12 +85,96 @@ fn bench_spsc_multi_threaded(c: &mut Criterion) {
     for size in [1024, 4096] {
         group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
             b.iter(|| {
-                let queue = Arc::new(SpscQueue::new(size));
-                let queue_clone = queue.clone();
+                let (mut tx, mut rx) = spsc::channel(size);
 
                 let producer = thread::spawn(move || {
                     for i in 0u64..10000 {
-                        while queue_clone.push(i).is_err() {
+                        while tx.push(i).is_err() {
+                            std::hint::spin_loop();
+                        }
+                    }
+                });
+
+                let consumer = thread::spawn(move || {
+                    for _ in 0..10000 {
+                        while rx.pop().is_none() {
+                            std::hint::spin_loop();
+                        }
+                    }
+                });
+
+                producer.join().unwrap();
+                consumer.join().unwrap();
+            });
+        });
+    }
+
+    group.finish();
+}
+
+// Baseline ring without cached indices: every push/pop loads the other
+// side's index, as `SpscQueue` did before it cached them.
+struct UncachedQueue {
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    buffer: Box<[UnsafeCell<u64>]>,
+    mask: usize,
+}
+
+unsafe impl Sync for UncachedQueue {}
+
+impl UncachedQueue {
+    fn new(capacity: usize) -> Self {
+        UncachedQueue {
+            head: Default::default(),
+            tail: Default::default(),
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+            mask: capacity - 1,
+        }
+    }
+
+    #[inline(always)]
+    fn push(&self, value: u64) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) == self.buffer.len() {
+            return false;
+        }
+
+        unsafe { *self.buffer[tail & self.mask].get() = value };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<u64> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { *self.buffer[head & self.mask].get() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+fn bench_spsc_index_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc_index_cache");
+    group.throughput(Throughput::Elements(10000));
+
+    for size in [1024, 4096] {
+        group.bench_with_input(BenchmarkId::new("uncached", size), &size, |b, &size| {
+            b.iter(|| {
+                let queue = Arc::new(UncachedQueue::new(size));
+                let producer_queue = queue.clone();
+
+                let producer = thread::spawn(move || {
+                    for i in 0u64..10000 {
+                        while !producer_queue.push(i) {
                             std::hint::spin_loop();
                         }
                     }
@@ -103,11 +192,162 @@ fn bench_spsc_multi_threaded(c: &mut Criterion) {
                 consumer.join().unwrap();
             });
         });
+
+        group.bench_with_input(BenchmarkId::new("cached", size), &size, |b, &size| {
+            b.iter(|| {
+                let (mut tx, mut rx) = spsc::channel(size);
+
+                let producer = thread::spawn(move || {
+                    for i in 0u64..10000 {
+                        while tx.push(i).is_err() {
+                            std::hint::spin_loop();
+                        }
+                    }
+                });
+
+                let consumer = thread::spawn(move || {
+                    for _ in 0..10000 {
+                        while rx.pop().is_none() {
+                            std::hint::spin_loop();
+                        }
+                    }
+                });
+
+                producer.join().unwrap();
+                consumer.join().unwrap();
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_spsc_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc_batch");
+
+    for batch in [8, 32, 128] {
+        group.throughput(Throughput::Elements(batch as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &batch, |b, &batch| {
+            let queue = SpscQueue::new(1024);
+            let values = vec![42u64; batch];
+            let mut out = vec![0u64; batch];
+
+            b.iter(|| {
+                queue.push_batch(black_box(&values));
+                black_box(queue.pop_batch(&mut out));
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_spsc_multi_threaded_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc_multi_threaded_batch");
+    group.throughput(Throughput::Elements(10000));
+
+    for batch in [8, 32] {
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &batch, |b, &batch| {
+            b.iter(|| {
+                let (mut tx, mut rx) = spsc::channel(1024);
+
+                let producer = thread::spawn(move || {
+                    let values: Vec<u64> = (0..batch as u64).collect();
+                    let mut sent = 0;
+                    while sent < 10000 {
+                        let n = (10000 - sent).min(batch);
+                        let mut offset = 0;
+                        while offset < n {
+                            offset += tx.push_batch(&values[offset..n]);
+                        }
+                        sent += n;
+                    }
+                });
+
+                let consumer = thread::spawn(move || {
+                    let mut out = vec![0u64; batch];
+                    let mut received = 0;
+                    while received < 10000 {
+                        received += rx.pop_batch(&mut out);
+                    }
+                });
+
+                producer.join().unwrap();
+                consumer.join().unwrap();
+            });
+        });
     }
 
     group.finish();
 }
 
+fn book_update(seq: u64) -> MarketEvent {
+    let mut bids = [PriceLevel::empty(); DEFAULT_DEPTH];
+    let mut asks = [PriceLevel::empty(); DEFAULT_DEPTH];
+    for i in 0..DEFAULT_DEPTH {
+        bids[i] = PriceLevel::new(Price::new(100 - i as i64, 0), Quantity::new(10, 0));
+        asks[i] = PriceLevel::new(Price::new(101 + i as i64, 0), Quantity::new(10, 0));
+    }
+
+    MarketEvent::BookUpdate(BookSnapshot {
+        symbol: 1,
+        bids,
+        asks,
+        seq,
+        timestamp: Timestamp::from_cycles(seq),
+    })
+}
+
+fn bench_spsc_book_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc_book_update");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("push_pop", |b| {
+        let queue = SpscQueue::new(256);
+        let mut seq = 0u64;
+
+        b.iter(|| {
+            queue.push(book_update(black_box(seq))).unwrap();
+            black_box(queue.pop().unwrap());
+            seq += 1;
+        });
+    });
+
+    // The same `BookUpdate`, with the levels written straight into the
+    // reserved slot without building the event first.
+    group.bench_function("reserve_commit", |b| {
+        let mut queue = SpscQueue::<MarketEvent>::new(256);
+        let mut seq = 0u64;
+
+        b.iter(|| {
+            let mut slot = queue.reserve().unwrap();
+            unsafe {
+                let ptr = MarketEvent::book_update_in(slot.as_mut_ptr());
+                std::ptr::addr_of_mut!((*ptr).symbol).write(1);
+                std::ptr::addr_of_mut!((*ptr).seq).write(black_box(seq));
+                std::ptr::addr_of_mut!((*ptr).timestamp).write(Timestamp::from_cycles(seq));
+                let bids = std::ptr::addr_of_mut!((*ptr).bids) as *mut PriceLevel;
+                let asks = std::ptr::addr_of_mut!((*ptr).asks) as *mut PriceLevel;
+                for i in 0..DEFAULT_DEPTH {
+                    bids.add(i).write(PriceLevel::new(
+                        Price::new(100 - i as i64, 0),
+                        Quantity::new(10, 0),
+                    ));
+                    asks.add(i).write(PriceLevel::new(
+                        Price::new(101 + i as i64, 0),
+                        Quantity::new(10, 0),
+                    ));
+                }
+                slot.commit();
+            }
+            black_box(queue.pop().unwrap());
+            seq += 1;
+        });
+    });
+
+    group.finish();
+}
+
 fn bench_rdtsc(c: &mut Criterion) {
     let mut group = c.benchmark_group("rdtsc");
     group.throughput(Throughput::Elements(1));
@@ -146,6 +386,10 @@ criterion_group!(
     bench_spsc_push,
     bench_spsc_pop,
     bench_spsc_multi_threaded,
+    bench_spsc_index_cache,
+    bench_spsc_batch,
+    bench_spsc_multi_threaded_batch,
+    bench_spsc_book_update,
     bench_rdtsc,
     bench_rdtsc_latency_measurement
 );
@@ -0,0 +1,96 @@
+#[cfg(unix)]
+use hft_engine::core::shm_metrics::{DEFAULT_METRICS_SEGMENT, MetricsSegment, StageSnapshot};
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let name = args
+        .next()
+        .unwrap_or_else(|| DEFAULT_METRICS_SEGMENT.to_string());
+    let interval = Duration::from_millis(
+        args.next()
+            .map(|ms| {
+                ms.parse()
+                    .expect("interval must be a number of milliseconds")
+            })
+            .unwrap_or(1000),
+    );
+
+    let segment = match MetricsSegment::open(&name) {
+        Ok(segment) => segment,
+        Err(e) => {
+            eprintln!("hft-monitor: cannot attach to /{}: {}", name, e);
+            std::process::exit(1);
+        }
+    };
+
+    let clock = segment.clock();
+    println!(
+        "Attached to /{} ({} stages, {:?}, {:.3} cycles/ns)",
+        name,
+        segment.stage_count(),
+        clock.source(),
+        clock.cycles_per_ns()
+    );
+
+    let mut previous: Vec<StageSnapshot> = (0..segment.stage_count())
+        .map(|stage| segment.read(stage))
+        .collect();
+    let mut last = Instant::now();
+
+    loop {
+        thread::sleep(interval);
+
+        let elapsed = last.elapsed().as_secs_f64();
+        last = Instant::now();
+
+        println!(
+            "\n{:<14} {:>12} {:>12} {:>10} {:>7} {:>9} {:>9} {:>9} {:>9} {:>9}",
+            "stage",
+            "rate/s",
+            "sent/s",
+            "rejected/s",
+            "depth",
+            "p50",
+            "p99",
+            "p99.9",
+            "p99.99",
+            "max"
+        );
+
+        for (stage, prev) in previous.iter_mut().enumerate() {
+            let current = segment.read(stage);
+            let interval = current.latency.since(&prev.latency);
+            // The latency count only covers sampled messages, so rates come
+            // from the stage's own counters.
+            let rate = |now: u64, before: u64| now.saturating_sub(before) as f64 / elapsed;
+            let ns = |cycles: u64| clock.cycles_to_nanos(cycles);
+
+            println!(
+                "{:<14} {:>12.0} {:>12.0} {:>10.0} {:>7} {:>7}ns {:>7}ns {:>7}ns {:>7}ns {:>7}ns",
+                segment.stage_name(stage),
+                rate(current.counts.processed, prev.counts.processed),
+                rate(current.counts.sent, prev.counts.sent),
+                rate(current.counts.rejected, prev.counts.rejected),
+                current.queue_depth,
+                ns(interval.percentile(50.0)),
+                ns(interval.percentile(99.0)),
+                ns(interval.percentile(99.9)),
+                ns(interval.percentile(99.99)),
+                ns(interval.max())
+            );
+
+            *prev = current;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("hft-monitor: shared-memory metrics are only available on unix");
+    std::process::exit(1);
+}
@@ -0,0 +1,427 @@
+use crate::core::memory::{Allocator, Buffer};
+use crate::core::wait::{Parker, WakeList};
+use crossbeam_utils::CachePadded;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering, fence};
+
+// Each slot carries a seqlock stamp: `2 * pos + 1` while message `pos` is
+// being written and `2 * pos + 2` once it is published. A reader expecting
+// `pos` that sees a larger stamp has been lapped by the writer.
+struct Slot<T> {
+    seq: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// Subscribers from `subscribe_gated` publish their cursor in a gate, and
+// `has_room` holds the publisher back until the slowest one has read the
+// slot it would overwrite. Everyone else can still be lapped.
+pub const MAX_GATED: usize = 32;
+
+struct BroadcastRing<T> {
+    tail: CachePadded<AtomicU64>,
+    slots: Buffer<Slot<T>>,
+    mask: u64,
+    wakers: WakeList,
+    gate_count: AtomicUsize,
+    gates: [CachePadded<AtomicU64>; MAX_GATED],
+}
+
+impl<T> BroadcastRing<T> {
+    fn claim_gate(&self, cursor: u64) -> usize {
+        let index = self.gate_count.fetch_add(1, Ordering::AcqRel);
+        assert!(index < MAX_GATED, "at most {} gated subscribers", MAX_GATED);
+        self.gates[index].store(cursor, Ordering::Release);
+        index
+    }
+
+    // `u64::MAX` without any live gated subscriber.
+    fn slowest_gate(&self) -> u64 {
+        let count = self.gate_count.load(Ordering::Acquire).min(MAX_GATED);
+        self.gates[..count]
+            .iter()
+            .map(|gate| gate.load(Ordering::Acquire))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+unsafe impl<T: Copy + Send> Send for BroadcastRing<T> {}
+unsafe impl<T: Copy + Send> Sync for BroadcastRing<T> {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Overrun { missed: u64 },
+}
+
+pub fn channel<T: Copy>(capacity: usize) -> Publisher<T> {
+    channel_in(capacity, &Allocator::heap())
+}
+
+pub fn channel_in<T: Copy>(capacity: usize, allocator: &Allocator) -> Publisher<T> {
+    assert!(capacity > 0, "capacity must be greater than 0");
+    assert!(capacity.is_power_of_two(), "capacity must be a power of 2");
+
+    let slots = allocator.alloc_with(capacity, |_| Slot {
+        seq: AtomicU64::new(0),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+    });
+
+    Publisher {
+        ring: Arc::new(BroadcastRing {
+            tail: CachePadded::new(AtomicU64::new(0)),
+            slots,
+            mask: capacity as u64 - 1,
+            wakers: WakeList::new(),
+            gate_count: AtomicUsize::new(0),
+            gates: std::array::from_fn(|_| CachePadded::new(AtomicU64::new(u64::MAX))),
+        }),
+        next: 0,
+        limit: 0,
+    }
+}
+
+pub struct Publisher<T> {
+    ring: Arc<BroadcastRing<T>>,
+    next: u64,
+    // First position that may still overwrite a gated subscriber's unread
+    // message, as of the last look at the gates.
+    limit: u64,
+}
+
+impl<T: Copy> Publisher<T> {
+    #[inline(always)]
+    pub fn publish(&mut self, value: T) {
+        let pos = self.next;
+        let slot = unsafe {
+            self.ring
+                .slots
+                .get_unchecked((pos & self.ring.mask) as usize)
+        };
+
+        slot.seq.store(2 * pos + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        unsafe {
+            std::ptr::write_volatile((*slot.value.get()).as_mut_ptr(), value);
+        }
+
+        slot.seq.store(2 * pos + 2, Ordering::Release);
+        self.ring.tail.store(pos + 1, Ordering::Release);
+        self.next = pos + 1;
+        self.ring.wakers.wake();
+    }
+
+    #[inline(always)]
+    pub fn reserve(&mut self) -> BroadcastSlot<'_, T> {
+        let ring = &*self.ring;
+        let pos = self.next;
+        let slot = unsafe { ring.slots.get_unchecked((pos & ring.mask) as usize) };
+
+        slot.seq.store(2 * pos + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        BroadcastSlot {
+            slot,
+            ring,
+            next: &mut self.next,
+        }
+    }
+
+    // Whether the next message can be written without lapping a gated
+    // subscriber. Always true when there are none.
+    #[inline(always)]
+    pub fn has_room(&mut self) -> bool {
+        self.next < self.limit || self.refresh_limit()
+    }
+
+    #[cold]
+    fn refresh_limit(&mut self) -> bool {
+        self.limit = self
+            .ring
+            .slowest_gate()
+            .saturating_add(self.ring.slots.len() as u64);
+        self.next < self.limit
+    }
+
+    pub fn subscribe(&self) -> Subscriber<T> {
+        Subscriber {
+            ring: self.ring.clone(),
+            cursor: self.next,
+            gate: None,
+        }
+    }
+
+    pub fn subscribe_gated(&mut self) -> Subscriber<T> {
+        let gate = self.ring.claim_gate(self.next);
+        self.limit = 0;
+        Subscriber {
+            ring: self.ring.clone(),
+            cursor: self.next,
+            gate: Some(gate),
+        }
+    }
+
+    #[inline]
+    pub fn published(&self) -> u64 {
+        self.next
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+}
+
+// The next slot, already marked as being written, filled in place through
+// `as_mut_ptr` and then `commit`ted. Dropping it uncommitted costs readers
+// the message that was there, as if they had been lapped.
+pub struct BroadcastSlot<'a, T> {
+    slot: &'a Slot<T>,
+    ring: &'a BroadcastRing<T>,
+    next: &'a mut u64,
+}
+
+impl<T> BroadcastSlot<'_, T> {
+    #[inline(always)]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        unsafe { (*self.slot.value.get()).as_mut_ptr() }
+    }
+
+    /// # Safety
+    ///
+    /// The slot must have been fully initialised through `as_mut_ptr`.
+    #[inline(always)]
+    pub unsafe fn commit(self) {
+        let pos = *self.next;
+        self.slot.seq.store(2 * pos + 2, Ordering::Release);
+        self.ring.tail.store(pos + 1, Ordering::Release);
+        *self.next = pos + 1;
+        self.ring.wakers.wake();
+    }
+}
+
+pub struct Subscriber<T> {
+    ring: Arc<BroadcastRing<T>>,
+    cursor: u64,
+    gate: Option<usize>,
+}
+
+impl<T: Copy> Subscriber<T> {
+    #[inline(always)]
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let pos = self.cursor;
+        let expected = 2 * pos + 2;
+        let slot = unsafe {
+            self.ring
+                .slots
+                .get_unchecked((pos & self.ring.mask) as usize)
+        };
+
+        let before = slot.seq.load(Ordering::Acquire);
+        if before < expected {
+            return Err(TryRecvError::Empty);
+        }
+
+        if before == expected {
+            let value = unsafe { std::ptr::read_volatile((*slot.value.get()).as_ptr()) };
+            fence(Ordering::Acquire);
+
+            if slot.seq.load(Ordering::Relaxed) == expected {
+                self.cursor = pos + 1;
+                if let Some(gate) = self.gate {
+                    self.ring.gates[gate].store(pos + 1, Ordering::Release);
+                }
+                return Ok(value);
+            }
+        }
+
+        Err(self.resync())
+    }
+
+    // Lapped readers skip straight to the live head rather than replaying a
+    // backlog the writer is about to overwrite again.
+    #[cold]
+    fn resync(&mut self) -> TryRecvError {
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        let missed = tail - self.cursor;
+        self.cursor = tail;
+        TryRecvError::Overrun { missed }
+    }
+
+    // Has the publisher unpark `parker` after each publish.
+    pub fn register_parker(&self, parker: Arc<Parker>) {
+        self.ring.wakers.register(parker);
+    }
+
+    #[inline]
+    pub fn lag(&self) -> u64 {
+        self.ring.tail.load(Ordering::Acquire) - self.cursor
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+}
+
+// A clone of a gated subscriber gets its own gate; ours keeps the
+// publisher from passing `cursor` until the new one is in place.
+impl<T> Clone for Subscriber<T> {
+    fn clone(&self) -> Self {
+        Subscriber {
+            ring: self.ring.clone(),
+            cursor: self.cursor,
+            gate: self.gate.map(|_| self.ring.claim_gate(self.cursor)),
+        }
+    }
+}
+
+// Dropping a gated subscriber releases the publisher it was holding back.
+impl<T> Drop for Subscriber<T> {
+    fn drop(&mut self) {
+        if let Some(gate) = self.gate {
+            self.ring.gates[gate].store(u64::MAX, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_publish_to_all_subscribers() {
+        let mut publisher = channel(8);
+        let mut a = publisher.subscribe();
+        let mut b = publisher.subscribe();
+
+        assert_eq!(a.try_recv(), Err(TryRecvError::Empty));
+
+        publisher.publish(1u64);
+        publisher.publish(2u64);
+
+        assert_eq!(a.try_recv(), Ok(1));
+        assert_eq!(a.try_recv(), Ok(2));
+        assert_eq!(a.try_recv(), Err(TryRecvError::Empty));
+
+        assert_eq!(b.try_recv(), Ok(1));
+        assert_eq!(b.lag(), 1);
+        assert_eq!(b.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn test_late_subscriber_starts_at_head() {
+        let mut publisher = channel(4);
+        publisher.publish(1u64);
+
+        let mut late = publisher.subscribe();
+        assert_eq!(late.try_recv(), Err(TryRecvError::Empty));
+
+        publisher.publish(2);
+        assert_eq!(late.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn test_slow_subscriber_overrun() {
+        let mut publisher = channel(4);
+        let mut slow = publisher.subscribe();
+
+        for i in 0..10u64 {
+            publisher.publish(i);
+        }
+
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Overrun { missed: 10 }));
+        assert_eq!(slow.try_recv(), Err(TryRecvError::Empty));
+
+        publisher.publish(10);
+        assert_eq!(slow.try_recv(), Ok(10));
+    }
+
+    #[test]
+    fn test_gated_subscriber_holds_publisher() {
+        let mut publisher = channel(4);
+        let mut lossy = publisher.subscribe();
+        let mut gated = publisher.subscribe_gated();
+
+        for i in 0..4u64 {
+            assert!(publisher.has_room());
+            publisher.publish(i);
+        }
+        assert!(!publisher.has_room());
+
+        assert_eq!(gated.try_recv(), Ok(0));
+        assert!(publisher.has_room());
+        publisher.publish(4);
+        assert!(!publisher.has_room());
+
+        let mut clone = gated.clone();
+        drop(gated);
+        assert!(!publisher.has_room());
+        assert_eq!(clone.try_recv(), Ok(1));
+        assert!(publisher.has_room());
+
+        drop(clone);
+        for i in 5..16u64 {
+            assert!(publisher.has_room());
+            publisher.publish(i);
+        }
+        assert_eq!(lossy.try_recv(), Err(TryRecvError::Overrun { missed: 16 }));
+    }
+
+    #[test]
+    fn test_reserve_commit() {
+        let mut publisher = channel::<u64>(4);
+        let mut sub = publisher.subscribe();
+
+        let _ = publisher.reserve();
+        assert_eq!(sub.try_recv(), Err(TryRecvError::Empty));
+
+        let mut slot = publisher.reserve();
+        unsafe {
+            slot.as_mut_ptr().write(5);
+            slot.commit();
+        }
+        publisher.publish(6);
+
+        assert_eq!(sub.try_recv(), Ok(5));
+        assert_eq!(sub.try_recv(), Ok(6));
+        assert_eq!(publisher.published(), 2);
+    }
+
+    #[test]
+    fn test_multi_threaded_fan_out() {
+        const COUNT: u64 = 10_000;
+
+        let mut publisher = channel(16384);
+        let readers: Vec<_> = (0..3)
+            .map(|_| {
+                let mut sub = publisher.subscribe();
+                thread::spawn(move || {
+                    let mut expected = 0u64;
+                    while expected < COUNT {
+                        match sub.try_recv() {
+                            Ok(v) => {
+                                assert_eq!(v, expected);
+                                expected += 1;
+                            }
+                            Err(TryRecvError::Empty) => thread::yield_now(),
+                            Err(e) => panic!("unexpected {:?}", e),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..COUNT {
+            publisher.publish(i);
+        }
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+}
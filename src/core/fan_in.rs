@@ -0,0 +1,106 @@
+use crate::core::spsc::Consumer;
+use crate::core::wait::Parker;
+use std::sync::Arc;
+
+// Round-robin poller over several SPSC consumers. Each producer keeps its own
+// ring, so the fan-in stays lock-free without a shared MPSC tail; starting the
+// scan after the last input served keeps a busy producer from starving others.
+pub struct FanIn<T> {
+    inputs: Box<[Consumer<T>]>,
+    next: usize,
+}
+
+impl<T> FanIn<T> {
+    pub fn new(inputs: Vec<Consumer<T>>) -> Self {
+        assert!(!inputs.is_empty(), "fan-in needs at least one input");
+
+        FanIn {
+            inputs: inputs.into_boxed_slice(),
+            next: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn pop(&mut self) -> Option<T> {
+        let count = self.inputs.len();
+
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+
+            if let Some(value) = self.inputs[index].pop() {
+                self.next = (index + 1) % count;
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
+    pub fn register_parker(&self, parker: Arc<Parker>) {
+        for input in &self.inputs {
+            input.register_parker(parker.clone());
+        }
+    }
+
+    #[inline]
+    pub fn inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inputs.iter().map(Consumer::len).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inputs.iter().all(Consumer::is_empty)
+    }
+}
+
+impl<T> From<Consumer<T>> for FanIn<T> {
+    fn from(input: Consumer<T>) -> Self {
+        FanIn::new(vec![input])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::spsc::channel;
+
+    #[test]
+    fn test_round_robin_fairness() {
+        let (mut a_tx, a_rx) = channel(8);
+        let (mut b_tx, b_rx) = channel(8);
+        let mut fan_in = FanIn::new(vec![a_rx, b_rx]);
+
+        for i in 0..3 {
+            a_tx.push(("a", i)).unwrap();
+            b_tx.push(("b", i)).unwrap();
+        }
+        assert_eq!(fan_in.len(), 6);
+
+        let order: Vec<_> = std::iter::from_fn(|| fan_in.pop()).collect();
+        assert_eq!(
+            order,
+            vec![("a", 0), ("b", 0), ("a", 1), ("b", 1), ("a", 2), ("b", 2)]
+        );
+        assert!(fan_in.is_empty());
+    }
+
+    #[test]
+    fn test_skips_empty_inputs() {
+        let (_a_tx, a_rx) = channel::<u32>(4);
+        let (mut b_tx, b_rx) = channel(4);
+        let mut fan_in = FanIn::new(vec![a_rx, b_rx]);
+
+        assert_eq!(fan_in.pop(), None);
+
+        b_tx.push(1).unwrap();
+        b_tx.push(2).unwrap();
+        assert_eq!(fan_in.pop(), Some(1));
+        assert_eq!(fan_in.pop(), Some(2));
+        assert_eq!(fan_in.pop(), None);
+    }
+}
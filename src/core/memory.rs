@@ -0,0 +1,443 @@
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+pub const PAGE_SIZE: usize = 4 * 1024;
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    Heap,
+    // Explicit 2 MiB pages from the hugetlbfs pool (`MAP_HUGETLB`).
+    HugeTlb,
+    // Anonymous mapping with `MADV_HUGEPAGE`; the kernel promotes it to huge
+    // pages when it can.
+    TransparentHuge,
+    // Anonymous mapping on regular pages, used for `on_node` without
+    // `huge_pages` or when the kernel refuses `MADV_HUGEPAGE`.
+    Mapped,
+}
+
+// Where long-lived hot-path arrays (queue rings, book arrays) get their
+// memory. The default is the plain heap; `huge_pages` and `on_node` switch to
+// an anonymous mapping that is `mbind`-ed to one NUMA node before any page
+// is touched, so first-touch placement cannot put it on the wrong node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Allocator {
+    huge_pages: bool,
+    node: Option<usize>,
+}
+
+impl Allocator {
+    pub const fn heap() -> Self {
+        Allocator {
+            huge_pages: false,
+            node: None,
+        }
+    }
+
+    pub const fn huge_pages(mut self) -> Self {
+        self.huge_pages = true;
+        self
+    }
+
+    pub const fn on_node(mut self, node: usize) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    #[inline]
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+
+    #[inline]
+    pub fn uses_huge_pages(&self) -> bool {
+        self.huge_pages
+    }
+
+    // The next thing to try when this allocator's memory is refused: first
+    // the same mapping without the NUMA binding (`mbind` needs
+    // CAP_SYS_NICE and a NUMA kernel), then the plain heap.
+    pub const fn fallback(&self) -> Option<Allocator> {
+        if self.node.is_some() && self.huge_pages {
+            Some(Allocator::heap().huge_pages())
+        } else if self.node.is_some() || self.huge_pages {
+            Some(Allocator::heap())
+        } else {
+            None
+        }
+    }
+
+    // Never fails: walks down `fallback` until something maps, so the
+    // buffer's `backing` and `node` say what was actually obtained.
+    pub fn alloc_with<T>(&self, len: usize, mut init: impl FnMut(usize) -> T) -> Buffer<T> {
+        let mut allocator = *self;
+        loop {
+            match (
+                allocator.try_alloc_with(len, &mut init),
+                allocator.fallback(),
+            ) {
+                (Ok(buffer), _) => return buffer,
+                (Err(_), Some(next)) => allocator = next,
+                (Err(e), None) => panic!(
+                    "failed to allocate {} x {}: {}",
+                    len,
+                    std::any::type_name::<T>(),
+                    e
+                ),
+            }
+        }
+    }
+
+    pub fn try_alloc_with<T>(
+        &self,
+        len: usize,
+        mut init: impl FnMut(usize) -> T,
+    ) -> io::Result<Buffer<T>> {
+        let layout = Layout::array::<T>(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "allocation too large"))?;
+
+        let (ptr, backing, map_len) = if layout.size() == 0 {
+            (NonNull::<T>::dangling(), Backing::Heap, 0)
+        } else if self.huge_pages || self.node.is_some() {
+            let (ptr, backing, map_len) = map_anonymous(layout, self.huge_pages, self.node)?;
+            (ptr.cast(), backing, map_len)
+        } else {
+            let ptr = unsafe { alloc::alloc(layout) };
+            match NonNull::new(ptr) {
+                Some(ptr) => (ptr.cast(), Backing::Heap, 0),
+                None => alloc::handle_alloc_error(layout),
+            }
+        };
+
+        // `len` tracks how many elements are initialized so a panicking
+        // `init` only drops what it built.
+        let mut buffer = Buffer {
+            ptr,
+            len: 0,
+            layout,
+            map_len,
+            backing,
+            node: self.node.filter(|_| backing != Backing::Heap),
+        };
+        for i in 0..len {
+            unsafe { ptr::write(buffer.ptr.as_ptr().add(i), init(i)) };
+            buffer.len = i + 1;
+        }
+
+        Ok(buffer)
+    }
+}
+
+// Owned, fixed-length slice from an `Allocator`. Derefs to `[T]` so it drops
+// in wherever a `Box<[T]>` was used.
+pub struct Buffer<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    layout: Layout,
+    map_len: usize,
+    backing: Backing,
+    node: Option<usize>,
+}
+
+unsafe impl<T: Send> Send for Buffer<T> {}
+unsafe impl<T: Sync> Sync for Buffer<T> {}
+
+impl<T> Buffer<T> {
+    #[inline]
+    pub fn backing(&self) -> Backing {
+        self.backing
+    }
+
+    #[inline]
+    pub fn node(&self) -> Option<usize> {
+        self.node
+    }
+}
+
+impl<T> Deref for Buffer<T> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> DerefMut for Buffer<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.ptr.as_ptr(), self.len));
+
+            if self.layout.size() == 0 {
+                return;
+            }
+            match self.backing {
+                Backing::Heap => alloc::dealloc(self.ptr.as_ptr().cast(), self.layout),
+                Backing::HugeTlb | Backing::TransparentHuge | Backing::Mapped => {
+                    unmap(self.ptr.cast(), self.map_len)
+                }
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Buffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("len", &self.len)
+            .field("backing", &self.backing)
+            .field("node", &self.node)
+            .finish()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn map_anonymous(
+    layout: Layout,
+    huge_pages: bool,
+    node: Option<usize>,
+) -> io::Result<(NonNull<u8>, Backing, usize)> {
+    use libc::{MAP_ANONYMOUS, MAP_FAILED, MAP_HUGETLB, MAP_PRIVATE, PROT_READ, PROT_WRITE, mmap};
+
+    // Only a hugetlb mapping is aligned beyond the base page size.
+    let too_aligned = |limit| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("alignment {} exceeds {} byte pages", layout.align(), limit),
+        )
+    };
+    if layout.align() > HUGE_PAGE_SIZE {
+        return Err(too_aligned(HUGE_PAGE_SIZE));
+    }
+    if layout.align() > PAGE_SIZE && !huge_pages {
+        return Err(too_aligned(PAGE_SIZE));
+    }
+
+    let len = if huge_pages {
+        layout.size().next_multiple_of(HUGE_PAGE_SIZE)
+    } else {
+        layout.size().next_multiple_of(PAGE_SIZE)
+    };
+    let map = |flags| unsafe {
+        mmap(
+            ptr::null_mut(),
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS | flags,
+            -1,
+            0,
+        )
+    };
+
+    // The hugetlbfs pool is often empty; fall back to THP instead of failing.
+    let mut backing = Backing::Mapped;
+    let mut addr = MAP_FAILED;
+    if huge_pages {
+        addr = map(MAP_HUGETLB);
+        if addr != MAP_FAILED {
+            backing = Backing::HugeTlb;
+        }
+    }
+    if addr == MAP_FAILED {
+        if layout.align() > PAGE_SIZE {
+            return Err(too_aligned(PAGE_SIZE));
+        }
+        addr = map(0);
+        if addr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        if huge_pages && unsafe { libc::madvise(addr, len, libc::MADV_HUGEPAGE) } == 0 {
+            backing = Backing::TransparentHuge;
+        }
+    }
+
+    let ptr = NonNull::new(addr.cast()).expect("mmap returned null");
+
+    if let Some(node) = node
+        && let Err(e) = bind_to_node(ptr, len, node)
+    {
+        unsafe { libc::munmap(addr, len) };
+        return Err(e);
+    }
+
+    Ok((ptr, backing, len))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn map_anonymous(
+    _layout: Layout,
+    _huge_pages: bool,
+    _node: Option<usize>,
+) -> io::Result<(NonNull<u8>, Backing, usize)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "huge pages and NUMA binding are only available on linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_node(ptr: NonNull<u8>, len: usize, node: usize) -> io::Result<()> {
+    const BITS: usize = u64::BITS as usize;
+
+    let mut mask = vec![0u64; node / BITS + 1];
+    mask[node / BITS] |= 1 << (node % BITS);
+
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            ptr.as_ptr(),
+            len,
+            libc::MPOL_BIND,
+            mask.as_ptr(),
+            mask.len() * BITS + 1,
+            0,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+
+    // Kernels built without NUMA support have a single node to begin with.
+    let e = io::Error::last_os_error();
+    if e.raw_os_error() == Some(libc::ENOSYS) && node == 0 {
+        return Ok(());
+    }
+    Err(e)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn unmap(ptr: NonNull<u8>, len: usize) {
+    unsafe { libc::munmap(ptr.as_ptr().cast(), len) };
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn unmap(_ptr: NonNull<u8>, _len: usize) {
+    unreachable!("mapped buffers are only created on linux");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_heap_buffer() {
+        let buffer = Allocator::heap().alloc_with(16, |i| i as u64 * 3);
+
+        assert_eq!(buffer.len(), 16);
+        assert_eq!(buffer[5], 15);
+        assert_eq!(buffer.backing(), Backing::Heap);
+    }
+
+    #[test]
+    fn test_drops_elements() {
+        let marker = Rc::new(());
+        let buffer = Allocator::heap().alloc_with(4, |_| marker.clone());
+        assert_eq!(Rc::strong_count(&marker), 5);
+
+        drop(buffer);
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_huge_page_buffer_on_node() {
+        // Containers without CAP_SYS_NICE or NUMA support refuse `mbind`;
+        // keep checking the mapping itself without the node.
+        let (mut buffer, bound) = match Allocator::heap()
+            .huge_pages()
+            .on_node(0)
+            .try_alloc_with(1024, |i| i as u32)
+        {
+            Ok(buffer) => (buffer, true),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EPERM | libc::ENOSYS)) => (
+                Allocator::heap()
+                    .huge_pages()
+                    .alloc_with(1024, |i| i as u32),
+                false,
+            ),
+            Err(e) => panic!("huge_pages().on_node(0) failed: {}", e),
+        };
+
+        assert_ne!(buffer.backing(), Backing::Heap);
+        if bound {
+            assert_eq!(buffer.node(), Some(0));
+        }
+        assert_eq!(buffer.as_ptr() as usize % PAGE_SIZE, 0);
+
+        buffer[1023] = 7;
+        assert_eq!(
+            buffer.iter().map(|&v| v as u64).sum::<u64>(),
+            1022 * 1023 / 2 + 7
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_on_node_without_huge_pages_is_mapped() {
+        let buffer = match Allocator::heap()
+            .on_node(0)
+            .try_alloc_with(64, |i| i as u64)
+        {
+            Ok(buffer) => buffer,
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EPERM | libc::ENOSYS)) => return,
+            Err(e) => panic!("on_node(0) failed: {}", e),
+        };
+
+        assert_eq!(buffer.backing(), Backing::Mapped);
+        assert_eq!(buffer[63], 63);
+    }
+
+    #[test]
+    fn test_fallback_order() {
+        let bound = Allocator::heap().huge_pages().on_node(1);
+        assert_eq!(bound.fallback(), Some(Allocator::heap().huge_pages()));
+        assert_eq!(
+            Allocator::heap().huge_pages().fallback(),
+            Some(Allocator::heap())
+        );
+        assert_eq!(
+            Allocator::heap().on_node(1).fallback(),
+            Some(Allocator::heap())
+        );
+        assert_eq!(Allocator::heap().fallback(), None);
+    }
+
+    #[test]
+    fn test_alloc_with_falls_back_to_heap() {
+        #[repr(align(8192))]
+        struct OverAligned(u8);
+
+        let buffer = Allocator::heap()
+            .on_node(0)
+            .alloc_with(2, |i| OverAligned(i as u8));
+        assert_eq!(buffer.backing(), Backing::Heap);
+        assert_eq!(buffer.node(), None);
+        assert_eq!(buffer[1].0, 1);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_rejects_alignment_beyond_page_size() {
+        #[repr(align(8192))]
+        struct OverAligned(#[allow(dead_code)] u8);
+
+        let err = Allocator::heap()
+            .on_node(0)
+            .try_alloc_with(1, |_| OverAligned(0))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
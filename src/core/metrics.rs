@@ -1,10 +1,19 @@
 use crate::core::types::Timestamp;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+// Reads the TSC when it is invariant and the monotonic clock otherwise, so a
+// TSC that stops or changes rate in power states never leaks into
+// timestamps. `TscClock::calibrate` follows the same choice.
 #[inline(always)]
 pub fn rdtsc() -> Timestamp {
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[cfg(target_arch = "x86_64")]
     {
+        if !uses_tsc() {
+            return Timestamp::from_cycles(monotonic_nanos());
+        }
+
         unsafe {
             let mut aux: u32 = 0;
             let cycles = core::arch::x86_64::__rdtscp(&mut aux as *mut u32);
@@ -12,82 +21,670 @@ pub fn rdtsc() -> Timestamp {
         }
     }
 
-    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    #[cfg(not(target_arch = "x86_64"))]
     {
-        Timestamp::from_cycles(0)
+        Timestamp::from_cycles(monotonic_nanos())
+    }
+}
+
+#[cfg(target_os = "linux")]
+const MONOTONIC_CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC_RAW;
+
+#[cfg(all(unix, not(target_os = "linux")))]
+const MONOTONIC_CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+#[cfg(unix)]
+#[inline(always)]
+fn monotonic_nanos() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(MONOTONIC_CLOCK, &mut ts);
+    }
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(not(unix))]
+#[inline(always)]
+fn monotonic_nanos() -> u64 {
+    static ORIGIN: OnceLock<std::time::Instant> = OnceLock::new();
+    ORIGIN
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_nanos() as u64
+}
+
+fn epoch_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+pub fn has_invariant_tsc() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use core::arch::x86_64::__cpuid;
+
+        let max_extended = __cpuid(0x8000_0000).eax;
+        if max_extended < 0x8000_0007 {
+            return false;
+        }
+
+        __cpuid(0x8000_0007).edx & (1 << 8) != 0
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[inline(always)]
+fn uses_tsc() -> bool {
+    static INVARIANT: OnceLock<bool> = OnceLock::new();
+    *INVARIANT.get_or_init(has_invariant_tsc)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    Tsc { invariant: bool },
+    Monotonic,
+}
+
+// Maps raw `rdtsc()` readings onto nanoseconds. Calibration pins one TSC
+// reading to both the monotonic clock and the wall clock, so conversions are
+// a multiply and an offset with no syscalls on the hot path.
+#[derive(Debug, Clone, Copy)]
+pub struct TscClock {
+    source: ClockSource,
+    cycles_per_ns: f64,
+    base_cycles: u64,
+    base_nanos: u64,
+    base_epoch_nanos: u64,
+}
+
+impl TscClock {
+    pub const DEFAULT_CALIBRATION: Duration = Duration::from_millis(10);
+
+    pub fn calibrate() -> Self {
+        Self::calibrate_for(Self::DEFAULT_CALIBRATION)
+    }
+
+    pub fn calibrate_for(window: Duration) -> Self {
+        if !uses_tsc() {
+            return Self::monotonic();
+        }
+
+        let (start_cycles, start_nanos) = Self::sample();
+        let deadline = start_nanos + window.as_nanos() as u64;
+
+        let mut end = Self::sample();
+        while end.1 < deadline {
+            std::hint::spin_loop();
+            end = Self::sample();
+        }
+        let (end_cycles, end_nanos) = end;
+
+        let cycles_per_ns = (end_cycles - start_cycles) as f64 / (end_nanos - start_nanos) as f64;
+
+        TscClock {
+            source: ClockSource::Tsc { invariant: true },
+            cycles_per_ns,
+            base_cycles: end_cycles,
+            base_nanos: end_nanos,
+            base_epoch_nanos: epoch_nanos(),
+        }
+    }
+
+    pub fn monotonic() -> Self {
+        let now = monotonic_nanos();
+
+        TscClock {
+            source: ClockSource::Monotonic,
+            cycles_per_ns: 1.0,
+            base_cycles: now,
+            base_nanos: now,
+            base_epoch_nanos: epoch_nanos(),
+        }
+    }
+
+    pub fn from_rate(source: ClockSource, cycles_per_ns: f64) -> Self {
+        TscClock {
+            source,
+            cycles_per_ns,
+            base_cycles: rdtsc().cycles(),
+            base_nanos: monotonic_nanos(),
+            base_epoch_nanos: epoch_nanos(),
+        }
+    }
+
+    pub fn global() -> &'static TscClock {
+        static CLOCK: OnceLock<TscClock> = OnceLock::new();
+        CLOCK.get_or_init(TscClock::calibrate)
+    }
+
+    // Brackets the TSC read between two clock reads and keeps the tightest
+    // pair so a preemption mid-sample does not skew the ratio.
+    fn sample() -> (u64, u64) {
+        let mut best = (0, 0);
+        let mut best_gap = u64::MAX;
+
+        for _ in 0..16 {
+            let before = monotonic_nanos();
+            let cycles = rdtsc().cycles();
+            let after = monotonic_nanos();
+
+            if after - before < best_gap {
+                best_gap = after - before;
+                best = (cycles, before + (after - before) / 2);
+            }
+        }
+
+        best
+    }
+
+    #[inline]
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    #[inline]
+    pub fn is_invariant(&self) -> bool {
+        match self.source {
+            ClockSource::Tsc { invariant } => invariant,
+            ClockSource::Monotonic => true,
+        }
+    }
+
+    #[inline]
+    pub fn cycles_per_ns(&self) -> f64 {
+        self.cycles_per_ns
+    }
+
+    #[inline(always)]
+    pub fn now(&self) -> Timestamp {
+        rdtsc()
+    }
+
+    #[inline(always)]
+    pub fn cycles_to_nanos(&self, cycles: u64) -> u64 {
+        (cycles as f64 / self.cycles_per_ns) as u64
+    }
+
+    #[inline(always)]
+    pub fn nanos_to_cycles(&self, nanos: u64) -> u64 {
+        (nanos as f64 * self.cycles_per_ns) as u64
+    }
+
+    #[inline]
+    pub fn to_nanos(&self, timestamp: Timestamp) -> u64 {
+        Self::offset(self.base_nanos, timestamp.cycles(), self.base_cycles, |d| {
+            self.cycles_to_nanos(d)
+        })
+    }
+
+    #[inline]
+    pub fn from_nanos(&self, nanos: u64) -> Timestamp {
+        Timestamp::from_cycles(Self::offset(
+            self.base_cycles,
+            nanos,
+            self.base_nanos,
+            |d| self.nanos_to_cycles(d),
+        ))
+    }
+
+    #[inline]
+    pub fn to_epoch_nanos(&self, timestamp: Timestamp) -> u64 {
+        Self::offset(
+            self.base_epoch_nanos,
+            timestamp.cycles(),
+            self.base_cycles,
+            |d| self.cycles_to_nanos(d),
+        )
+    }
+
+    #[inline]
+    pub fn from_epoch_nanos(&self, epoch_nanos: u64) -> Timestamp {
+        Timestamp::from_cycles(Self::offset(
+            self.base_cycles,
+            epoch_nanos,
+            self.base_epoch_nanos,
+            |d| self.nanos_to_cycles(d),
+        ))
+    }
+
+    #[inline(always)]
+    fn offset(base: u64, value: u64, origin: u64, scale: impl Fn(u64) -> u64) -> u64 {
+        // Saturates for readings taken before calibration, including 0.
+        if value >= origin {
+            base.saturating_add(scale(value - origin))
+        } else {
+            base.saturating_sub(scale(origin - value))
+        }
+    }
+}
+
+pub const HISTOGRAM_SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << HISTOGRAM_SUB_BUCKET_BITS;
+pub const HISTOGRAM_BUCKETS: usize = (64 - HISTOGRAM_SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+// Log-linear bucketing: values below 2 * SUB_BUCKETS get their own bucket,
+// every power of two above that is split into SUB_BUCKETS linear buckets,
+// which bounds the relative error to 1 / SUB_BUCKETS (~3%).
+#[inline(always)]
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+
+    let exp = 63 - value.leading_zeros();
+    let group = (exp - HISTOGRAM_SUB_BUCKET_BITS + 1) as usize;
+    let sub = (value >> (exp - HISTOGRAM_SUB_BUCKET_BITS)) as usize - SUB_BUCKETS;
+
+    group * SUB_BUCKETS + sub
+}
+
+#[inline]
+fn bucket_low(index: usize) -> u64 {
+    if index < 2 * SUB_BUCKETS {
+        return index as u64;
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift
+}
+
+#[inline]
+fn bucket_high(index: usize) -> u64 {
+    if index < 2 * SUB_BUCKETS {
+        return index as u64;
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    bucket_low(index) + ((1u64 << shift) - 1)
+}
+
+#[derive(Clone)]
+pub struct Histogram {
+    counts: [u64; HISTOGRAM_BUCKETS],
+    total: u64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Histogram {
+            counts: [0; HISTOGRAM_BUCKETS],
+            total: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket_index(value)] += 1;
+        self.total += 1;
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn min(&self) -> u64 {
+        self.counts
+            .iter()
+            .position(|&c| c > 0)
+            .map_or(0, bucket_low)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.counts
+            .iter()
+            .rposition(|&c| c > 0)
+            .map_or(0, bucket_high)
+    }
+
+    pub fn percentile(&self, q: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let rank = ((q / 100.0) * self.total as f64).ceil() as u64;
+        let rank = rank.clamp(1, self.total);
+
+        let mut seen = 0u64;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_high(index);
+            }
+        }
+
+        self.max()
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (dst, src) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *dst += src;
+        }
+        self.total += other.total;
+    }
+
+    pub fn since(&self, earlier: &Histogram) -> Histogram {
+        let mut delta = Histogram::new();
+
+        for ((dst, now), then) in delta
+            .counts
+            .iter_mut()
+            .zip(self.counts.iter())
+            .zip(earlier.counts.iter())
+        {
+            *dst = now.saturating_sub(*then);
+            delta.total += *dst;
+        }
+
+        delta
+    }
+
+    pub fn reset(&mut self) {
+        self.counts = [0; HISTOGRAM_BUCKETS];
+        self.total = 0;
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.total)
+            .field("min", &self.min())
+            .field("p50", &self.percentile(50.0))
+            .field("p99", &self.percentile(99.0))
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+#[repr(transparent)]
+pub struct AtomicHistogram {
+    counts: [AtomicU64; HISTOGRAM_BUCKETS],
+}
+
+impl AtomicHistogram {
+    pub const fn new() -> Self {
+        AtomicHistogram {
+            counts: [const { AtomicU64::new(0) }; HISTOGRAM_BUCKETS],
+        }
+    }
+
+    #[inline(always)]
+    pub fn record(&self, value: u64) {
+        self.counts[bucket_index(value)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> Histogram {
+        self.collect(|count| count.load(Ordering::Relaxed))
+    }
+
+    pub fn interval_snapshot(&self) -> Histogram {
+        self.collect(|count| count.swap(0, Ordering::Relaxed))
+    }
+
+    pub fn add(&self, histogram: &Histogram) {
+        for (dst, &src) in self.counts.iter().zip(histogram.counts.iter()) {
+            if src != 0 {
+                dst.fetch_add(src, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn store(&self, histogram: &Histogram) {
+        for (dst, &src) in self.counts.iter().zip(histogram.counts.iter()) {
+            dst.store(src, Ordering::Relaxed);
+        }
+    }
+
+    pub fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn collect(&self, mut read: impl FnMut(&AtomicU64) -> u64) -> Histogram {
+        let mut histogram = Histogram::new();
+
+        for (dst, src) in histogram.counts.iter_mut().zip(self.counts.iter()) {
+            *dst = read(src);
+            histogram.total += *dst;
+        }
+
+        histogram
+    }
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Single-owner recorder for one stage thread: plain integers and a local
+// histogram, so timing a message costs no shared-cache-line traffic. Only one
+// in `sample_every` messages is timed; `publish` merges the batch into a
+// shared `LatencyTracker`.
+pub struct LocalRecorder {
+    sample_every: u32,
+    countdown: u32,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+    histogram: Histogram,
+}
+
+impl LocalRecorder {
+    pub fn new(sample_every: u32) -> Self {
+        assert!(sample_every > 0, "sample_every must be at least 1");
+
+        LocalRecorder {
+            sample_every,
+            countdown: 1,
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+            histogram: Histogram::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn should_sample(&mut self) -> bool {
+        self.countdown -= 1;
+        if self.countdown == 0 {
+            self.countdown = self.sample_every;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, cycles: u64) {
+        self.histogram.record(cycles);
+        self.count += 1;
+        self.sum += cycles;
+        self.min = self.min.min(cycles);
+        self.max = self.max.max(cycles);
+    }
+
+    #[inline]
+    pub fn pending(&self) -> u64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn sample_every(&self) -> u32 {
+        self.sample_every
+    }
+
+    pub fn publish(&mut self, tracker: &LatencyTracker) {
+        if self.count == 0 {
+            return;
+        }
+
+        tracker.merge(self);
+
+        self.count = 0;
+        self.sum = 0;
+        self.min = u64::MAX;
+        self.max = 0;
+        self.histogram.reset();
     }
 }
 
 pub struct LatencyTracker {
+    seq: AtomicU64,
     count: AtomicU64,
     sum: AtomicU64,
     min: AtomicU64,
     max: AtomicU64,
+    histogram: AtomicHistogram,
 }
 
 impl LatencyTracker {
     pub const fn new() -> Self {
         LatencyTracker {
+            seq: AtomicU64::new(0),
             count: AtomicU64::new(0),
             sum: AtomicU64::new(0),
             min: AtomicU64::new(u64::MAX),
             max: AtomicU64::new(0),
+            histogram: AtomicHistogram::new(),
         }
     }
 
     #[inline(always)]
     pub fn record(&self, cycles: u64) {
+        let seq = self.begin_write();
+
+        self.histogram.record(cycles);
         self.count.fetch_add(1, Ordering::Relaxed);
         self.sum.fetch_add(cycles, Ordering::Relaxed);
+        self.min.fetch_min(cycles, Ordering::Relaxed);
+        self.max.fetch_max(cycles, Ordering::Relaxed);
+
+        self.end_write(seq);
+    }
 
-        let mut current_min = self.min.load(Ordering::Relaxed);
-        while cycles < current_min {
-            match self.min.compare_exchange_weak(
-                current_min,
-                cycles,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
+    fn merge(&self, local: &LocalRecorder) {
+        let seq = self.begin_write();
+
+        self.count.fetch_add(local.count, Ordering::Relaxed);
+        self.sum.fetch_add(local.sum, Ordering::Relaxed);
+        self.min.fetch_min(local.min, Ordering::Relaxed);
+        self.max.fetch_max(local.max, Ordering::Relaxed);
+        self.histogram.add(&local.histogram);
+
+        self.end_write(seq);
+    }
+
+    // Every write (`record`, `merge`, `reset`) is bracketed by a seqlock so
+    // readers never see a count without its histogram. Several threads may
+    // write the same tracker, so writers take the odd sequence with a CAS.
+    #[inline(always)]
+    fn begin_write(&self) -> u64 {
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 1 {
+                std::hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self
+                .seq
+                .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
                 Ok(_) => break,
-                Err(x) => current_min = x,
+                Err(x) => seq = x,
             }
         }
+        fence(Ordering::Release);
+        seq
+    }
 
-        let mut current_max = self.max.load(Ordering::Relaxed);
-        while cycles > current_max {
-            match self.max.compare_exchange_weak(
-                current_max,
-                cycles,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(x) => current_max = x,
+    #[inline(always)]
+    fn end_write(&self, seq: u64) {
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    fn read(&self) -> (u64, u64, u64, u64, Histogram) {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let count = self.count.load(Ordering::Relaxed);
+            let sum = self.sum.load(Ordering::Relaxed);
+            let min = self.min.load(Ordering::Relaxed);
+            let max = self.max.load(Ordering::Relaxed);
+            let histogram = self.histogram.snapshot();
+
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return (count, sum, min, max, histogram);
             }
         }
     }
 
     pub fn stats(&self) -> LatencyStats {
-        let count = self.count.load(Ordering::Relaxed);
-        let sum = self.sum.load(Ordering::Relaxed);
-        let min = self.min.load(Ordering::Relaxed);
-        let max = self.max.load(Ordering::Relaxed);
+        self.snapshot().0
+    }
+
+    pub fn snapshot(&self) -> (LatencyStats, Histogram) {
+        let (count, sum, min, max, histogram) = self.read();
 
-        let avg = if count > 0 { sum / count } else { 0 };
+        let avg = sum.checked_div(count).unwrap_or(0);
 
-        LatencyStats {
+        let percentile = |q: f64| histogram.percentile(q).min(max);
+
+        let stats = LatencyStats {
             count,
+            sum,
             min: if min == u64::MAX { 0 } else { min },
             max,
             avg,
-        }
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            p999: percentile(99.9),
+            p9999: percentile(99.99),
+        };
+
+        (stats, histogram)
+    }
+
+    pub fn histogram(&self) -> Histogram {
+        self.read().4
     }
 
     pub fn reset(&self) {
+        let seq = self.begin_write();
+
         self.count.store(0, Ordering::Relaxed);
         self.sum.store(0, Ordering::Relaxed);
         self.min.store(u64::MAX, Ordering::Relaxed);
         self.max.store(0, Ordering::Relaxed);
+        self.histogram.reset();
+
+        self.end_write(seq);
     }
 }
 
@@ -97,22 +694,163 @@ impl Default for LatencyTracker {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Stage {
+    MarketData = 0,
+    Strategy = 1,
+    Risk = 2,
+    Gateway = 3,
+}
+
+impl Stage {
+    pub const COUNT: usize = 4;
+
+    pub const ALL: [Stage; Self::COUNT] = [
+        Stage::MarketData,
+        Stage::Strategy,
+        Stage::Risk,
+        Stage::Gateway,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Stage::MarketData => "market_data",
+            Stage::Strategy => "strategy",
+            Stage::Risk => "risk",
+            Stage::Gateway => "gateway",
+        }
+    }
+}
+
+// Running totals from a stage's counters, as opposed to the latency
+// tracker's count, which only covers sampled messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageCounts {
+    pub processed: u64,
+    pub sent: u64,
+    pub rejected: u64,
+}
+
+static LATENCY_TRACKERS: [LatencyTracker; Stage::COUNT] =
+    [const { LatencyTracker::new() }; Stage::COUNT];
+
+#[inline(always)]
+pub fn tracker(stage: Stage) -> &'static LatencyTracker {
+    &LATENCY_TRACKERS[stage as usize]
+}
+
+// With the `metrics` feature off this compiles to a constant, so stages can
+// stamp traces unconditionally without paying for `rdtsc`.
+#[inline(always)]
+pub fn latency_start() -> Timestamp {
+    #[cfg(feature = "metrics")]
+    {
+        rdtsc()
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    {
+        Timestamp::from_cycles(0)
+    }
+}
+
+// Per-thread front end to a stage's shared tracker. Sampled messages are
+// recorded into a `LocalRecorder` and merged into `tracker(stage)` at most
+// once per flush interval, or when the stage goes idle.
+pub struct StageRecorder {
+    stage: Stage,
+    local: LocalRecorder,
+    flush_cycles: u64,
+    last_flush: u64,
+}
+
+impl StageRecorder {
+    pub const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn new(stage: Stage, sample_every: u32) -> Self {
+        // Nothing is sampled without `metrics`, so don't pay for calibrating
+        // the clock just to never flush.
+        #[cfg(feature = "metrics")]
+        let (flush_cycles, last_flush) = (
+            TscClock::global().nanos_to_cycles(Self::FLUSH_INTERVAL.as_nanos() as u64),
+            rdtsc().cycles(),
+        );
+        #[cfg(not(feature = "metrics"))]
+        let (flush_cycles, last_flush) = (u64::MAX, 0);
+
+        StageRecorder {
+            stage,
+            local: LocalRecorder::new(sample_every),
+            flush_cycles,
+            last_flush,
+        }
+    }
+
+    #[inline]
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    // Always false with the `metrics` feature off.
+    #[inline(always)]
+    pub fn sample(&mut self) -> bool {
+        cfg!(feature = "metrics") && self.local.should_sample()
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, start: Timestamp) {
+        let now = rdtsc().cycles();
+        self.local.record(now.saturating_sub(start.cycles()));
+
+        if now.wrapping_sub(self.last_flush) >= self.flush_cycles {
+            self.local.publish(tracker(self.stage));
+            self.last_flush = now;
+        }
+    }
+
+    #[inline]
+    pub fn flush(&mut self) {
+        if self.local.pending() != 0 {
+            self.local.publish(tracker(self.stage));
+            self.last_flush = rdtsc().cycles();
+        }
+    }
+}
+
+impl Drop for StageRecorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct LatencyStats {
     pub count: u64,
+    pub sum: u64,
     pub min: u64,
     pub max: u64,
     pub avg: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub p9999: u64,
 }
 
 impl LatencyStats {
-    pub fn to_nanos(&self, cpu_ghz: f64) -> LatencyStatsNanos {
-        let cycles_per_ns = cpu_ghz;
+    pub fn to_nanos(&self, clock: &TscClock) -> LatencyStatsNanos {
+        let ns = |cycles: u64| clock.cycles_to_nanos(cycles);
         LatencyStatsNanos {
             count: self.count,
-            min_ns: (self.min as f64 / cycles_per_ns) as u64,
-            max_ns: (self.max as f64 / cycles_per_ns) as u64,
-            avg_ns: (self.avg as f64 / cycles_per_ns) as u64,
+            min_ns: ns(self.min),
+            max_ns: ns(self.max),
+            avg_ns: ns(self.avg),
+            p50_ns: ns(self.p50),
+            p90_ns: ns(self.p90),
+            p99_ns: ns(self.p99),
+            p999_ns: ns(self.p999),
+            p9999_ns: ns(self.p9999),
         }
     }
 }
@@ -123,14 +861,27 @@ pub struct LatencyStatsNanos {
     pub min_ns: u64,
     pub max_ns: u64,
     pub avg_ns: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub p9999_ns: u64,
 }
 
 impl std::fmt::Display for LatencyStatsNanos {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "count={} min={}ns avg={}ns max={}ns",
-            self.count, self.min_ns, self.avg_ns, self.max_ns
+            "count={} min={}ns avg={}ns p50={}ns p90={}ns p99={}ns p99.9={}ns p99.99={}ns max={}ns",
+            self.count,
+            self.min_ns,
+            self.avg_ns,
+            self.p50_ns,
+            self.p90_ns,
+            self.p99_ns,
+            self.p999_ns,
+            self.p9999_ns,
+            self.max_ns
         )
     }
 }
@@ -138,6 +889,8 @@ impl std::fmt::Display for LatencyStatsNanos {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
 
     #[test]
     fn test_rdtsc_monotonic() {
@@ -174,4 +927,233 @@ mod tests {
         assert_eq!(stats.min, 0);
         assert_eq!(stats.max, 0);
     }
+
+    #[test]
+    fn test_snapshot_consistent_with_record_and_reset() {
+        let tracker = Arc::new(LatencyTracker::new());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let (tracker, done) = (tracker.clone(), done.clone());
+            std::thread::spawn(move || {
+                for i in 0..20_000u64 {
+                    tracker.record(i % 1000 + 1);
+                    if i % 1000 == 999 {
+                        tracker.reset();
+                    }
+                }
+                done.store(true, Ordering::Release);
+            })
+        };
+
+        while !done.load(Ordering::Acquire) {
+            let (stats, histogram) = tracker.snapshot();
+            assert_eq!(stats.count, histogram.count());
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_latency_tracker_percentiles() {
+        let tracker = LatencyTracker::new();
+
+        for cycles in 1..=1000 {
+            tracker.record(cycles);
+        }
+
+        let stats = tracker.stats();
+        assert!(stats.p50 >= 500 && stats.p50 <= 516);
+        assert!(stats.p99 >= 990 && stats.p99 <= 1000);
+        assert_eq!(stats.p9999, 1000);
+    }
+
+    #[test]
+    fn test_bucket_index_roundtrip() {
+        for value in [
+            0u64,
+            1,
+            31,
+            32,
+            63,
+            64,
+            65,
+            1000,
+            123_456,
+            u64::MAX / 3,
+            u64::MAX,
+        ] {
+            let index = bucket_index(value);
+            assert!(index < HISTOGRAM_BUCKETS);
+            assert!(bucket_low(index) <= value && value <= bucket_high(index));
+
+            let error = (bucket_high(index) - bucket_low(index)) as f64 / value.max(1) as f64;
+            assert!(error <= 1.0 / SUB_BUCKETS as f64);
+        }
+    }
+
+    #[test]
+    fn test_histogram_percentile() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.percentile(99.0), 0);
+
+        for value in 0..100 {
+            histogram.record(value);
+        }
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.min(), 0);
+        assert_eq!(histogram.percentile(50.0), 49);
+        assert_eq!(histogram.percentile(100.0), 99);
+    }
+
+    #[test]
+    fn test_histogram_merge_and_reset() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+
+        a.record(10);
+        b.record(10_000);
+        a.merge(&b);
+
+        assert_eq!(a.count(), 2);
+        assert_eq!(a.percentile(50.0), 10);
+        assert!(a.max() >= 10_000);
+
+        a.reset();
+        assert_eq!(a.count(), 0);
+        assert_eq!(a.max(), 0);
+    }
+
+    #[test]
+    fn test_atomic_histogram_interval_snapshot() {
+        let histogram = AtomicHistogram::new();
+
+        histogram.record(5);
+        histogram.record(7);
+
+        let interval = histogram.interval_snapshot();
+        assert_eq!(interval.count(), 2);
+        assert_eq!(histogram.snapshot().count(), 0);
+
+        histogram.record(9);
+        assert_eq!(histogram.snapshot().percentile(100.0), 9);
+    }
+
+    #[test]
+    fn test_local_recorder_samples_and_publishes() {
+        let tracker = LatencyTracker::new();
+        let mut local = LocalRecorder::new(4);
+
+        let sampled = (0..12).filter(|_| local.should_sample()).count();
+        assert_eq!(sampled, 3);
+
+        for cycles in [100, 200, 300] {
+            local.record(cycles);
+        }
+        assert_eq!(local.pending(), 3);
+
+        local.publish(&tracker);
+        assert_eq!(local.pending(), 0);
+
+        local.record(50);
+        local.publish(&tracker);
+
+        let (stats, histogram) = tracker.snapshot();
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.sum, 650);
+        assert_eq!(stats.min, 50);
+        assert_eq!(stats.max, 300);
+        assert_eq!(histogram.count(), 4);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_stage_recorder_flushes_on_drop() {
+        let mut recorder = StageRecorder::new(Stage::Gateway, 1);
+        let start = latency_start();
+
+        assert!(recorder.sample());
+        recorder.record(start);
+        drop(recorder);
+
+        assert!(tracker(Stage::Gateway).stats().count >= 1);
+        assert_eq!(Stage::ALL[Stage::Gateway as usize], Stage::Gateway);
+    }
+
+    #[test]
+    fn test_histogram_since() {
+        let mut histogram = Histogram::new();
+        histogram.record(10);
+        let earlier = histogram.clone();
+
+        histogram.record(20);
+        histogram.record(30);
+
+        let delta = histogram.since(&earlier);
+        assert_eq!(delta.count(), 2);
+        assert_eq!(delta.min(), 20);
+        assert_eq!(delta.percentile(100.0), 30);
+    }
+
+    #[test]
+    fn test_tsc_clock_measures_elapsed_time() {
+        let clock = TscClock::calibrate();
+        assert!(clock.cycles_per_ns() > 0.0);
+
+        let start = clock.now();
+        std::thread::sleep(Duration::from_millis(20));
+        let elapsed_ns = clock.cycles_to_nanos(clock.now() - start);
+
+        assert!(elapsed_ns >= 15_000_000, "elapsed {}ns", elapsed_ns);
+        assert!(elapsed_ns < 1_000_000_000, "elapsed {}ns", elapsed_ns);
+    }
+
+    #[test]
+    fn test_tsc_clock_conversions_roundtrip() {
+        let clock = TscClock::global();
+        let now = clock.now();
+
+        let nanos = clock.to_nanos(now);
+        let back = clock.from_nanos(nanos).cycles();
+        assert!(back.abs_diff(now.cycles()) <= clock.nanos_to_cycles(1) + 1);
+
+        let epoch = clock.to_epoch_nanos(now);
+        let wall = epoch_nanos();
+        assert!(wall.abs_diff(epoch) < 1_000_000_000);
+        let earlier = clock.to_epoch_nanos(clock.from_nanos(nanos - 1000));
+        assert!((epoch - earlier).abs_diff(1000) <= 2);
+    }
+
+    #[test]
+    fn test_tsc_clock_saturates_before_calibration() {
+        // One cycle per second, so any reading before the base is further
+        // back than the epoch.
+        let clock = TscClock::from_rate(ClockSource::Tsc { invariant: true }, 1e-9);
+
+        assert_eq!(clock.to_epoch_nanos(Timestamp::from_cycles(0)), 0);
+        assert_eq!(clock.to_nanos(Timestamp::from_cycles(0)), 0);
+    }
+
+    #[test]
+    fn test_rdtsc_follows_clock_source() {
+        let clock = TscClock::global();
+        if uses_tsc() {
+            assert!(matches!(
+                clock.source(),
+                ClockSource::Tsc { invariant: true }
+            ));
+        } else {
+            assert_eq!(clock.source(), ClockSource::Monotonic);
+        }
+    }
+
+    #[test]
+    fn test_monotonic_clock_is_identity() {
+        let clock = TscClock::monotonic();
+
+        assert_eq!(clock.source(), ClockSource::Monotonic);
+        assert!(clock.is_invariant());
+        assert_eq!(clock.cycles_to_nanos(12_345), 12_345);
+        assert_eq!(clock.nanos_to_cycles(12_345), 12_345);
+    }
 }
@@ -1,9 +1,31 @@
+pub mod broadcast;
+pub mod fan_in;
+pub mod memory;
 pub mod metrics;
+#[cfg(unix)]
+pub mod shm;
+#[cfg(unix)]
+pub mod shm_metrics;
+pub mod signal;
 pub mod spsc;
 pub mod thread;
+pub mod topology;
 pub mod types;
+pub mod wait;
 
-pub use metrics::{LatencyTracker, rdtsc};
-pub use spsc::SpscQueue;
-pub use thread::pin_to_cpu;
+pub use broadcast::{Publisher, Subscriber};
+pub use fan_in::FanIn;
+pub use memory::{Allocator, Backing, Buffer};
+pub use metrics::{
+    AtomicHistogram, ClockSource, Histogram, LatencyTracker, LocalRecorder, Stage, StageCounts,
+    StageRecorder, TscClock, latency_start, rdtsc,
+};
+#[cfg(unix)]
+pub use shm::{ShmConsumer, ShmProducer, ShmSpscQueue};
+#[cfg(unix)]
+pub use shm_metrics::{MetricsSegment, StageSnapshot};
+pub use spsc::{Consumer, Producer, QueueDepth, SpscQueue};
+pub use thread::{ThreadProfile, ThreadReport, pin_to_cpu};
+pub use topology::{CpuTopology, Placement};
 pub use types::{Price, Quantity};
+pub use wait::{Parker, WaitStrategy, Waiter};
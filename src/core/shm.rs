@@ -0,0 +1,563 @@
+use crossbeam_utils::CachePadded;
+use std::cell::Cell;
+use std::ffi::CString;
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const SHM_MAGIC: u64 = u64::from_le_bytes(*b"HFTSPSC\0");
+pub const SHM_VERSION: u32 = 1;
+// Upper bound on element slots, so a corrupt or hostile header cannot make
+// `open` map an absurd length.
+pub const SHM_MAX_CAPACITY: usize = 1 << 30;
+
+#[repr(C)]
+struct ShmHeader {
+    magic: AtomicU64,
+    version: u32,
+    element_size: u32,
+    element_align: u32,
+    _reserved: u32,
+    capacity: u64,
+    layout_hash: u64,
+}
+
+#[repr(C)]
+struct ShmControl {
+    header: CachePadded<ShmHeader>,
+    head: CachePadded<AtomicU64>,
+    tail: CachePadded<AtomicU64>,
+}
+
+// The mapped segment. Its constructors hand out one `ShmProducer` and one
+// `ShmConsumer`, like `spsc::channel`; a process that only needs one end
+// drops the other.
+pub struct ShmSpscQueue<T: Copy> {
+    fd: OwnedFd,
+    control: NonNull<ShmControl>,
+    buffer: NonNull<T>,
+    map_len: usize,
+    mask: u64,
+    cached_head: Cell<u64>,
+    cached_tail: Cell<u64>,
+    unlink_on_drop: Option<CString>,
+    _marker: PhantomData<T>,
+}
+
+pub type ShmChannel<T> = (ShmProducer<T>, ShmConsumer<T>);
+
+impl<T: Copy> ShmSpscQueue<T> {
+    pub fn create(name: &str, capacity: usize) -> io::Result<ShmChannel<T>> {
+        let c_name = shm_name(name)?;
+
+        let fd = unsafe {
+            libc::shm_open(
+                c_name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o600,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        match Self::init(fd, capacity) {
+            Ok(mut queue) => {
+                queue.unlink_on_drop = Some(c_name);
+                Ok(queue.split())
+            }
+            Err(e) => {
+                unsafe { libc::shm_unlink(c_name.as_ptr()) };
+                Err(e)
+            }
+        }
+    }
+
+    pub fn open(name: &str) -> io::Result<ShmChannel<T>> {
+        let c_name = shm_name(name)?;
+
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDWR, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::attach(unsafe { OwnedFd::from_raw_fd(fd) }).map(Self::split)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn create_memfd(name: &str, capacity: usize) -> io::Result<ShmChannel<T>> {
+        let c_name = CString::new(name).map_err(|_| invalid("name contains a NUL byte"))?;
+
+        let fd = unsafe { libc::memfd_create(c_name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::init(unsafe { OwnedFd::from_raw_fd(fd) }, capacity).map(Self::split)
+    }
+
+    pub fn from_fd(fd: OwnedFd) -> io::Result<ShmChannel<T>> {
+        Self::attach(fd).map(Self::split)
+    }
+
+    fn split(self) -> ShmChannel<T> {
+        let queue = Arc::new(self);
+        (
+            ShmProducer {
+                queue: queue.clone(),
+            },
+            ShmConsumer { queue },
+        )
+    }
+
+    fn init(fd: OwnedFd, capacity: usize) -> io::Result<Self> {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        assert!(capacity.is_power_of_two(), "capacity must be a power of 2");
+        if capacity > SHM_MAX_CAPACITY {
+            return Err(invalid("capacity exceeds SHM_MAX_CAPACITY"));
+        }
+
+        let map_len =
+            segment_len::<T>(capacity).ok_or_else(|| invalid("segment length overflows usize"))?;
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), map_len as libc::off_t) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let queue = Self::map(fd, map_len, capacity)?;
+
+        unsafe {
+            let header = &mut *std::ptr::addr_of_mut!((*queue.control.as_ptr()).header);
+            header.version = SHM_VERSION;
+            header.element_size = size_of::<T>() as u32;
+            header.element_align = align_of::<T>() as u32;
+            header.capacity = capacity as u64;
+            header.layout_hash = layout_hash::<T>();
+        }
+
+        queue.header().magic.store(SHM_MAGIC, Ordering::Release);
+
+        Ok(queue)
+    }
+
+    fn attach(fd: OwnedFd) -> io::Result<Self> {
+        let file_len = segment_file_len(&fd)?;
+        if file_len < size_of::<ShmControl>() {
+            return Err(invalid("segment is smaller than the queue header"));
+        }
+
+        let header = Self::map(fd, size_of::<ShmControl>(), 1)?;
+        let capacity = header.validate_header()? as usize;
+        let fd = header.fd.try_clone()?;
+        drop(header);
+
+        let map_len =
+            segment_len::<T>(capacity).ok_or_else(|| invalid("segment length overflows usize"))?;
+        if file_len < map_len {
+            return Err(invalid("segment is smaller than its declared capacity"));
+        }
+
+        let queue = Self::map(fd, map_len, capacity)?;
+        queue
+            .cached_head
+            .set(queue.control().head.load(Ordering::Acquire));
+        queue
+            .cached_tail
+            .set(queue.control().tail.load(Ordering::Acquire));
+
+        Ok(queue)
+    }
+
+    fn map(fd: OwnedFd, map_len: usize, capacity: usize) -> io::Result<Self> {
+        let base = map_shared(&fd, map_len, true)?.as_ptr();
+
+        Ok(ShmSpscQueue {
+            fd,
+            control: unsafe { NonNull::new_unchecked(base as *mut ShmControl) },
+            buffer: unsafe { NonNull::new_unchecked(base.add(data_offset::<T>()) as *mut T) },
+            map_len,
+            mask: capacity as u64 - 1,
+            cached_head: Cell::new(0),
+            cached_tail: Cell::new(0),
+            unlink_on_drop: None,
+            _marker: PhantomData,
+        })
+    }
+
+    fn validate_header(&self) -> io::Result<u64> {
+        let header = self.header();
+
+        if header.magic.load(Ordering::Acquire) != SHM_MAGIC {
+            return Err(invalid(
+                "bad magic (segment not initialised or not a queue)",
+            ));
+        }
+        if header.version != SHM_VERSION {
+            return Err(invalid("unsupported segment version"));
+        }
+        if header.element_size as usize != size_of::<T>()
+            || header.element_align as usize != align_of::<T>()
+        {
+            return Err(invalid("element size or alignment mismatch"));
+        }
+        if header.layout_hash != layout_hash::<T>() {
+            return Err(invalid("element layout hash mismatch"));
+        }
+        if header.capacity == 0 || !header.capacity.is_power_of_two() {
+            return Err(invalid("capacity must be a non-zero power of 2"));
+        }
+        if header.capacity > SHM_MAX_CAPACITY as u64 {
+            return Err(invalid("capacity exceeds SHM_MAX_CAPACITY"));
+        }
+
+        Ok(header.capacity)
+    }
+
+    #[inline(always)]
+    fn control(&self) -> &ShmControl {
+        unsafe { self.control.as_ref() }
+    }
+
+    #[inline(always)]
+    fn header(&self) -> &ShmHeader {
+        &self.control().header
+    }
+
+    #[inline(always)]
+    fn push(&self, value: T) -> Result<(), T> {
+        let control = self.control();
+        let tail = control.tail.load(Ordering::Relaxed);
+
+        if tail.wrapping_sub(self.cached_head.get()) > self.mask {
+            self.cached_head.set(control.head.load(Ordering::Acquire));
+            if tail.wrapping_sub(self.cached_head.get()) > self.mask {
+                return Err(value);
+            }
+        }
+
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .add((tail & self.mask) as usize)
+                .write(value);
+        }
+
+        control.tail.store(tail.wrapping_add(1), Ordering::Release);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        let control = self.control();
+        let head = control.head.load(Ordering::Relaxed);
+
+        if head == self.cached_tail.get() {
+            self.cached_tail.set(control.tail.load(Ordering::Acquire));
+            if head == self.cached_tail.get() {
+                return None;
+            }
+        }
+
+        let value = unsafe { self.buffer.as_ptr().add((head & self.mask) as usize).read() };
+
+        control.head.store(head.wrapping_add(1), Ordering::Release);
+
+        Some(value)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        let tail = self.control().tail.load(Ordering::Acquire);
+        let head = self.control().head.load(Ordering::Acquire);
+        tail.wrapping_sub(head) as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.mask as usize + 1
+    }
+}
+
+impl<T: Copy> AsRawFd for ShmSpscQueue<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+pub struct ShmProducer<T: Copy> {
+    queue: Arc<ShmSpscQueue<T>>,
+}
+
+unsafe impl<T: Copy + Send> Send for ShmProducer<T> {}
+
+impl<T: Copy> ShmProducer<T> {
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        self.queue.push(value)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T: Copy> AsRawFd for ShmProducer<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.queue.as_raw_fd()
+    }
+}
+
+pub struct ShmConsumer<T: Copy> {
+    queue: Arc<ShmSpscQueue<T>>,
+}
+
+unsafe impl<T: Copy + Send> Send for ShmConsumer<T> {}
+
+impl<T: Copy> ShmConsumer<T> {
+    #[inline(always)]
+    pub fn pop(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T: Copy> AsRawFd for ShmConsumer<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.queue.as_raw_fd()
+    }
+}
+
+impl<T: Copy> Drop for ShmSpscQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.control.as_ptr() as *mut libc::c_void, self.map_len);
+            if let Some(ref name) = self.unlink_on_drop {
+                libc::shm_unlink(name.as_ptr());
+            }
+        }
+    }
+}
+
+pub(crate) fn map_shared(fd: &OwnedFd, len: usize, writable: bool) -> io::Result<NonNull<u8>> {
+    let prot = if writable {
+        libc::PROT_READ | libc::PROT_WRITE
+    } else {
+        libc::PROT_READ
+    };
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            prot,
+            libc::MAP_SHARED,
+            fd.as_raw_fd(),
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { NonNull::new_unchecked(addr as *mut u8) })
+}
+
+pub(crate) fn segment_file_len(fd: &OwnedFd) -> io::Result<usize> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(stat.st_size as usize)
+}
+
+pub(crate) fn shm_name(name: &str) -> io::Result<CString> {
+    let name = if name.starts_with('/') {
+        name.to_string()
+    } else {
+        format!("/{}", name)
+    };
+
+    CString::new(name).map_err(|_| invalid("name contains a NUL byte"))
+}
+
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+const fn data_offset<T>() -> usize {
+    let align = if align_of::<T>() > 64 {
+        align_of::<T>()
+    } else {
+        64
+    };
+    size_of::<ShmControl>().div_ceil(align) * align
+}
+
+const fn segment_len<T>(capacity: usize) -> Option<usize> {
+    match capacity.checked_mul(size_of::<T>()) {
+        Some(data_len) => data_offset::<T>().checked_add(data_len),
+        None => None,
+    }
+}
+
+// FNV-1a over the element's type name, size and alignment. Both processes
+// must be built from the same sources for the hash to match.
+fn layout_hash<T>() -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let name = std::any::type_name::<T>().as_bytes();
+    let size = (size_of::<T>() as u64).to_le_bytes();
+    let align = (align_of::<T>() as u64).to_le_bytes();
+
+    name.iter()
+        .chain(size.iter())
+        .chain(align.iter())
+        .fold(FNV_OFFSET, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{Price, Quantity, Timestamp};
+    use crate::messages::{SignalEvent, Trace};
+
+    fn unique_name(tag: &str) -> String {
+        format!("hft-test-{}-{}", tag, std::process::id())
+    }
+
+    #[test]
+    fn test_create_open_roundtrip() {
+        let name = unique_name("roundtrip");
+        let (mut producer, _) = ShmSpscQueue::<SignalEvent>::create(&name, 8).unwrap();
+        let (_, mut consumer) = ShmSpscQueue::<SignalEvent>::open(&name).unwrap();
+
+        assert_eq!(consumer.capacity(), 8);
+
+        let signal = SignalEvent::Buy {
+            strategy_id: 0,
+            symbol: 7,
+            price: Price::new(100, 0),
+            qty: Quantity::new(10, 0),
+            timestamp: Timestamp::from_cycles(42),
+            trace: Trace::from_origin(Timestamp::from_cycles(40)),
+        };
+        producer.push(signal).unwrap();
+        assert_eq!(consumer.len(), 1);
+
+        match consumer.pop() {
+            Some(SignalEvent::Buy { symbol, price, .. }) => {
+                assert_eq!(symbol, 7);
+                assert_eq!(price, Price::new(100, 0));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(consumer.pop().is_none());
+    }
+
+    #[test]
+    fn test_full_and_wraparound() {
+        let name = unique_name("wrap");
+        let (mut producer, _) = ShmSpscQueue::<u64>::create(&name, 4).unwrap();
+        let (_, mut consumer) = ShmSpscQueue::<u64>::open(&name).unwrap();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(producer.push(4), Err(4));
+
+        for i in 0..100 {
+            assert_eq!(consumer.pop(), Some(i));
+            producer.push(i + 4).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_element_type() {
+        let name = unique_name("mismatch");
+        let _queue = ShmSpscQueue::<u64>::create(&name, 4).unwrap();
+
+        let err = ShmSpscQueue::<u32>::open(&name).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = ShmSpscQueue::<i64>::open(&name).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_open_rejects_oversized_capacity() {
+        let name = unique_name("oversized");
+        let (producer, _) = ShmSpscQueue::<u64>::create(&name, 4).unwrap();
+
+        for capacity in [1u64 << 62, (SHM_MAX_CAPACITY as u64) << 1, 6] {
+            unsafe {
+                let header =
+                    &mut *std::ptr::addr_of_mut!((*producer.queue.control.as_ptr()).header);
+                header.capacity = capacity;
+            }
+            let err = ShmSpscQueue::<u64>::open(&name).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_create_exclusive_and_unlink() {
+        let name = unique_name("exclusive");
+        let channel = ShmSpscQueue::<u64>::create(&name, 4).unwrap();
+        assert!(ShmSpscQueue::<u64>::create(&name, 4).is_err());
+
+        drop(channel);
+        assert!(ShmSpscQueue::<u64>::open(&name).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memfd_from_fd() {
+        let (mut producer, _) = ShmSpscQueue::<u64>::create_memfd("hft-memfd", 16).unwrap();
+        let fd = producer.queue.fd.try_clone().unwrap();
+        let (_, mut consumer) = ShmSpscQueue::<u64>::from_fd(fd).unwrap();
+
+        producer.push(11).unwrap();
+        producer.push(12).unwrap();
+        assert_eq!(consumer.pop(), Some(11));
+        assert_eq!(consumer.pop(), Some(12));
+        assert_eq!(consumer.pop(), None);
+    }
+}
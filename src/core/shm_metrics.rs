@@ -0,0 +1,466 @@
+use crate::core::metrics::{
+    AtomicHistogram, ClockSource, Histogram, LatencyTracker, StageCounts, TscClock,
+};
+use crate::core::shm::{invalid, map_shared, segment_file_len, shm_name};
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const METRICS_MAGIC: u64 = u64::from_le_bytes(*b"HFTMETR\0");
+pub const METRICS_VERSION: u32 = 2;
+pub const DEFAULT_METRICS_SEGMENT: &str = "hft-engine-metrics";
+pub const MAX_STAGE_NAME: usize = 24;
+
+#[repr(C)]
+struct MetricsHeader {
+    magic: AtomicU64,
+    version: u32,
+    stage_count: u32,
+    histogram_buckets: u32,
+    clock_source: u32,
+    cycles_per_ns: u64,
+    created_epoch_nanos: u64,
+}
+
+// One slot per stage, guarded by a seqlock: `seq` is odd while the publisher
+// is copying a snapshot in, and readers retry until they see the same even
+// value on both sides of their copy.
+#[repr(C, align(64))]
+struct StageSlot {
+    seq: AtomicU64,
+    name: [u8; MAX_STAGE_NAME],
+    count: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+    avg: AtomicU64,
+    queue_depth: AtomicU64,
+    processed: AtomicU64,
+    sent: AtomicU64,
+    rejected: AtomicU64,
+    updated_epoch_nanos: AtomicU64,
+    histogram: AtomicHistogram,
+}
+
+// `count` and the latency figures cover sampled messages only; rates come
+// from `counts`.
+#[derive(Clone, Debug, Default)]
+pub struct StageSnapshot {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub avg: u64,
+    pub queue_depth: u64,
+    pub counts: StageCounts,
+    pub updated_epoch_nanos: u64,
+    pub latency: Histogram,
+}
+
+impl StageSnapshot {
+    pub fn from_tracker(tracker: &LatencyTracker, queue_depth: usize) -> Self {
+        let (stats, latency) = tracker.snapshot();
+
+        StageSnapshot {
+            count: stats.count,
+            min: stats.min,
+            max: stats.max,
+            avg: stats.avg,
+            queue_depth: queue_depth as u64,
+            counts: StageCounts::default(),
+            updated_epoch_nanos: epoch_nanos(),
+            latency,
+        }
+    }
+
+    pub fn counts(mut self, counts: StageCounts) -> Self {
+        self.counts = counts;
+        self
+    }
+}
+
+// Metrics segment in POSIX shared memory. The engine creates it and publishes
+// stage snapshots from a housekeeping thread; `hft-monitor` opens it read-only
+// in another process, so the pipeline threads never do any I/O for it.
+pub struct MetricsSegment {
+    fd: OwnedFd,
+    base: NonNull<u8>,
+    map_len: usize,
+    stage_count: usize,
+    writable: bool,
+    unlink_on_drop: Option<CString>,
+}
+
+unsafe impl Send for MetricsSegment {}
+unsafe impl Sync for MetricsSegment {}
+
+impl MetricsSegment {
+    pub fn create(name: &str, stages: &[&str], clock: &TscClock) -> io::Result<Self> {
+        assert!(
+            !stages.is_empty(),
+            "metrics segment needs at least one stage"
+        );
+
+        let c_name = shm_name(name)?;
+        let create = || unsafe {
+            libc::shm_open(
+                c_name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o600,
+            )
+        };
+
+        // A segment left behind by an engine that never reached its Drop
+        // (kill -9, abort) would otherwise block every later run. Monitors
+        // still attached to it keep their mapping but stop seeing updates.
+        let mut fd = create();
+        if fd < 0 && io::Error::last_os_error().raw_os_error() == Some(libc::EEXIST) {
+            unsafe { libc::shm_unlink(c_name.as_ptr()) };
+            fd = create();
+        }
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        match Self::init(fd, stages, clock) {
+            Ok(mut segment) => {
+                segment.unlink_on_drop = Some(c_name);
+                Ok(segment)
+            }
+            Err(e) => {
+                unsafe { libc::shm_unlink(c_name.as_ptr()) };
+                Err(e)
+            }
+        }
+    }
+
+    // Read-only: a monitor can never corrupt what the engine publishes.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let c_name = shm_name(name)?;
+
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), libc::O_RDONLY, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Self::attach(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn init(fd: OwnedFd, stages: &[&str], clock: &TscClock) -> io::Result<Self> {
+        let map_len = segment_len(stages.len());
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), map_len as libc::off_t) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let segment = Self::map(fd, map_len, stages.len(), true)?;
+
+        unsafe {
+            let header = &mut *(segment.base.as_ptr() as *mut MetricsHeader);
+            header.version = METRICS_VERSION;
+            header.stage_count = stages.len() as u32;
+            header.histogram_buckets = crate::core::metrics::HISTOGRAM_BUCKETS as u32;
+            header.clock_source = encode_source(clock.source());
+            header.cycles_per_ns = clock.cycles_per_ns().to_bits();
+            header.created_epoch_nanos = epoch_nanos();
+
+            for (index, stage) in stages.iter().enumerate() {
+                let slot = &mut *segment.slot_ptr(index);
+                let len = stage.len().min(MAX_STAGE_NAME);
+                slot.name[..len].copy_from_slice(&stage.as_bytes()[..len]);
+            }
+        }
+
+        segment
+            .header()
+            .magic
+            .store(METRICS_MAGIC, Ordering::Release);
+
+        Ok(segment)
+    }
+
+    fn attach(fd: OwnedFd) -> io::Result<Self> {
+        let file_len = segment_file_len(&fd)?;
+        if file_len < size_of::<MetricsHeader>() {
+            return Err(invalid("segment is smaller than the metrics header"));
+        }
+
+        let header = Self::map(fd, size_of::<MetricsHeader>(), 0, false)?;
+        let stage_count = header.validate_header()?;
+        let fd = header.fd.try_clone()?;
+        drop(header);
+
+        let map_len = segment_len(stage_count);
+        if file_len < map_len {
+            return Err(invalid("segment is smaller than its declared stage count"));
+        }
+
+        Self::map(fd, map_len, stage_count, false)
+    }
+
+    fn map(fd: OwnedFd, map_len: usize, stage_count: usize, writable: bool) -> io::Result<Self> {
+        let base = map_shared(&fd, map_len, writable)?;
+
+        Ok(MetricsSegment {
+            fd,
+            base,
+            map_len,
+            stage_count,
+            writable,
+            unlink_on_drop: None,
+        })
+    }
+
+    fn validate_header(&self) -> io::Result<usize> {
+        let header = self.header();
+
+        if header.magic.load(Ordering::Acquire) != METRICS_MAGIC {
+            return Err(invalid(
+                "bad magic (segment not initialised or not a metrics segment)",
+            ));
+        }
+        if header.version != METRICS_VERSION {
+            return Err(invalid("unsupported metrics segment version"));
+        }
+        if header.histogram_buckets as usize != crate::core::metrics::HISTOGRAM_BUCKETS {
+            return Err(invalid("histogram bucket layout mismatch"));
+        }
+        if header.stage_count == 0 {
+            return Err(invalid("metrics segment has no stages"));
+        }
+
+        Ok(header.stage_count as usize)
+    }
+
+    #[inline(always)]
+    fn header(&self) -> &MetricsHeader {
+        unsafe { &*(self.base.as_ptr() as *const MetricsHeader) }
+    }
+
+    #[inline(always)]
+    fn slot_ptr(&self, index: usize) -> *mut StageSlot {
+        unsafe {
+            self.base
+                .as_ptr()
+                .add(slots_offset() + index * size_of::<StageSlot>()) as *mut StageSlot
+        }
+    }
+
+    #[inline(always)]
+    fn slot(&self, index: usize) -> &StageSlot {
+        assert!(index < self.stage_count, "stage index out of range");
+        unsafe { &*self.slot_ptr(index) }
+    }
+
+    #[inline]
+    pub fn stage_count(&self) -> usize {
+        self.stage_count
+    }
+
+    pub fn stage_name(&self, index: usize) -> &str {
+        let name = &self.slot(index).name;
+        let len = name.iter().position(|&b| b == 0).unwrap_or(MAX_STAGE_NAME);
+        std::str::from_utf8(&name[..len]).unwrap_or("?")
+    }
+
+    pub fn clock(&self) -> TscClock {
+        let header = self.header();
+        TscClock::from_rate(
+            decode_source(header.clock_source),
+            f64::from_bits(header.cycles_per_ns),
+        )
+    }
+
+    #[inline]
+    pub fn created_epoch_nanos(&self) -> u64 {
+        self.header().created_epoch_nanos
+    }
+
+    // `&mut self` keeps a single writer per segment in this process; a second
+    // process publishing into the same segment would break the seqlock.
+    pub fn publish(&mut self, stage: usize, snapshot: &StageSnapshot) {
+        assert!(self.writable, "metrics segment was opened read-only");
+        let slot = self.slot(stage);
+        let seq = slot.seq.load(Ordering::Relaxed);
+
+        slot.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        slot.count.store(snapshot.count, Ordering::Relaxed);
+        slot.min.store(snapshot.min, Ordering::Relaxed);
+        slot.max.store(snapshot.max, Ordering::Relaxed);
+        slot.avg.store(snapshot.avg, Ordering::Relaxed);
+        slot.queue_depth
+            .store(snapshot.queue_depth, Ordering::Relaxed);
+        slot.processed
+            .store(snapshot.counts.processed, Ordering::Relaxed);
+        slot.sent.store(snapshot.counts.sent, Ordering::Relaxed);
+        slot.rejected
+            .store(snapshot.counts.rejected, Ordering::Relaxed);
+        slot.updated_epoch_nanos
+            .store(snapshot.updated_epoch_nanos, Ordering::Relaxed);
+        slot.histogram.store(&snapshot.latency);
+
+        slot.seq.store(seq + 2, Ordering::Release);
+    }
+
+    pub fn read(&self, stage: usize) -> StageSnapshot {
+        let slot = self.slot(stage);
+
+        loop {
+            let before = slot.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let snapshot = StageSnapshot {
+                count: slot.count.load(Ordering::Relaxed),
+                min: slot.min.load(Ordering::Relaxed),
+                max: slot.max.load(Ordering::Relaxed),
+                avg: slot.avg.load(Ordering::Relaxed),
+                queue_depth: slot.queue_depth.load(Ordering::Relaxed),
+                counts: StageCounts {
+                    processed: slot.processed.load(Ordering::Relaxed),
+                    sent: slot.sent.load(Ordering::Relaxed),
+                    rejected: slot.rejected.load(Ordering::Relaxed),
+                },
+                updated_epoch_nanos: slot.updated_epoch_nanos.load(Ordering::Relaxed),
+                latency: slot.histogram.snapshot(),
+            };
+
+            fence(Ordering::Acquire);
+            if slot.seq.load(Ordering::Relaxed) == before {
+                return snapshot;
+            }
+        }
+    }
+}
+
+impl AsRawFd for MetricsSegment {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Drop for MetricsSegment {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base.as_ptr() as *mut libc::c_void, self.map_len);
+            if let Some(ref name) = self.unlink_on_drop {
+                libc::shm_unlink(name.as_ptr());
+            }
+        }
+    }
+}
+
+const fn slots_offset() -> usize {
+    size_of::<MetricsHeader>().div_ceil(64) * 64
+}
+
+const fn segment_len(stage_count: usize) -> usize {
+    slots_offset() + stage_count * size_of::<StageSlot>()
+}
+
+fn encode_source(source: ClockSource) -> u32 {
+    match source {
+        ClockSource::Monotonic => 0,
+        ClockSource::Tsc { invariant: false } => 1,
+        ClockSource::Tsc { invariant: true } => 2,
+    }
+}
+
+fn decode_source(raw: u32) -> ClockSource {
+    match raw {
+        1 => ClockSource::Tsc { invariant: false },
+        2 => ClockSource::Tsc { invariant: true },
+        _ => ClockSource::Monotonic,
+    }
+}
+
+fn epoch_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique_name(tag: &str) -> String {
+        format!("hft-test-metrics-{}-{}", tag, std::process::id())
+    }
+
+    #[test]
+    fn test_publish_and_read_across_mappings() {
+        let name = unique_name("roundtrip");
+        let clock = TscClock::monotonic();
+        let mut writer = MetricsSegment::create(&name, &["risk", "gateway"], &clock).unwrap();
+        let reader = MetricsSegment::open(&name).unwrap();
+
+        assert_eq!(reader.stage_count(), 2);
+        assert_eq!(reader.stage_name(0), "risk");
+        assert_eq!(reader.stage_name(1), "gateway");
+        assert_eq!(reader.clock().source(), ClockSource::Monotonic);
+
+        let tracker = LatencyTracker::new();
+        for cycles in [100, 200, 300] {
+            tracker.record(cycles);
+        }
+        let counts = StageCounts {
+            processed: 10,
+            sent: 8,
+            rejected: 2,
+        };
+        writer.publish(1, &StageSnapshot::from_tracker(&tracker, 7).counts(counts));
+
+        let snapshot = reader.read(1);
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.min, 100);
+        assert_eq!(snapshot.max, 300);
+        assert_eq!(snapshot.queue_depth, 7);
+        assert_eq!(snapshot.counts, counts);
+        assert_eq!(snapshot.latency.count(), 3);
+
+        assert_eq!(reader.read(0).count, 0);
+    }
+
+    #[test]
+    fn test_create_replaces_stale_segment() {
+        let name = unique_name("stale");
+        let clock = TscClock::monotonic();
+        let stale = MetricsSegment::create(&name, &["risk"], &clock).unwrap();
+        std::mem::forget(stale);
+
+        let writer = MetricsSegment::create(&name, &["strategy", "risk"], &clock).unwrap();
+        let reader = MetricsSegment::open(&name).unwrap();
+        assert_eq!(reader.stage_count(), 2);
+        assert_eq!(reader.stage_name(0), "strategy");
+        drop(writer);
+    }
+
+    #[test]
+    #[should_panic(expected = "opened read-only")]
+    fn test_opened_segment_is_read_only() {
+        let name = unique_name("readonly");
+        let clock = TscClock::monotonic();
+        let _writer = MetricsSegment::create(&name, &["risk"], &clock).unwrap();
+        let mut reader = MetricsSegment::open(&name).unwrap();
+
+        reader.publish(0, &StageSnapshot::default());
+    }
+
+    #[test]
+    fn test_open_rejects_non_metrics_segment() {
+        let name = unique_name("foreign");
+        let _channel = crate::core::shm::ShmSpscQueue::<u64>::create(&name, 8).unwrap();
+
+        assert_eq!(
+            MetricsSegment::open(&name).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
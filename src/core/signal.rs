@@ -0,0 +1,70 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Routes SIGINT and SIGTERM into a flag the main thread polls, so the pipeline
+// can drain instead of dying mid-message. A second signal exits immediately
+// for when the drain itself hangs.
+pub fn install_shutdown_handler() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "signal handling is only available on unix",
+        ))
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    if SHUTDOWN_REQUESTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(128 + signal) };
+    }
+}
+
+#[inline]
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+// The flag is process-wide, so a test that raises a signal must clear it
+// again before other tests can observe it.
+#[cfg(all(test, unix))]
+fn reset_shutdown() {
+    SHUTDOWN_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sigterm_requests_shutdown() {
+        install_shutdown_handler().unwrap();
+        assert!(!shutdown_requested());
+
+        unsafe { libc::raise(libc::SIGTERM) };
+
+        assert!(shutdown_requested());
+        reset_shutdown();
+        assert!(!shutdown_requested());
+    }
+}
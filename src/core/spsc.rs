@@ -1,42 +1,92 @@
+use crate::core::memory::{Allocator, Buffer};
+use crate::core::wait::{Parker, WakeList};
 use crossbeam_utils::CachePadded;
-use std::cell::UnsafeCell;
+use std::cell::{Cell, UnsafeCell};
 use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+struct ProducerState {
+    tail: AtomicUsize,
+    cached_head: Cell<usize>,
+}
+
+struct ConsumerState {
+    head: AtomicUsize,
+    cached_tail: Cell<usize>,
+}
+
 pub struct SpscQueue<T> {
-    head: CachePadded<AtomicUsize>,
-    tail: CachePadded<AtomicUsize>,
-    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    consumer: CachePadded<ConsumerState>,
+    producer: CachePadded<ProducerState>,
+    buffer: Buffer<UnsafeCell<MaybeUninit<T>>>,
     mask: usize,
+    // Consumer threads parked on this queue; woken after every publish.
+    wakers: WakeList,
 }
 
-unsafe impl<T: Send> Send for SpscQueue<T> {}
-unsafe impl<T: Send> Sync for SpscQueue<T> {}
-
 impl<T> SpscQueue<T> {
     pub fn new(capacity: usize) -> Self {
+        Self::new_in(capacity, &Allocator::heap())
+    }
+
+    pub fn new_in(capacity: usize, allocator: &Allocator) -> Self {
         assert!(capacity > 0, "capacity must be greater than 0");
         assert!(capacity.is_power_of_two(), "capacity must be a power of 2");
 
-        let buffer = (0..capacity)
-            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
+        let buffer = allocator.alloc_with(capacity, |_| UnsafeCell::new(MaybeUninit::uninit()));
 
         SpscQueue {
-            head: CachePadded::new(AtomicUsize::new(0)),
-            tail: CachePadded::new(AtomicUsize::new(0)),
+            consumer: CachePadded::new(ConsumerState {
+                head: AtomicUsize::new(0),
+                cached_tail: Cell::new(0),
+            }),
+            producer: CachePadded::new(ProducerState {
+                tail: AtomicUsize::new(0),
+                cached_head: Cell::new(0),
+            }),
             buffer,
             mask: capacity - 1,
+            wakers: WakeList::new(),
+        }
+    }
+
+    // Producer side: free slots from `tail`, only re-reading the consumer's
+    // head when the cached copy cannot satisfy `wanted`.
+    #[inline(always)]
+    fn free_slots(&self, tail: usize, wanted: usize) -> usize {
+        let capacity = self.buffer.len();
+        let free = capacity - tail.wrapping_sub(self.producer.cached_head.get());
+
+        if free >= wanted {
+            return free;
+        }
+
+        let head = self.consumer.head.load(Ordering::Acquire);
+        self.producer.cached_head.set(head);
+        capacity - tail.wrapping_sub(head)
+    }
+
+    // Consumer side: mirror of `free_slots` against the producer's tail.
+    #[inline(always)]
+    fn available_slots(&self, head: usize, wanted: usize) -> usize {
+        let available = self.consumer.cached_tail.get().wrapping_sub(head);
+
+        if available >= wanted {
+            return available;
         }
+
+        let tail = self.producer.tail.load(Ordering::Acquire);
+        self.consumer.cached_tail.set(tail);
+        tail.wrapping_sub(head)
     }
 
     #[inline(always)]
     pub fn push(&self, value: T) -> Result<(), T> {
-        let tail = self.tail.load(Ordering::Relaxed);
-        let head = self.head.load(Ordering::Acquire);
+        let tail = self.producer.tail.load(Ordering::Relaxed);
 
-        if tail.wrapping_sub(head) == self.buffer.len() {
+        if self.free_slots(tail, 1) == 0 {
             return Err(value);
         }
 
@@ -45,17 +95,19 @@ impl<T> SpscQueue<T> {
             (*slot.get()).write(value);
         }
 
-        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        self.producer
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        self.wakers.wake();
 
         Ok(())
     }
 
     #[inline(always)]
     pub fn pop(&self) -> Option<T> {
-        let head = self.head.load(Ordering::Relaxed);
-        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.consumer.head.load(Ordering::Relaxed);
 
-        if head == tail {
+        if self.available_slots(head, 1) == 0 {
             return None;
         }
 
@@ -64,15 +116,92 @@ impl<T> SpscQueue<T> {
             (*slot.get()).assume_init_read()
         };
 
-        self.head.store(head.wrapping_add(1), Ordering::Release);
+        self.consumer
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
 
         Some(value)
     }
 
+    #[inline]
+    pub fn push_batch(&self, values: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        let tail = self.producer.tail.load(Ordering::Relaxed);
+        let count = self.free_slots(tail, values.len()).min(values.len());
+
+        if count == 0 {
+            return 0;
+        }
+
+        for (i, value) in values[..count].iter().enumerate() {
+            unsafe {
+                let slot = self.buffer.get_unchecked(tail.wrapping_add(i) & self.mask);
+                (*slot.get()).write(*value);
+            }
+        }
+
+        self.producer
+            .tail
+            .store(tail.wrapping_add(count), Ordering::Release);
+        self.wakers.wake();
+
+        count
+    }
+
+    #[inline]
+    pub fn pop_batch(&self, out: &mut [T]) -> usize {
+        let head = self.consumer.head.load(Ordering::Relaxed);
+        let count = self.available_slots(head, out.len()).min(out.len());
+
+        if count == 0 {
+            return 0;
+        }
+
+        for (i, dst) in out[..count].iter_mut().enumerate() {
+            unsafe {
+                let slot = self.buffer.get_unchecked(head.wrapping_add(i) & self.mask);
+                *dst = (*slot.get()).assume_init_read();
+            }
+        }
+
+        self.consumer
+            .head
+            .store(head.wrapping_add(count), Ordering::Release);
+
+        count
+    }
+
+    #[inline(always)]
+    pub fn reserve(&mut self) -> Option<SlotGuard<'_, T>> {
+        unsafe { self.reserve_slot() }
+    }
+
+    // Safety: the caller must guarantee that no other `SlotGuard` or `push`
+    // on this queue is live until the returned guard is dropped or committed.
+    #[inline(always)]
+    unsafe fn reserve_slot(&self) -> Option<SlotGuard<'_, T>> {
+        let tail = self.producer.tail.load(Ordering::Relaxed);
+
+        if self.free_slots(tail, 1) == 0 {
+            return None;
+        }
+
+        let slot = unsafe { &mut *self.buffer.get_unchecked(tail & self.mask).get() };
+
+        Some(SlotGuard {
+            slot,
+            tail: &self.producer.tail,
+            next: tail.wrapping_add(1),
+            wakers: &self.wakers,
+        })
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
-        let tail = self.tail.load(Ordering::Acquire);
-        let head = self.head.load(Ordering::Acquire);
+        let tail = self.producer.tail.load(Ordering::Acquire);
+        let head = self.consumer.head.load(Ordering::Acquire);
         tail.wrapping_sub(head)
     }
 
@@ -93,10 +222,186 @@ impl<T> Drop for SpscQueue<T> {
     }
 }
 
+// The next free slot, written in place: fill it field by field through
+// `as_mut_ptr` (`addr_of_mut!((*ptr).field)`), then `commit`. Dropping the
+// guard without committing leaves the queue unchanged.
+pub struct SlotGuard<'a, T> {
+    slot: &'a mut MaybeUninit<T>,
+    tail: &'a AtomicUsize,
+    next: usize,
+    wakers: &'a WakeList,
+}
+
+impl<T> SlotGuard<'_, T> {
+    /// # Safety
+    ///
+    /// The slot must have been fully initialised through the guard.
+    #[inline(always)]
+    pub unsafe fn commit(self) {
+        self.tail.store(self.next, Ordering::Release);
+        self.wakers.wake();
+    }
+}
+
+impl<T> Deref for SlotGuard<'_, T> {
+    type Target = MaybeUninit<T>;
+
+    #[inline(always)]
+    fn deref(&self) -> &MaybeUninit<T> {
+        self.slot
+    }
+}
+
+impl<T> DerefMut for SlotGuard<'_, T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut MaybeUninit<T> {
+        self.slot
+    }
+}
+
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    channel_in(capacity, &Allocator::heap())
+}
+
+pub fn channel_in<T>(capacity: usize, allocator: &Allocator) -> (Producer<T>, Consumer<T>) {
+    let queue = Arc::new(SpscQueue::new_in(capacity, allocator));
+
+    (
+        Producer {
+            queue: queue.clone(),
+        },
+        Consumer { queue },
+    )
+}
+
+pub struct Producer<T> {
+    queue: Arc<SpscQueue<T>>,
+}
+
+unsafe impl<T: Send> Send for Producer<T> {}
+
+impl<T> Producer<T> {
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        self.queue.push(value)
+    }
+
+    #[inline]
+    pub fn push_batch(&mut self, values: &[T]) -> usize
+    where
+        T: Copy,
+    {
+        self.queue.push_batch(values)
+    }
+
+    #[inline(always)]
+    pub fn reserve(&mut self) -> Option<SlotGuard<'_, T>> {
+        unsafe { self.queue.reserve_slot() }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    pub fn depth(&self) -> QueueDepth<T> {
+        QueueDepth {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+pub struct Consumer<T> {
+    queue: Arc<SpscQueue<T>>,
+}
+
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+impl<T> Consumer<T> {
+    #[inline(always)]
+    pub fn pop(&mut self) -> Option<T> {
+        self.queue.pop()
+    }
+
+    // Has the producer unpark `parker` after each push; see `SpinThenPark`.
+    pub fn register_parker(&self, parker: Arc<Parker>) {
+        self.queue.wakers.register(parker);
+    }
+
+    #[inline]
+    pub fn pop_batch(&mut self, out: &mut [T]) -> usize {
+        self.queue.pop_batch(out)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    pub fn depth(&self) -> QueueDepth<T> {
+        QueueDepth {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+// Read-only view of a channel's fill level for a monitoring thread. It only
+// loads the head and tail indices, so it can be shared freely.
+pub struct QueueDepth<T> {
+    queue: Arc<SpscQueue<T>>,
+}
+
+unsafe impl<T: Send> Send for QueueDepth<T> {}
+unsafe impl<T: Send> Sync for QueueDepth<T> {}
+
+impl<T> Clone for QueueDepth<T> {
+    fn clone(&self) -> Self {
+        QueueDepth {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> QueueDepth<T> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Arc;
     use std::thread;
 
     #[test]
@@ -148,12 +453,11 @@ mod tests {
 
     #[test]
     fn test_multi_threaded() {
-        let queue = Arc::new(SpscQueue::new(1024));
-        let queue_clone = queue.clone();
+        let (mut tx, mut rx) = channel(1024);
 
         let producer = thread::spawn(move || {
             for i in 0..10000 {
-                while queue_clone.push(i).is_err() {
+                while tx.push(i).is_err() {
                     std::hint::spin_loop();
                 }
             }
@@ -162,7 +466,7 @@ mod tests {
         let consumer = thread::spawn(move || {
             let mut received = Vec::new();
             while received.len() < 10000 {
-                if let Some(val) = queue.pop() {
+                if let Some(val) = rx.pop() {
                     received.push(val);
                 } else {
                     std::hint::spin_loop();
@@ -180,9 +484,165 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_small_channel_multi_threaded() {
+        let (mut tx, mut rx) = channel(2);
+
+        let producer = thread::spawn(move || {
+            for i in 0..10000u64 {
+                while tx.push(i).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0u64;
+        while expected < 10000 {
+            if let Some(val) = rx.pop() {
+                assert_eq!(val, expected);
+                expected += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+
+        producer.join().unwrap();
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn test_push_pop_batch() {
+        let queue = SpscQueue::new(8);
+
+        assert_eq!(queue.push_batch(&[1, 2, 3, 4, 5]), 5);
+        assert_eq!(queue.push_batch(&[6, 7, 8, 9, 10]), 3);
+        assert_eq!(queue.len(), 8);
+
+        let mut out = [0; 6];
+        assert_eq!(queue.pop_batch(&mut out), 6);
+        assert_eq!(out, [1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(queue.pop_batch(&mut out), 2);
+        assert_eq!(&out[..2], &[7, 8]);
+        assert_eq!(queue.pop_batch(&mut out), 0);
+    }
+
+    #[test]
+    fn test_batch_wraparound() {
+        let queue = SpscQueue::new(4);
+        let mut out = [0; 3];
+
+        for i in 0..50 {
+            assert_eq!(queue.push_batch(&[i, i + 1, i + 2]), 3);
+            assert_eq!(queue.pop_batch(&mut out), 3);
+            assert_eq!(out, [i, i + 1, i + 2]);
+        }
+    }
+
+    #[test]
+    fn test_reserve_commit() {
+        let mut queue = SpscQueue::new(2);
+
+        {
+            let mut slot = queue.reserve().unwrap();
+            slot.write(7u64);
+        }
+        assert!(queue.is_empty());
+
+        let mut slot = queue.reserve().unwrap();
+        unsafe {
+            slot.as_mut_ptr().write(7u64);
+            slot.commit();
+        }
+        let mut slot = queue.reserve().unwrap();
+        unsafe {
+            slot.as_mut_ptr().write(8u64);
+            slot.commit();
+        }
+
+        assert!(queue.reserve().is_none());
+        assert_eq!(queue.pop(), Some(7));
+        assert_eq!(queue.pop(), Some(8));
+    }
+
+    #[test]
+    fn test_reserve_fieldwise() {
+        #[derive(Debug, PartialEq)]
+        struct Snapshot {
+            seq: u64,
+            levels: [u32; 4],
+        }
+
+        let mut queue = SpscQueue::<Snapshot>::new(2);
+
+        let mut slot = queue.reserve().unwrap();
+        let ptr = slot.as_mut_ptr();
+        unsafe {
+            std::ptr::addr_of_mut!((*ptr).seq).write(9);
+            let levels = std::ptr::addr_of_mut!((*ptr).levels) as *mut u32;
+            for i in 0..4 {
+                levels.add(i).write(i as u32 * 10);
+            }
+            slot.commit();
+        }
+
+        assert_eq!(
+            queue.pop(),
+            Some(Snapshot {
+                seq: 9,
+                levels: [0, 10, 20, 30],
+            })
+        );
+    }
+
+    #[test]
+    fn test_channel_handles() {
+        let (mut tx, mut rx) = channel(4);
+
+        assert_eq!(tx.capacity(), 4);
+        assert!(rx.is_empty());
+
+        tx.push(1).unwrap();
+        tx.push(2).unwrap();
+        assert_eq!(tx.len(), 2);
+        assert_eq!(rx.len(), 2);
+
+        assert_eq!(rx.pop(), Some(1));
+        assert_eq!(rx.pop(), Some(2));
+        assert_eq!(rx.pop(), None);
+    }
+
+    #[test]
+    fn test_channel_drops_remaining() {
+        let value = Arc::new(());
+        let (mut tx, rx) = channel(4);
+
+        tx.push(value.clone()).unwrap();
+        tx.push(value.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&value), 3);
+
+        drop(tx);
+        drop(rx);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
     #[test]
     #[should_panic(expected = "capacity must be a power of 2")]
     fn test_non_power_of_two() {
         let _queue: SpscQueue<i32> = SpscQueue::new(3);
     }
+
+    #[test]
+    fn test_queue_depth_probe() {
+        let (mut tx, mut rx) = channel::<u32>(8);
+        let depth = tx.depth();
+
+        tx.push(1).unwrap();
+        tx.push(2).unwrap();
+        assert_eq!(depth.len(), 2);
+
+        rx.pop();
+        assert_eq!(depth.len(), 1);
+        assert_eq!(depth.capacity(), 8);
+    }
 }
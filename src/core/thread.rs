@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 pub fn pin_to_cpu(core_id: usize) -> io::Result<()> {
@@ -24,6 +25,13 @@ fn pin_to_cpu_linux(core_id: usize) -> io::Result<()> {
     use libc::{CPU_SET, CPU_ZERO, cpu_set_t, pthread_self, pthread_setaffinity_np};
     use std::mem;
 
+    if core_id >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("CPU {} is outside the affinity mask", core_id),
+        ));
+    }
+
     unsafe {
         let mut cpuset: cpu_set_t = mem::zeroed();
         CPU_ZERO(&mut cpuset);
@@ -43,6 +51,13 @@ fn pin_to_cpu_linux(core_id: usize) -> io::Result<()> {
 fn pin_to_cpu_windows(core_id: usize) -> io::Result<()> {
     use windows::Win32::System::Threading::{GetCurrentThread, SetThreadAffinityMask};
 
+    if core_id >= usize::BITS as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("CPU {} is outside the affinity mask", core_id),
+        ));
+    }
+
     unsafe {
         let mask: usize = 1 << core_id;
         let result = SetThreadAffinityMask(GetCurrentThread(), mask);
@@ -55,6 +70,271 @@ fn pin_to_cpu_windows(core_id: usize) -> io::Result<()> {
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadSetting {
+    Affinity,
+    Name,
+    Scheduler,
+    MemoryLock,
+    StackPrefault,
+}
+
+impl ThreadSetting {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ThreadSetting::Affinity => "affinity",
+            ThreadSetting::Name => "name",
+            ThreadSetting::Scheduler => "sched_fifo",
+            ThreadSetting::MemoryLock => "mlockall",
+            ThreadSetting::StackPrefault => "stack_prefault",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingOutcome {
+    Applied,
+    // Refused for lack of privileges (CAP_SYS_NICE, RLIMIT_MEMLOCK, ...); the
+    // thread keeps running without it.
+    Downgraded(io::Error),
+    Unsupported,
+}
+
+#[derive(Debug, Default)]
+pub struct ThreadReport {
+    steps: Vec<(ThreadSetting, SettingOutcome)>,
+}
+
+impl ThreadReport {
+    pub fn steps(&self) -> &[(ThreadSetting, SettingOutcome)] {
+        &self.steps
+    }
+
+    pub fn outcome(&self, setting: ThreadSetting) -> Option<&SettingOutcome> {
+        self.steps
+            .iter()
+            .find(|(s, _)| *s == setting)
+            .map(|(_, outcome)| outcome)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, SettingOutcome::Applied))
+    }
+
+    fn push(&mut self, setting: ThreadSetting, result: io::Result<()>) -> io::Result<()> {
+        let outcome = match result {
+            Ok(()) => SettingOutcome::Applied,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => SettingOutcome::Unsupported,
+            Err(e) if is_privilege_error(&e) => SettingOutcome::Downgraded(e),
+            Err(e) => return Err(e),
+        };
+        self.steps.push((setting, outcome));
+        Ok(())
+    }
+}
+
+impl fmt::Display for ThreadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (setting, outcome)) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match outcome {
+                SettingOutcome::Applied => write!(f, "{} ok", setting.as_str())?,
+                SettingOutcome::Downgraded(e) => {
+                    write!(f, "{} downgraded ({})", setting.as_str(), e)?
+                }
+                SettingOutcome::Unsupported => write!(f, "{} unsupported", setting.as_str())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_privilege_error(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::PermissionDenied {
+        return true;
+    }
+
+    // mlockall reports an exhausted RLIMIT_MEMLOCK as ENOMEM.
+    #[cfg(unix)]
+    if e.raw_os_error() == Some(libc::ENOMEM) {
+        return true;
+    }
+
+    false
+}
+
+// Everything a pipeline thread sets up about itself before entering its hot
+// loop. Applied from inside the thread; settings the host refuses for lack of
+// privileges are downgraded and reported rather than failing the stage.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadProfile {
+    name: Option<String>,
+    cpu: Option<usize>,
+    fifo_priority: Option<i32>,
+    lock_memory: bool,
+    prefault_stack: usize,
+}
+
+impl ThreadProfile {
+    pub const DEFAULT_PREFAULT: usize = 256 * 1024;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // What production hosts run with: FIFO priority, locked memory and a
+    // pre-faulted stack. Only use on isolated cores; a spinning FIFO thread
+    // starves anything else scheduled on its CPU.
+    pub fn realtime(priority: i32) -> Self {
+        Self::new()
+            .fifo_priority(priority)
+            .lock_memory(true)
+            .prefault_stack(Self::DEFAULT_PREFAULT)
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn cpu(mut self, cpu: usize) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    pub fn fifo_priority(mut self, priority: i32) -> Self {
+        self.fifo_priority = Some(priority);
+        self
+    }
+
+    pub fn lock_memory(mut self, lock: bool) -> Self {
+        self.lock_memory = lock;
+        self
+    }
+
+    pub fn prefault_stack(mut self, bytes: usize) -> Self {
+        self.prefault_stack = bytes;
+        self
+    }
+
+    pub fn apply(&self) -> io::Result<ThreadReport> {
+        let mut report = ThreadReport::default();
+
+        if let Some(cpu) = self.cpu {
+            report.push(ThreadSetting::Affinity, pin_to_cpu(cpu))?;
+        }
+        if let Some(ref name) = self.name {
+            report.push(ThreadSetting::Name, set_thread_name(name))?;
+        }
+        if self.lock_memory {
+            report.push(ThreadSetting::MemoryLock, lock_all_memory())?;
+        }
+        if self.prefault_stack > 0 {
+            prefault_stack(self.prefault_stack);
+            report.push(ThreadSetting::StackPrefault, Ok(()))?;
+        }
+        // Last, so the setup above is not competing at FIFO priority.
+        if let Some(priority) = self.fifo_priority {
+            report.push(ThreadSetting::Scheduler, set_fifo_priority(priority))?;
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_name(name: &str) -> io::Result<()> {
+    // The kernel keeps 15 bytes plus the terminator.
+    let mut bytes: Vec<u8> = name.bytes().filter(|&b| b != 0).take(15).collect();
+    bytes.push(0);
+
+    let result = unsafe { libc::pthread_setname_np(libc::pthread_self(), bytes.as_ptr().cast()) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_thread_name(_name: &str) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(target_os = "linux")]
+fn set_fifo_priority(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_fifo_priority(_priority: i32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(unix)]
+fn lock_all_memory() -> io::Result<()> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lock_all_memory() -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+// Touches `bytes` of stack below the caller so the hot loop never takes a
+// page fault growing into it.
+#[inline(never)]
+fn prefault_stack(bytes: usize) {
+    const CHUNK: usize = 4096;
+
+    let mut page = [0u8; CHUNK];
+    std::hint::black_box(&mut page);
+
+    if bytes > CHUNK {
+        prefault_stack(bytes - CHUNK);
+    }
+}
+
+// CPUs this process may run on, i.e. what `taskset`, cgroup cpusets or a
+// container runtime left in its `sched_getaffinity` mask.
+pub fn affinity_cpus() -> io::Result<Vec<usize>> {
+    #[cfg(target_os = "linux")]
+    {
+        use libc::{CPU_ISSET, CPU_SETSIZE, cpu_set_t, sched_getaffinity};
+        use std::mem;
+
+        unsafe {
+            let mut cpuset: cpu_set_t = mem::zeroed();
+            if sched_getaffinity(0, mem::size_of::<cpu_set_t>(), &mut cpuset) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((0..CPU_SETSIZE as usize)
+                .filter(|&cpu| CPU_ISSET(cpu, &cpuset))
+                .collect())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok((0..num_cpus()).collect())
+    }
+}
+
 pub fn num_cpus() -> usize {
     std::thread::available_parallelism()
         .map(|n| n.get())
@@ -65,6 +345,13 @@ pub fn num_cpus() -> usize {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_affinity_cpus() {
+        let cpus = affinity_cpus().unwrap();
+        assert!(!cpus.is_empty());
+        assert!(cpus.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
     #[test]
     fn test_num_cpus() {
         let cpus = num_cpus();
@@ -72,6 +359,26 @@ mod tests {
         println!("Available CPUs: {}", cpus);
     }
 
+    #[test]
+    fn test_thread_profile_report() {
+        let report = std::thread::spawn(|| {
+            ThreadProfile::new()
+                .name("hft-test-profile-thread")
+                .prefault_stack(64 * 1024)
+                .apply()
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+
+        assert!(matches!(
+            report.outcome(ThreadSetting::StackPrefault),
+            Some(SettingOutcome::Applied)
+        ));
+        assert!(report.outcome(ThreadSetting::Scheduler).is_none());
+        assert!(report.to_string().contains("stack_prefault ok"));
+    }
+
     #[test]
     fn test_pin_to_cpu() {
         let result = pin_to_cpu(0);
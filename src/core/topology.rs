@@ -0,0 +1,539 @@
+use crate::core::metrics::Stage;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SYSFS_CPU_ROOT: &str = "/sys/devices/system/cpu";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    pub id: usize,
+    pub core_id: usize,
+    pub socket: usize,
+    pub node: usize,
+    pub siblings: Vec<usize>,
+    pub isolated: bool,
+    pub nohz_full: bool,
+}
+
+impl Cpu {
+    // Physical cores are only unique within a socket.
+    #[inline]
+    pub fn physical_core(&self) -> (usize, usize) {
+        (self.socket, self.core_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CpuTopology {
+    cpus: Vec<Cpu>,
+    // Online CPUs dropped by `restrict_to`, reported by `plan`.
+    excluded: Vec<usize>,
+}
+
+impl CpuTopology {
+    pub fn detect() -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            Self::from_sysfs(SYSFS_CPU_ROOT)?.restrict_to(&crate::core::thread::affinity_cpus()?)
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            Ok(Self::flat(crate::core::thread::num_cpus()))
+        }
+    }
+
+    // Reads the layout the kernel exports under `/sys/devices/system/cpu`.
+    // Missing topology files (containers, old kernels) degrade to one core
+    // per CPU on socket 0 and node 0 rather than failing.
+    pub fn from_sysfs(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref();
+
+        let online = parse_cpu_list(&fs::read_to_string(root.join("online"))?)?;
+        let isolated = read_cpu_list(&root.join("isolated"))?;
+        let nohz_full = read_cpu_list(&root.join("nohz_full"))?;
+
+        let mut cpus = Vec::with_capacity(online.len());
+        for id in online {
+            let dir = root.join(format!("cpu{}", id));
+            let topology = dir.join("topology");
+
+            let core_id = read_usize(&topology.join("core_id"))?.unwrap_or(id);
+            let socket = read_usize(&topology.join("physical_package_id"))?.unwrap_or(0);
+
+            let mut siblings = read_cpu_list(&topology.join("thread_siblings_list"))?;
+            if siblings.is_empty() {
+                siblings.push(id);
+            }
+
+            cpus.push(Cpu {
+                id,
+                core_id,
+                socket,
+                node: node_of(&dir)?.unwrap_or(0),
+                siblings,
+                isolated: isolated.contains(&id),
+                nohz_full: nohz_full.contains(&id),
+            });
+        }
+
+        if cpus.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no online CPUs listed in sysfs",
+            ));
+        }
+
+        Ok(CpuTopology {
+            cpus,
+            excluded: Vec::new(),
+        })
+    }
+
+    // Keeps only the CPUs in `allowed`, typically the process affinity mask:
+    // pinning a stage anywhere else would fail.
+    pub fn restrict_to(mut self, allowed: &[usize]) -> io::Result<Self> {
+        let (cpus, excluded): (Vec<Cpu>, Vec<Cpu>) = self
+            .cpus
+            .into_iter()
+            .partition(|cpu| allowed.contains(&cpu.id));
+        if cpus.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no online CPUs in the affinity mask",
+            ));
+        }
+
+        self.cpus = cpus;
+        self.excluded.extend(excluded.iter().map(|cpu| cpu.id));
+        self.excluded.sort_unstable();
+        Ok(self)
+    }
+
+    pub fn flat(count: usize) -> Self {
+        Self::from_cpus(
+            (0..count.max(1))
+                .map(|id| Cpu {
+                    id,
+                    core_id: id,
+                    socket: 0,
+                    node: 0,
+                    siblings: vec![id],
+                    isolated: false,
+                    nohz_full: false,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn from_cpus(mut cpus: Vec<Cpu>) -> Self {
+        cpus.sort_by_key(|cpu| cpu.id);
+        CpuTopology {
+            cpus,
+            excluded: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn cpus(&self) -> &[Cpu] {
+        &self.cpus
+    }
+
+    pub fn cpu(&self, id: usize) -> Option<&Cpu> {
+        self.cpus.iter().find(|cpu| cpu.id == id)
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.cpu(id).is_some()
+    }
+
+    #[inline]
+    pub fn excluded(&self) -> &[usize] {
+        &self.excluded
+    }
+
+    pub fn nodes(&self) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self.cpus.iter().map(|cpu| cpu.node).collect();
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+    }
+
+    pub fn sockets(&self) -> Vec<usize> {
+        let mut sockets: Vec<usize> = self.cpus.iter().map(|cpu| cpu.socket).collect();
+        sockets.sort_unstable();
+        sockets.dedup();
+        sockets
+    }
+
+    // One representative CPU per physical core on `node`, lowest id first.
+    // Placing a stage there leaves the core's other hyperthreads unused.
+    pub fn physical_cores(&self, node: usize) -> Vec<&Cpu> {
+        let mut cores: BTreeMap<(usize, usize), &Cpu> = BTreeMap::new();
+        for cpu in self.cpus.iter().filter(|cpu| cpu.node == node) {
+            cores.entry(cpu.physical_core()).or_insert(cpu);
+        }
+
+        let mut cores: Vec<&Cpu> = cores.into_values().collect();
+        cores.sort_by_key(|cpu| cpu.id);
+        cores
+    }
+
+    // A core only counts as isolated if none of its hyperthreads can be
+    // handed work by the scheduler.
+    fn core_isolated(&self, cpu: &Cpu) -> bool {
+        cpu.siblings
+            .iter()
+            .all(|&id| self.cpu(id).is_none_or(|sibling| sibling.isolated))
+    }
+
+    pub fn plan(&self) -> Placement {
+        let mut best: Option<(usize, Vec<&Cpu>)> = None;
+
+        for node in self.nodes() {
+            let mut cores = self.physical_cores(node);
+            cores.sort_by_key(|cpu| (!self.core_isolated(cpu), !cpu.nohz_full, cpu.id));
+
+            let isolated = cores.iter().filter(|cpu| self.core_isolated(cpu)).count();
+            let score = (isolated.min(Stage::COUNT), cores.len().min(Stage::COUNT));
+
+            let better = match best {
+                None => true,
+                Some((_, ref current)) => {
+                    let current_isolated =
+                        current.iter().filter(|cpu| self.core_isolated(cpu)).count();
+                    score
+                        > (
+                            current_isolated.min(Stage::COUNT),
+                            current.len().min(Stage::COUNT),
+                        )
+                }
+            };
+            if better {
+                best = Some((node, cores));
+            }
+        }
+
+        let (node, mut cores) = best.expect("topology has at least one CPU");
+        let mut warnings = Vec::new();
+        if !self.excluded.is_empty() {
+            warnings.push(format!(
+                "CPUs {} are online but outside the affinity mask",
+                format_cpu_list(&self.excluded)
+            ));
+        }
+
+        // Not enough physical cores on the node: fall back to hyperthreads on
+        // the same node, then to other nodes, then to doubling up.
+        if cores.len() < Stage::COUNT {
+            let extra: Vec<&Cpu> = self
+                .cpus
+                .iter()
+                .filter(|cpu| cpu.node == node && !cores.iter().any(|c| c.id == cpu.id))
+                .chain(self.cpus.iter().filter(|cpu| cpu.node != node))
+                .collect();
+            cores.extend(extra);
+        }
+
+        let mut cpus = [0usize; Stage::COUNT];
+        for (i, stage) in Stage::ALL.iter().enumerate() {
+            let cpu = cores[i % cores.len()];
+            cpus[i] = cpu.id;
+
+            if i >= cores.len() {
+                warnings.push(format!(
+                    "{} shares CPU {} with {}",
+                    stage.as_str(),
+                    cpu.id,
+                    Stage::ALL[i % cores.len()].as_str()
+                ));
+                continue;
+            }
+            if !self.core_isolated(cpu) {
+                warnings.push(format!(
+                    "{} on CPU {} which is not isolated (isolcpus)",
+                    stage.as_str(),
+                    cpu.id
+                ));
+            }
+            if !cpu.nohz_full {
+                warnings.push(format!(
+                    "{} on CPU {} which still takes scheduler ticks (nohz_full)",
+                    stage.as_str(),
+                    cpu.id
+                ));
+            }
+            if cpu.node != node {
+                warnings.push(format!(
+                    "{} on CPU {} is on NUMA node {}, not node {}",
+                    stage.as_str(),
+                    cpu.id,
+                    cpu.node,
+                    node
+                ));
+            }
+            if let Some(other) = cores[..i]
+                .iter()
+                .position(|c| c.physical_core() == cpu.physical_core())
+            {
+                warnings.push(format!(
+                    "{} on CPU {} shares a physical core with {}",
+                    stage.as_str(),
+                    cpu.id,
+                    Stage::ALL[other].as_str()
+                ));
+            }
+        }
+
+        Placement {
+            node,
+            cpus,
+            warnings,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Placement {
+    node: usize,
+    cpus: [usize; Stage::COUNT],
+    warnings: Vec<String>,
+}
+
+impl Placement {
+    #[inline]
+    pub fn cpu(&self, stage: Stage) -> usize {
+        self.cpus[stage as usize]
+    }
+
+    #[inline]
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    #[inline]
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    #[inline]
+    pub fn is_ideal(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stage placement (NUMA node {}):", self.node)?;
+        for stage in Stage::ALL {
+            writeln!(f, "  {:<12} -> CPU {}", stage.as_str(), self.cpu(stage))?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  Warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+// Kernel cpulist format: "0-3,8,10-11". Empty input and "(null)" (what
+// `nohz_full` reads as when unset) both mean no CPUs.
+pub fn parse_cpu_list(list: &str) -> io::Result<Vec<usize>> {
+    let list = list.trim();
+    if list.is_empty() || list == "(null)" {
+        return Ok(Vec::new());
+    }
+
+    let parse = |s: &str| {
+        s.trim().parse::<usize>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad cpu list entry {:?}", s),
+            )
+        })
+    };
+
+    let mut cpus = Vec::new();
+    for range in list.split(',') {
+        match range.split_once('-') {
+            Some((low, high)) => cpus.extend(parse(low)?..=parse(high)?),
+            None => cpus.push(parse(range)?),
+        }
+    }
+
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+// The inverse of `parse_cpu_list` for sorted, deduplicated ids.
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, high)) if *high + 1 == cpu => *high = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(low, high)| {
+            if low == high {
+                low.to_string()
+            } else {
+                format!("{}-{}", low, high)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn read_cpu_list(path: &Path) -> io::Result<Vec<usize>> {
+    match fs::read_to_string(path) {
+        Ok(list) => parse_cpu_list(&list),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_usize(path: &Path) -> io::Result<Option<usize>> {
+    match fs::read_to_string(path) {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad value in {}", path.display()),
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Each `cpuN` directory carries a `nodeM` link to its NUMA node.
+fn node_of(cpu_dir: &Path) -> io::Result<Option<usize>> {
+    for entry in fs::read_dir(cpu_dir)? {
+        let name = entry?.file_name();
+        let node = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("node"))
+            .and_then(|n| n.parse().ok());
+        if node.is_some() {
+            return Ok(node);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(id: usize, core_id: usize, node: usize, siblings: &[usize], isolated: bool) -> Cpu {
+        Cpu {
+            id,
+            core_id,
+            socket: node,
+            node,
+            siblings: siblings.to_vec(),
+            isolated,
+            nohz_full: isolated,
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert!(parse_cpu_list("(null)\n").unwrap().is_empty());
+        assert!(parse_cpu_list("").unwrap().is_empty());
+        assert!(parse_cpu_list("1-x").is_err());
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+    }
+
+    #[test]
+    fn test_from_sysfs() {
+        let root = std::env::temp_dir().join(format!("hft-topology-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("online"), "0-3\n").unwrap();
+        fs::write(root.join("isolated"), "2-3\n").unwrap();
+        fs::write(root.join("nohz_full"), "(null)\n").unwrap();
+        for id in 0..4 {
+            let topology = root.join(format!("cpu{}", id)).join("topology");
+            fs::create_dir_all(&topology).unwrap();
+            fs::create_dir_all(root.join(format!("cpu{}", id)).join("node0")).unwrap();
+            fs::write(topology.join("core_id"), format!("{}\n", id % 2)).unwrap();
+            fs::write(topology.join("physical_package_id"), "0\n").unwrap();
+            fs::write(
+                topology.join("thread_siblings_list"),
+                format!("{},{}\n", id % 2, id % 2 + 2),
+            )
+            .unwrap();
+        }
+
+        let topology = CpuTopology::from_sysfs(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(topology.cpus().len(), 4);
+        assert_eq!(topology.cpu(2).unwrap().siblings, vec![0, 2]);
+        assert!(topology.cpu(3).unwrap().isolated);
+        assert!(!topology.cpu(3).unwrap().nohz_full);
+        assert_eq!(topology.physical_cores(0).len(), 2);
+    }
+
+    #[test]
+    fn test_plan_isolated_node() {
+        // Node 0: four cores, none isolated. Node 1: four isolated cores,
+        // each with a hyperthread sibling.
+        let mut cpus = Vec::new();
+        for core in 0..4 {
+            cpus.push(cpu(core, core, 0, &[core], false));
+            cpus.push(cpu(4 + core, core, 1, &[4 + core, 8 + core], true));
+            cpus.push(cpu(8 + core, core, 1, &[4 + core, 8 + core], true));
+        }
+        let placement = CpuTopology::from_cpus(cpus).plan();
+
+        assert!(placement.is_ideal(), "{}", placement);
+        assert_eq!(placement.node(), 1);
+        assert_eq!(placement.cpu(Stage::MarketData), 4);
+        assert_eq!(placement.cpu(Stage::Gateway), 7);
+    }
+
+    #[test]
+    fn test_plan_within_affinity_mask() {
+        let topology = CpuTopology::flat(8).restrict_to(&[2, 3, 4, 5, 12]).unwrap();
+        assert_eq!(topology.excluded(), &[0, 1, 6, 7]);
+
+        let placement = topology.plan();
+        assert_eq!(placement.cpu(Stage::MarketData), 2);
+        assert_eq!(placement.cpu(Stage::Gateway), 5);
+        assert!(
+            placement
+                .warnings()
+                .iter()
+                .any(|w| w.contains("CPUs 0-1,6-7 are online but outside the affinity mask"))
+        );
+
+        assert!(CpuTopology::flat(2).restrict_to(&[4]).is_err());
+    }
+
+    #[test]
+    fn test_plan_reports_shortfall() {
+        let placement = CpuTopology::flat(2).plan();
+
+        assert!(!placement.is_ideal());
+        assert_eq!(placement.cpu(Stage::Risk), 0);
+        assert!(
+            placement
+                .warnings()
+                .iter()
+                .any(|w| w.contains("risk shares CPU 0"))
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+pub trait WaitStrategy {
+    fn wait(&mut self);
+
+    fn reset(&mut self);
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BusySpin;
+
+impl WaitStrategy for BusySpin {
+    #[inline(always)]
+    fn wait(&mut self) {
+        std::hint::spin_loop();
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpinThenYield {
+    spins: u32,
+    count: u32,
+}
+
+impl SpinThenYield {
+    pub const fn new(spins: u32) -> Self {
+        SpinThenYield { spins, count: 0 }
+    }
+}
+
+impl WaitStrategy for SpinThenYield {
+    #[inline(always)]
+    fn wait(&mut self) {
+        if self.count < self.spins {
+            self.count += 1;
+            std::hint::spin_loop();
+        } else {
+            thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+// One per thread. A consumer announces itself before parking and producers
+// that see the announcement unpark it, so a parked stage wakes as soon as
+// something is published rather than at its timeout. `std::thread::park` is a
+// futex wait on Linux.
+#[derive(Debug)]
+pub struct Parker {
+    parked: AtomicBool,
+    thread: Thread,
+}
+
+thread_local! {
+    static CURRENT_PARKER: Arc<Parker> = Arc::new(Parker {
+        parked: AtomicBool::new(false),
+        thread: thread::current(),
+    });
+}
+
+impl Parker {
+    pub fn current() -> Arc<Parker> {
+        CURRENT_PARKER.with(Arc::clone)
+    }
+
+    // The fence pairs with the one in `WakeList::wake`: either the producer
+    // sees `parked` and unparks, or the consumer's next poll sees the item.
+    #[inline]
+    fn announce(&self) {
+        self.parked.store(true, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+    }
+
+    #[inline]
+    fn cancel(&self) {
+        self.parked.store(false, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn is_parked(&self) -> bool {
+        self.parked.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn unpark(&self) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            self.thread.unpark();
+        }
+    }
+}
+
+pub const MAX_WAITERS: usize = 32;
+
+// Parkers of the threads consuming one queue. Producers call `wake` after
+// publishing; with nobody registered it is a single load.
+pub struct WakeList {
+    count: AtomicUsize,
+    parkers: [OnceLock<Arc<Parker>>; MAX_WAITERS],
+}
+
+impl WakeList {
+    pub const fn new() -> Self {
+        WakeList {
+            count: AtomicUsize::new(0),
+            parkers: [const { OnceLock::new() }; MAX_WAITERS],
+        }
+    }
+
+    // Registering the same parker twice is a no-op.
+    pub fn register(&self, parker: Arc<Parker>) {
+        let registered = self.count.load(Ordering::Acquire).min(MAX_WAITERS);
+        if self.parkers[..registered]
+            .iter()
+            .any(|slot| slot.get().is_some_and(|p| Arc::ptr_eq(p, &parker)))
+        {
+            return;
+        }
+
+        let index = self.count.fetch_add(1, Ordering::AcqRel);
+        assert!(index < MAX_WAITERS, "more than {} waiters", MAX_WAITERS);
+        let _ = self.parkers[index].set(parker);
+    }
+
+    #[inline(always)]
+    pub fn wake(&self) {
+        let count = self.count.load(Ordering::Acquire);
+        if count == 0 {
+            return;
+        }
+
+        fence(Ordering::SeqCst);
+        for slot in &self.parkers[..count.min(MAX_WAITERS)] {
+            if let Some(parker) = slot.get()
+                && parker.is_parked()
+            {
+                parker.unpark();
+            }
+        }
+    }
+}
+
+impl Default for WakeList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Spins, then parks the thread until a producer wakes it. Stages using it
+// register `Parker::current()` with their input queues. `timeout` only
+// bounds waits nothing wakes, such as pushing into a full queue or a stop
+// raised by the watchdog.
+#[derive(Debug, Clone, Copy)]
+pub struct SpinThenPark {
+    spins: u32,
+    count: u32,
+    timeout: Duration,
+    announced: bool,
+}
+
+impl SpinThenPark {
+    pub const fn new(spins: u32, timeout: Duration) -> Self {
+        SpinThenPark {
+            spins,
+            count: 0,
+            timeout,
+            announced: false,
+        }
+    }
+}
+
+impl WaitStrategy for SpinThenPark {
+    #[inline(always)]
+    fn wait(&mut self) {
+        if self.count < self.spins {
+            self.count += 1;
+            std::hint::spin_loop();
+        } else if !self.announced {
+            // Return once more so the caller polls after announcing.
+            CURRENT_PARKER.with(|parker| parker.announce());
+            self.announced = true;
+        } else {
+            thread::park_timeout(self.timeout);
+            CURRENT_PARKER.with(|parker| parker.cancel());
+            self.announced = false;
+        }
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.count = 0;
+        if self.announced {
+            CURRENT_PARKER.with(|parker| parker.cancel());
+            self.announced = false;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FixedBackoff {
+    backoff: Duration,
+}
+
+impl FixedBackoff {
+    pub const fn new(backoff: Duration) -> Self {
+        FixedBackoff { backoff }
+    }
+}
+
+impl WaitStrategy for FixedBackoff {
+    #[inline(always)]
+    fn wait(&mut self) {
+        thread::sleep(self.backoff);
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Waiter {
+    BusySpin(BusySpin),
+    SpinThenYield(SpinThenYield),
+    SpinThenPark(SpinThenPark),
+    FixedBackoff(FixedBackoff),
+}
+
+impl Waiter {
+    pub const fn busy_spin() -> Self {
+        Waiter::BusySpin(BusySpin)
+    }
+
+    pub const fn spin_then_yield(spins: u32) -> Self {
+        Waiter::SpinThenYield(SpinThenYield::new(spins))
+    }
+
+    pub const fn spin_then_park(spins: u32, timeout: Duration) -> Self {
+        Waiter::SpinThenPark(SpinThenPark::new(spins, timeout))
+    }
+
+    pub const fn fixed_backoff(backoff: Duration) -> Self {
+        Waiter::FixedBackoff(FixedBackoff::new(backoff))
+    }
+}
+
+impl Waiter {
+    // Whether the stage should register `Parker::current()` with its inputs.
+    #[inline]
+    pub const fn parks(&self) -> bool {
+        matches!(self, Waiter::SpinThenPark(_))
+    }
+}
+
+impl Default for Waiter {
+    fn default() -> Self {
+        Self::busy_spin()
+    }
+}
+
+impl WaitStrategy for Waiter {
+    #[inline(always)]
+    fn wait(&mut self) {
+        match self {
+            Waiter::BusySpin(w) => w.wait(),
+            Waiter::SpinThenYield(w) => w.wait(),
+            Waiter::SpinThenPark(w) => w.wait(),
+            Waiter::FixedBackoff(w) => w.wait(),
+        }
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        match self {
+            Waiter::BusySpin(w) => w.reset(),
+            Waiter::SpinThenYield(w) => w.reset(),
+            Waiter::SpinThenPark(w) => w.reset(),
+            Waiter::FixedBackoff(w) => w.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_spin_then_yield_counts() {
+        let mut waiter = SpinThenYield::new(3);
+
+        for _ in 0..5 {
+            waiter.wait();
+        }
+        assert_eq!(waiter.count, 3);
+
+        waiter.reset();
+        assert_eq!(waiter.count, 0);
+    }
+
+    #[test]
+    fn test_spin_then_park_is_woken() {
+        let wakers = Arc::new(WakeList::new());
+        let ready = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let (wakers, ready) = (wakers.clone(), ready.clone());
+            thread::spawn(move || {
+                wakers.register(Parker::current());
+                wakers.register(Parker::current());
+
+                let mut waiter = Waiter::spin_then_park(0, Duration::from_secs(30));
+                let start = Instant::now();
+                while !ready.load(Ordering::Acquire) {
+                    waiter.wait();
+                }
+                start.elapsed()
+            })
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        ready.store(true, Ordering::Release);
+        wakers.wake();
+
+        assert_eq!(wakers.count.load(Ordering::Relaxed), 1);
+        assert!(handle.join().unwrap() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_fixed_backoff_sleeps() {
+        let mut waiter = Waiter::fixed_backoff(Duration::from_millis(1));
+
+        let start = Instant::now();
+        waiter.wait();
+        assert!(start.elapsed() >= Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_waiter_default_is_busy_spin() {
+        assert!(matches!(Waiter::default(), Waiter::BusySpin(_)));
+        assert!(!Waiter::default().parks());
+        assert!(Waiter::spin_then_park(10, Duration::from_millis(1)).parks());
+    }
+}
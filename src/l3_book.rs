@@ -0,0 +1,748 @@
+use crate::core::memory::{Allocator, Buffer};
+use crate::core::types::{Price, Quantity};
+use crate::messages::{PriceLevel, Side};
+use crate::order_book::OrderBook;
+use std::fmt;
+
+const NIL: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookError {
+    DuplicateOrder,
+    UnknownOrder,
+    InvalidQuantity,
+    PoolFull,
+    LevelsFull,
+}
+
+impl BookError {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            BookError::DuplicateOrder => "duplicate_order",
+            BookError::UnknownOrder => "unknown_order",
+            BookError::InvalidQuantity => "invalid_quantity",
+            BookError::PoolFull => "pool_full",
+            BookError::LevelsFull => "levels_full",
+        }
+    }
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::error::Error for BookError {}
+
+// One order-by-order feed message, keyed by exchange order ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L3Update {
+    Add {
+        order_id: u64,
+        side: Side,
+        price: Price,
+        qty: Quantity,
+    },
+    Modify {
+        order_id: u64,
+        price: Price,
+        qty: Quantity,
+    },
+    Delete {
+        order_id: u64,
+    },
+    Execute {
+        order_id: u64,
+        qty: Quantity,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestingOrder {
+    pub order_id: u64,
+    pub side: Side,
+    pub price: Price,
+    pub qty: Quantity,
+}
+
+// Where an order sits in its level's FIFO: what has to trade or cancel
+// before it fills, and what rests behind it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub orders_ahead: u32,
+    pub qty_ahead: Quantity,
+    pub qty_behind: Quantity,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OrderNode {
+    order_id: u64,
+    price: Price,
+    qty: Quantity,
+    side: Side,
+    prev: u32,
+    next: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    price: Price,
+    qty: Quantity,
+    count: u32,
+    head: u32,
+    tail: u32,
+}
+
+// Levels kept sorted best-first in a fixed buffer; `len` of them are live.
+struct Ladder {
+    levels: Buffer<Level>,
+    len: usize,
+}
+
+impl Ladder {
+    #[inline(always)]
+    fn live(&self) -> &[Level] {
+        &self.levels[..self.len]
+    }
+
+    // `Ok(pos)` if a level exists at `price`, else where it would go.
+    #[inline(always)]
+    fn search(&self, side: Side, price: Price) -> Result<usize, usize> {
+        match side {
+            Side::Buy => self
+                .live()
+                .binary_search_by(|level| price.cmp(&level.price)),
+            Side::Sell => self
+                .live()
+                .binary_search_by(|level| level.price.cmp(&price)),
+        }
+    }
+
+    fn insert(&mut self, pos: usize, price: Price) -> Result<(), BookError> {
+        if self.len == self.levels.len() {
+            return Err(BookError::LevelsFull);
+        }
+        self.levels.copy_within(pos..self.len, pos + 1);
+        self.levels[pos] = Level {
+            price,
+            qty: Quantity::from_raw(0),
+            count: 0,
+            head: NIL,
+            tail: NIL,
+        };
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, pos: usize) {
+        self.levels.copy_within(pos + 1..self.len, pos);
+        self.len -= 1;
+    }
+}
+
+// Order-by-order book. Orders live in a pool preallocated at construction
+// and are chained into one FIFO per price level, so add/modify/delete/execute
+// never allocate. Exchange order IDs are found through an open-addressed
+// index sized to twice the pool.
+//
+// Modify keeps queue priority only when the price is unchanged and the
+// quantity goes down; anything else moves the order to the back of the
+// (possibly new) level, as exchanges do.
+pub struct L3OrderBook {
+    orders: Buffer<OrderNode>,
+    free: u32,
+    live: usize,
+    index: Buffer<u32>,
+    shift: u32,
+    bids: Ladder,
+    asks: Ladder,
+}
+
+impl L3OrderBook {
+    pub fn new(max_orders: usize, max_levels: usize) -> Self {
+        Self::new_in(max_orders, max_levels, &Allocator::heap())
+    }
+
+    pub fn new_in(max_orders: usize, max_levels: usize, allocator: &Allocator) -> Self {
+        assert!(max_orders > 0, "order pool must hold at least one order");
+        assert!(max_orders < NIL as usize, "order pool too large");
+        assert!(max_levels > 0, "book must hold at least one level per side");
+
+        let orders = allocator.alloc_with(max_orders, |i| OrderNode {
+            order_id: 0,
+            price: Price::from_raw(0),
+            qty: Quantity::from_raw(0),
+            side: Side::Buy,
+            prev: NIL,
+            next: if i + 1 < max_orders {
+                i as u32 + 1
+            } else {
+                NIL
+            },
+        });
+        let slots = (max_orders * 2).next_power_of_two();
+        let empty_level = |_| Level {
+            price: Price::from_raw(0),
+            qty: Quantity::from_raw(0),
+            count: 0,
+            head: NIL,
+            tail: NIL,
+        };
+
+        L3OrderBook {
+            orders,
+            free: 0,
+            live: 0,
+            index: allocator.alloc_with(slots, |_| NIL),
+            shift: 64 - slots.trailing_zeros(),
+            bids: Ladder {
+                levels: allocator.alloc_with(max_levels, empty_level),
+                len: 0,
+            },
+            asks: Ladder {
+                levels: allocator.alloc_with(max_levels, empty_level),
+                len: 0,
+            },
+        }
+    }
+
+    pub fn apply(&mut self, update: &L3Update) -> Result<(), BookError> {
+        match *update {
+            L3Update::Add {
+                order_id,
+                side,
+                price,
+                qty,
+            } => self.add(order_id, side, price, qty),
+            L3Update::Modify {
+                order_id,
+                price,
+                qty,
+            } => self.modify(order_id, price, qty),
+            L3Update::Delete { order_id } => self.delete(order_id).map(|_| ()),
+            L3Update::Execute { order_id, qty } => self.execute(order_id, qty).map(|_| ()),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        order_id: u64,
+        side: Side,
+        price: Price,
+        qty: Quantity,
+    ) -> Result<(), BookError> {
+        if qty.raw() <= 0 {
+            return Err(BookError::InvalidQuantity);
+        }
+        let slot = match self.find(order_id) {
+            Ok(_) => return Err(BookError::DuplicateOrder),
+            Err(slot) => slot,
+        };
+        if self.free == NIL {
+            return Err(BookError::PoolFull);
+        }
+
+        let node = self.free;
+        let next_free = self.orders[node as usize].next;
+        self.enqueue(node, side, price, qty)?;
+
+        self.free = next_free;
+        let order = &mut self.orders[node as usize];
+        order.order_id = order_id;
+        order.side = side;
+        order.price = price;
+        order.qty = qty;
+        self.index[slot] = node;
+        self.live += 1;
+        Ok(())
+    }
+
+    pub fn modify(&mut self, order_id: u64, price: Price, qty: Quantity) -> Result<(), BookError> {
+        if qty.raw() <= 0 {
+            return Err(BookError::InvalidQuantity);
+        }
+        let node = self.find(order_id).map_err(|_| BookError::UnknownOrder)?.1;
+        let order = self.orders[node as usize];
+
+        if price == order.price && qty <= order.qty {
+            self.reduce(node, order.qty - qty);
+            return Ok(());
+        }
+
+        // Check the destination first so a full ladder leaves the order
+        // where it was.
+        if price != order.price {
+            let ladder = self.ladder(order.side);
+            if ladder.search(order.side, price).is_err() && ladder.len == ladder.levels.len() {
+                let own = ladder.search(order.side, order.price).expect("order level");
+                if ladder.live()[own].count > 1 {
+                    return Err(BookError::LevelsFull);
+                }
+            }
+        }
+
+        self.unlink(node);
+        self.enqueue(node, order.side, price, qty)
+            .expect("level space checked above");
+        let order = &mut self.orders[node as usize];
+        order.price = price;
+        order.qty = qty;
+        Ok(())
+    }
+
+    // Returns the order as it rested before removal.
+    pub fn delete(&mut self, order_id: u64) -> Result<RestingOrder, BookError> {
+        let (slot, node) = self.find(order_id).map_err(|_| BookError::UnknownOrder)?;
+        let order = self.resting(node);
+        self.unlink(node);
+        self.release(slot, node);
+        Ok(order)
+    }
+
+    // Fills `qty` of a resting order and returns what is left; a full fill
+    // removes it from the book.
+    pub fn execute(&mut self, order_id: u64, qty: Quantity) -> Result<Quantity, BookError> {
+        let (slot, node) = self.find(order_id).map_err(|_| BookError::UnknownOrder)?;
+        let resting = self.orders[node as usize].qty;
+        if qty.raw() <= 0 || qty > resting {
+            return Err(BookError::InvalidQuantity);
+        }
+
+        if qty == resting {
+            self.unlink(node);
+            self.release(slot, node);
+        } else {
+            self.reduce(node, qty);
+        }
+        Ok(resting - qty)
+    }
+
+    pub fn order(&self, order_id: u64) -> Option<RestingOrder> {
+        self.find(order_id).ok().map(|(_, node)| self.resting(node))
+    }
+
+    // Exact for orders visible in the feed; hidden or iceberg quantity at
+    // the level is not counted ahead.
+    pub fn queue_position(&self, order_id: u64) -> Option<QueuePosition> {
+        let node = self.find(order_id).ok()?.1;
+        let order = &self.orders[node as usize];
+        let ladder = self.ladder(order.side);
+        let level = &ladder.live()[ladder.search(order.side, order.price).ok()?];
+
+        let mut position = QueuePosition {
+            orders_ahead: 0,
+            qty_ahead: Quantity::from_raw(0),
+            qty_behind: Quantity::from_raw(0),
+        };
+        let mut cursor = level.head;
+        while cursor != node {
+            let ahead = &self.orders[cursor as usize];
+            position.orders_ahead += 1;
+            position.qty_ahead = position.qty_ahead + ahead.qty;
+            cursor = ahead.next;
+        }
+        position.qty_behind = level.qty - position.qty_ahead - order.qty;
+        Some(position)
+    }
+
+    // Quantity an order joining `price` now would queue behind.
+    #[inline]
+    pub fn qty_at(&self, side: Side, price: Price) -> Quantity {
+        let ladder = self.ladder(side);
+        match ladder.search(side, price) {
+            Ok(pos) => ladder.live()[pos].qty,
+            Err(_) => Quantity::from_raw(0),
+        }
+    }
+
+    // Orders at one level in time priority.
+    pub fn orders_at(&self, side: Side, price: Price) -> impl Iterator<Item = RestingOrder> + '_ {
+        let ladder = self.ladder(side);
+        let mut cursor = match ladder.search(side, price) {
+            Ok(pos) => ladder.live()[pos].head,
+            Err(_) => NIL,
+        };
+        std::iter::from_fn(move || {
+            if cursor == NIL {
+                return None;
+            }
+            let order = self.resting(cursor);
+            cursor = self.orders[cursor as usize].next;
+            Some(order)
+        })
+    }
+
+    #[inline(always)]
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.live().first().map(|level| level.price)
+    }
+
+    #[inline(always)]
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.live().first().map(|level| level.price)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.orders.len()
+    }
+
+    #[inline]
+    pub fn depth(&self, side: Side) -> usize {
+        self.ladder(side).len
+    }
+
+    // Aggregates the best `levels.len()` levels of one side into `levels`,
+    // with real order counts, and returns how many were written. Slots past
+    // that are cleared.
+    pub fn copy_levels(&self, side: Side, levels: &mut [PriceLevel]) -> usize {
+        let live = self.ladder(side).live();
+        let depth = live.len().min(levels.len());
+        for (out, level) in levels.iter_mut().zip(&live[..depth]) {
+            *out = PriceLevel::new(level.price, level.qty);
+            out.order_count = level.count;
+        }
+        levels[depth..].fill(PriceLevel::empty());
+        depth
+    }
+
+    // L2 view truncated to DEPTH, e.g. for code written against `OrderBook`.
+    pub fn to_l2<const DEPTH: usize>(&self) -> OrderBook<DEPTH> {
+        let mut bids = [PriceLevel::empty(); DEPTH];
+        let mut asks = [PriceLevel::empty(); DEPTH];
+        self.copy_levels(Side::Buy, &mut bids);
+        self.copy_levels(Side::Sell, &mut asks);
+
+        let mut book = OrderBook::<DEPTH>::new();
+        book.set_levels(&bids, &asks);
+        book
+    }
+
+    #[inline(always)]
+    fn ladder(&self, side: Side) -> &Ladder {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    #[inline(always)]
+    fn ladder_mut(&mut self, side: Side) -> &mut Ladder {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    #[inline(always)]
+    fn resting(&self, node: u32) -> RestingOrder {
+        let order = &self.orders[node as usize];
+        RestingOrder {
+            order_id: order.order_id,
+            side: order.side,
+            price: order.price,
+            qty: order.qty,
+        }
+    }
+
+    // Appends `node` to the tail of the level at `price`, creating it if
+    // needed.
+    fn enqueue(
+        &mut self,
+        node: u32,
+        side: Side,
+        price: Price,
+        qty: Quantity,
+    ) -> Result<(), BookError> {
+        let ladder = self.ladder_mut(side);
+        let pos = match ladder.search(side, price) {
+            Ok(pos) => pos,
+            Err(pos) => {
+                ladder.insert(pos, price)?;
+                pos
+            }
+        };
+        let level = &mut ladder.levels[pos];
+        let tail = level.tail;
+        level.qty = level.qty + qty;
+        level.count += 1;
+        level.tail = node;
+        if tail == NIL {
+            level.head = node;
+        } else {
+            self.orders[tail as usize].next = node;
+        }
+        let order = &mut self.orders[node as usize];
+        order.prev = tail;
+        order.next = NIL;
+        Ok(())
+    }
+
+    // Takes `node` out of its level's FIFO, dropping the level once empty.
+    fn unlink(&mut self, node: u32) {
+        let OrderNode {
+            side,
+            price,
+            qty,
+            prev,
+            next,
+            ..
+        } = self.orders[node as usize];
+
+        if prev != NIL {
+            self.orders[prev as usize].next = next;
+        }
+        if next != NIL {
+            self.orders[next as usize].prev = prev;
+        }
+
+        let ladder = self.ladder_mut(side);
+        let pos = ladder.search(side, price).expect("order level");
+        let level = &mut ladder.levels[pos];
+        if level.head == node {
+            level.head = next;
+        }
+        if level.tail == node {
+            level.tail = prev;
+        }
+        level.qty = level.qty - qty;
+        level.count -= 1;
+        if level.count == 0 {
+            ladder.remove(pos);
+        }
+    }
+
+    fn reduce(&mut self, node: u32, by: Quantity) {
+        let order = &mut self.orders[node as usize];
+        order.qty = order.qty - by;
+        let (side, price) = (order.side, order.price);
+
+        let ladder = self.ladder_mut(side);
+        let pos = ladder.search(side, price).expect("order level");
+        ladder.levels[pos].qty = ladder.levels[pos].qty - by;
+    }
+
+    // Returns an unlinked node to the free list and drops its index entry.
+    fn release(&mut self, slot: usize, node: u32) {
+        self.remove_slot(slot);
+        self.orders[node as usize].next = self.free;
+        self.free = node;
+        self.live -= 1;
+    }
+
+    #[inline(always)]
+    fn home(&self, order_id: u64) -> usize {
+        (order_id.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> self.shift) as usize
+    }
+
+    // `Ok((slot, node))` if indexed, else the empty slot to insert at.
+    #[inline(always)]
+    fn find(&self, order_id: u64) -> Result<(usize, u32), usize> {
+        let mask = self.index.len() - 1;
+        let mut slot = self.home(order_id);
+        loop {
+            let node = self.index[slot];
+            if node == NIL {
+                return Err(slot);
+            }
+            if self.orders[node as usize].order_id == order_id {
+                return Ok((slot, node));
+            }
+            slot = (slot + 1) & mask;
+        }
+    }
+
+    // Backward-shift deletion: pulls later entries of the probe run into the
+    // hole so lookups never need tombstones.
+    fn remove_slot(&mut self, slot: usize) {
+        let mask = self.index.len() - 1;
+        let mut hole = slot;
+        let mut next = slot;
+        loop {
+            next = (next + 1) & mask;
+            let node = self.index[next];
+            if node == NIL {
+                break;
+            }
+            let home = self.home(self.orders[node as usize].order_id);
+            if next.wrapping_sub(home) & mask >= next.wrapping_sub(hole) & mask {
+                self.index[hole] = node;
+                hole = next;
+            }
+        }
+        self.index[hole] = NIL;
+    }
+}
+
+impl fmt::Debug for L3OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("L3OrderBook")
+            .field("orders", &self.live)
+            .field("capacity", &self.orders.len())
+            .field("bid_levels", &self.bids.len)
+            .field("ask_levels", &self.asks.len)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::DEPTH_10;
+
+    fn px(p: i64) -> Price {
+        Price::new(p, 0)
+    }
+
+    fn qty(q: i64) -> Quantity {
+        Quantity::new(q, 0)
+    }
+
+    fn ids(book: &L3OrderBook, side: Side, price: Price) -> Vec<u64> {
+        book.orders_at(side, price).map(|o| o.order_id).collect()
+    }
+
+    #[test]
+    fn test_fifo_and_l2_view() {
+        let mut book = L3OrderBook::new(16, 8);
+        book.add(1, Side::Buy, px(100), qty(10)).unwrap();
+        book.add(2, Side::Buy, px(100), qty(5)).unwrap();
+        book.add(3, Side::Buy, px(101), qty(7)).unwrap();
+        book.add(4, Side::Sell, px(102), qty(3)).unwrap();
+
+        assert_eq!(book.best_bid(), Some(px(101)));
+        assert_eq!(book.best_ask(), Some(px(102)));
+        assert_eq!(ids(&book, Side::Buy, px(100)), [1, 2]);
+
+        let l2 = book.to_l2::<DEPTH_10>();
+        assert_eq!(l2.bids().len(), 2);
+        assert_eq!(l2.bids()[1].price, px(100));
+        assert_eq!(l2.bids()[1].qty, qty(15));
+        assert_eq!(l2.bids()[1].order_count, 2);
+        assert_eq!(l2.asks()[0].order_count, 1);
+
+        assert_eq!(
+            book.add(1, Side::Sell, px(103), qty(1)),
+            Err(BookError::DuplicateOrder)
+        );
+        assert_eq!(
+            book.add(9, Side::Sell, px(103), qty(0)),
+            Err(BookError::InvalidQuantity)
+        );
+    }
+
+    #[test]
+    fn test_modify_priority() {
+        let mut book = L3OrderBook::new(16, 8);
+        for id in 1..=3 {
+            book.add(id, Side::Sell, px(100), qty(10)).unwrap();
+        }
+
+        // Size down keeps the spot, size up goes to the back.
+        book.modify(1, px(100), qty(4)).unwrap();
+        assert_eq!(ids(&book, Side::Sell, px(100)), [1, 2, 3]);
+        book.modify(2, px(100), qty(12)).unwrap();
+        assert_eq!(ids(&book, Side::Sell, px(100)), [1, 3, 2]);
+        assert_eq!(book.qty_at(Side::Sell, px(100)), qty(26));
+
+        book.modify(1, px(99), qty(4)).unwrap();
+        assert_eq!(book.best_ask(), Some(px(99)));
+        assert_eq!(ids(&book, Side::Sell, px(100)), [3, 2]);
+        assert_eq!(book.qty_at(Side::Sell, px(100)), qty(22));
+        assert_eq!(
+            book.modify(42, px(99), qty(1)),
+            Err(BookError::UnknownOrder)
+        );
+    }
+
+    #[test]
+    fn test_execute_and_delete() {
+        let mut book = L3OrderBook::new(4, 4);
+        book.add(1, Side::Buy, px(100), qty(10)).unwrap();
+        book.add(2, Side::Buy, px(100), qty(5)).unwrap();
+
+        assert_eq!(book.execute(1, qty(4)), Ok(qty(6)));
+        assert_eq!(book.execute(1, qty(7)), Err(BookError::InvalidQuantity));
+        assert_eq!(book.execute(1, qty(6)), Ok(qty(0)));
+        assert!(book.order(1).is_none());
+        assert_eq!(ids(&book, Side::Buy, px(100)), [2]);
+
+        let removed = book.delete(2).unwrap();
+        assert_eq!((removed.price, removed.qty), (px(100), qty(5)));
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.depth(Side::Buy), 0);
+        assert!(book.is_empty());
+        assert_eq!(book.delete(2), Err(BookError::UnknownOrder));
+    }
+
+    #[test]
+    fn test_queue_position() {
+        let mut book = L3OrderBook::new(16, 8);
+        book.add(1, Side::Buy, px(100), qty(10)).unwrap();
+        book.add(2, Side::Buy, px(100), qty(20)).unwrap();
+        book.add(7, Side::Buy, px(100), qty(5)).unwrap();
+        book.add(3, Side::Buy, px(100), qty(8)).unwrap();
+
+        let pos = book.queue_position(7).unwrap();
+        assert_eq!(pos.orders_ahead, 2);
+        assert_eq!(pos.qty_ahead, qty(30));
+        assert_eq!(pos.qty_behind, qty(8));
+
+        book.execute(1, qty(10)).unwrap();
+        book.delete(2).unwrap();
+        let pos = book.queue_position(7).unwrap();
+        assert_eq!((pos.orders_ahead, pos.qty_ahead), (0, qty(0)));
+        assert!(book.queue_position(99).is_none());
+    }
+
+    #[test]
+    fn test_pool_and_level_limits() {
+        let mut book = L3OrderBook::new(2, 1);
+        book.add(1, Side::Buy, px(100), qty(1)).unwrap();
+        assert_eq!(
+            book.add(2, Side::Buy, px(99), qty(1)),
+            Err(BookError::LevelsFull)
+        );
+        book.add(2, Side::Buy, px(100), qty(1)).unwrap();
+        assert_eq!(
+            book.add(3, Side::Buy, px(100), qty(1)),
+            Err(BookError::PoolFull)
+        );
+        assert_eq!(book.modify(1, px(99), qty(1)), Err(BookError::LevelsFull));
+        assert_eq!(ids(&book, Side::Buy, px(100)), [1, 2]);
+
+        // Freed nodes are reused.
+        book.delete(1).unwrap();
+        book.add(3, Side::Buy, px(100), qty(1)).unwrap();
+        assert_eq!(ids(&book, Side::Buy, px(100)), [2, 3]);
+    }
+
+    #[test]
+    fn test_index_survives_churn() {
+        let mut book = L3OrderBook::new(64, 16);
+        for round in 0..50u64 {
+            for i in 0..64u64 {
+                let id = round * 1_000 + i * 7;
+                book.add(id, Side::Sell, px(100 + (i % 8) as i64), qty(1))
+                    .unwrap();
+            }
+            for i in (0..64u64).rev() {
+                let id = round * 1_000 + i * 7;
+                assert!(book.order(id).is_some());
+                book.delete(id).unwrap();
+            }
+            assert!(book.is_empty());
+        }
+    }
+}
@@ -1,7 +1,14 @@
 pub mod core;
+pub mod l3_book;
 pub mod messages;
 pub mod order_book;
 pub mod pipeline;
+pub mod symbols;
 
-pub use messages::{MarketEvent, Order, PriceLevel, RejectReason, RiskDecision, Side, SignalEvent};
-pub use order_book::OrderBook;
+pub use l3_book::L3OrderBook;
+pub use messages::{
+    LevelAction, MarketEvent, Order, PriceLevel, RejectReason, RiskDecision, Side, SignalEvent,
+    Trace,
+};
+pub use order_book::{BboBook, Book10, Book50, BookSet, MirrorBook, OrderBook, Sweep};
+pub use symbols::{Instrument, SymbolTable};
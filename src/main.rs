@@ -1,115 +1,254 @@
-use hft_engine::core::{LatencyTracker, SpscQueue};
-use hft_engine::messages::{MarketEvent, RiskDecision, SignalEvent};
-use hft_engine::pipeline::{gateway, market_data, risk, strategy};
+use hft_engine::core::metrics::tracker;
+use hft_engine::core::{
+    Allocator, ClockSource, CpuTopology, Quantity, Stage, ThreadProfile, TscClock, signal,
+};
+use hft_engine::pipeline::Pipeline;
+use hft_engine::symbols::{Instrument, SymbolTable};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use hft_engine::core::StageCounts;
+#[cfg(unix)]
+use hft_engine::core::shm_metrics::{DEFAULT_METRICS_SEGMENT, MetricsSegment, StageSnapshot};
+
+#[cfg(unix)]
+type StageProbe = Box<dyn Fn() -> StageSnapshot + Send>;
+
+// Copies tracker snapshots into the shared-memory segment read by
+// `hft-monitor`, off the pipeline threads.
+#[cfg(unix)]
+fn spawn_metrics_publisher(
+    clock: &TscClock,
+    stages: Vec<(&'static str, StageProbe)>,
+    shutdown: Arc<AtomicBool>,
+) -> Option<thread::JoinHandle<()>> {
+    let names: Vec<&str> = stages.iter().map(|(name, _)| *name).collect();
+
+    let mut segment = match MetricsSegment::create(DEFAULT_METRICS_SEGMENT, &names, clock) {
+        Ok(segment) => segment,
+        Err(e) => {
+            println!(
+                "Warning: metrics segment /{} unavailable ({}), hft-monitor will not attach",
+                DEFAULT_METRICS_SEGMENT, e
+            );
+            return None;
+        }
+    };
+
+    println!("Publishing metrics to /{}", DEFAULT_METRICS_SEGMENT);
+
+    Some(thread::spawn(move || {
+        while !shutdown.load(Ordering::Relaxed) {
+            for (index, (_, probe)) in stages.iter().enumerate() {
+                segment.publish(index, &probe());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }))
+}
 
 fn main() {
     println!("=== HFT Engine - Phase 2 Demo ===\n");
     println!("Starting 4-thread pipeline with lock-free SPSC queues...\n");
 
-    let md_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(1024));
-    let strategy_to_risk = Arc::new(SpscQueue::<SignalEvent>::new(1024));
-    let risk_to_gateway = Arc::new(SpscQueue::<RiskDecision>::new(1024));
+    let clock = TscClock::global();
+    println!(
+        "Clock: {:?}, {:.3} cycles/ns",
+        clock.source(),
+        clock.cycles_per_ns()
+    );
 
-    let shutdown = Arc::new(AtomicBool::new(false));
-
-    let md_tracker = Arc::new(LatencyTracker::new());
-    let strategy_tracker = Arc::new(LatencyTracker::new());
-    let risk_tracker = Arc::new(LatencyTracker::new());
-    let gateway_tracker = Arc::new(LatencyTracker::new());
-
-    let shutdown1 = shutdown.clone();
-    let shutdown2 = shutdown.clone();
-    let shutdown3 = shutdown.clone();
-    let shutdown4 = shutdown.clone();
-
-    let md_queue = md_to_strategy.clone();
-    let strategy_in = md_to_strategy.clone();
-    let strategy_out = strategy_to_risk.clone();
-    let risk_in = strategy_to_risk.clone();
-    let risk_out = risk_to_gateway.clone();
-    let gateway_in = risk_to_gateway.clone();
-
-    let md_track = md_tracker.clone();
-    let strategy_track = strategy_tracker.clone();
-    let risk_track = risk_tracker.clone();
-    let gateway_track = gateway_tracker.clone();
-
-    println!("Spawning threads on CPUs 0-3...\n");
-
-    let md_thread = thread::spawn(move || {
-        market_data::run_market_data(
-            market_data::MarketDataConfig::default(),
-            md_queue,
-            shutdown1,
-            Some(md_track),
-        );
+    let topology = CpuTopology::detect().unwrap_or_else(|e| {
+        println!("Warning: CPU topology unavailable ({}), assuming flat", e);
+        CpuTopology::flat(hft_engine::core::thread::num_cpus())
     });
+    let placement = topology.plan();
+    print!("{}", placement);
+    if cfg!(target_arch = "x86_64") && clock.source() == ClockSource::Monotonic {
+        println!("Warning: TSC is not invariant, timing falls back to the monotonic clock");
+    }
+    println!();
 
-    let strategy_thread = thread::spawn(move || {
-        strategy::run_strategy(
-            strategy::StrategyConfig::default(),
-            strategy_in,
-            strategy_out,
-            shutdown2,
-            Some(strategy_track),
-        );
-    });
+    // Probe once here so a refused `mbind` or huge page mapping is reported
+    // instead of every allocation quietly falling back.
+    let mut allocator = Allocator::heap().huge_pages().on_node(placement.node());
+    while let Err(e) = allocator.try_alloc_with(1, |_| 0u8)
+        && let Some(next) = allocator.fallback()
+    {
+        println!("Warning: {:?} unavailable ({}), falling back", allocator, e);
+        allocator = next;
+    }
+    match (allocator.uses_huge_pages(), allocator.node()) {
+        (true, Some(node)) => println!("Queue and book memory: huge pages on NUMA node {}", node),
+        (true, None) => println!("Queue and book memory: huge pages, unbound"),
+        _ => println!("Queue and book memory: heap"),
+    }
 
-    let risk_thread = thread::spawn(move || {
-        risk::run_risk(
-            risk::RiskConfig::default(),
-            risk_in,
-            risk_out,
-            shutdown3,
-            Some(risk_track),
-        );
-    });
+    let sample_every = std::env::var("HFT_LATENCY_SAMPLE")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(1);
+    println!("Sampling 1 in {} messages for stage latency", sample_every);
+
+    // SCHED_FIFO is opt-in: a spinning FIFO thread sharing a CPU with the
+    // rest of the process would starve it.
+    let profile = match std::env::var("HFT_RT_PRIORITY")
+        .ok()
+        .and_then(|p| p.parse().ok())
+    {
+        Some(priority) => {
+            if !placement.is_ideal() {
+                println!("Warning: SCHED_FIFO requested on a degraded stage placement");
+            }
+            ThreadProfile::realtime(priority)
+        }
+        None => ThreadProfile::new()
+            .lock_memory(true)
+            .prefault_stack(ThreadProfile::DEFAULT_PREFAULT),
+    };
+
+    let mut symbols = SymbolTable::new();
+    for ticker in ["AAPL", "MSFT", "NVDA", "AMZN"] {
+        symbols
+            .insert(Instrument::new(ticker).lot_size(Quantity::new(1, 0)))
+            .expect("valid instrument");
+    }
+    println!("Trading {} instruments", symbols.len());
 
-    let gateway_thread = thread::spawn(move || {
-        gateway::run_gateway(
-            gateway::GatewayConfig::default(),
-            gateway_in,
-            shutdown4,
-            Some(gateway_track),
+    if let Err(e) = signal::install_shutdown_handler() {
+        println!(
+            "Warning: no SIGINT/SIGTERM handler ({}), Ctrl-C skips the drain",
+            e
         );
-    });
+    }
+
+    println!("Spawning pipeline threads...\n");
+
+    let pipeline = Pipeline::builder()
+        .placement(&placement)
+        .allocator(allocator)
+        .profile(profile)
+        .sample_every(sample_every)
+        .symbols(Arc::new(symbols))
+        .market_data_backpressure(true)
+        .spawn()
+        .expect("Failed to spawn pipeline");
+    let metrics = pipeline.metrics().clone();
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    #[cfg(unix)]
+    let metrics_thread = {
+        let mut stages: Vec<(&'static str, StageProbe)> = Stage::ALL
+            .into_iter()
+            .map(|stage| {
+                let metrics = metrics.clone();
+                let probe: StageProbe = Box::new(move || {
+                    StageSnapshot::from_tracker(tracker(stage), metrics.queue_depth(stage))
+                        .counts(metrics.counts(stage))
+                });
+                (stage.as_str(), probe)
+            })
+            .collect();
+        // Counted in orders that reached the wire.
+        let wire_metrics = metrics.clone();
+        stages.push((
+            "tick_to_wire",
+            Box::new(move || {
+                let sent = wire_metrics.counts(Stage::Gateway).sent;
+                StageSnapshot::from_tracker(&wire_metrics.trace_latency.tick_to_wire, 0).counts(
+                    StageCounts {
+                        processed: sent,
+                        sent,
+                        rejected: 0,
+                    },
+                )
+            }),
+        ));
+
+        spawn_metrics_publisher(clock, stages, shutdown.clone())
+    };
+
+    let metrics_addr =
+        std::env::var("HFT_METRICS_ADDR").unwrap_or_else(|_| "127.0.0.1:9184".to_string());
+    let exporter_thread = match metrics
+        .exporter(*clock)
+        .spawn(metrics_addr.as_str(), shutdown.clone())
+    {
+        Ok(handle) => {
+            println!(
+                "Serving Prometheus metrics on http://{}/metrics",
+                metrics_addr
+            );
+            Some(handle)
+        }
+        Err(e) => {
+            println!(
+                "Warning: metrics exporter disabled ({}: {})",
+                metrics_addr, e
+            );
+            None
+        }
+    };
+
+    println!("Pipeline running (Ctrl-C to stop)...\n");
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline && !signal::shutdown_requested() && !pipeline.is_stopping() {
+        thread::sleep(Duration::from_millis(50));
+    }
 
-    println!("Pipeline running...\n");
-    thread::sleep(Duration::from_secs(5));
+    println!("Shutting down: stopping market data, draining downstream stages...\n");
+    pipeline.shutdown();
+    print!("{}", pipeline.join());
 
-    println!("\nSignaling shutdown...\n");
     shutdown.store(true, Ordering::Relaxed);
 
-    md_thread.join().unwrap();
-    strategy_thread.join().unwrap();
-    risk_thread.join().unwrap();
-    gateway_thread.join().unwrap();
+    if let Some(exporter_thread) = exporter_thread {
+        exporter_thread.join().unwrap();
+    }
 
-    println!("\n=== Latency Statistics ===\n");
+    #[cfg(unix)]
+    if let Some(metrics_thread) = metrics_thread {
+        metrics_thread.join().unwrap();
+    }
+
+    if cfg!(not(feature = "metrics")) {
+        println!("\nBuilt without the `metrics` feature; no latency was recorded.");
+        println!("\n=== Phase 2 Demo Complete ===");
+        return;
+    }
 
-    let md_stats = md_tracker.stats().to_nanos(3.0);
-    println!("Market Data:  {}", md_stats);
+    println!("\n=== Latency Statistics ===\n");
 
-    let strategy_stats = strategy_tracker.stats().to_nanos(3.0);
-    println!("Strategy:     {}", strategy_stats);
+    for stage in Stage::ALL {
+        println!(
+            "{:<13} {}",
+            format!("{}:", stage.as_str()),
+            tracker(stage).stats().to_nanos(clock)
+        );
+    }
 
-    let risk_stats = risk_tracker.stats().to_nanos(3.0);
-    println!("Risk:         {}", risk_stats);
+    println!("\n=== Queue Residency ===\n");
 
-    let gateway_stats = gateway_tracker.stats().to_nanos(3.0);
-    println!("Gateway:      {}", gateway_stats);
+    let residency = [
+        ("MD -> Strategy:  ", &metrics.trace_latency.md_to_strategy),
+        ("Strategy -> Risk:", &metrics.trace_latency.strategy_to_risk),
+        ("Risk -> Gateway: ", &metrics.trace_latency.risk_to_gateway),
+    ];
+    for (name, tracker) in residency {
+        println!("{} {}", name, tracker.stats().to_nanos(clock));
+    }
 
-    let total_avg_ns =
-        md_stats.avg_ns + strategy_stats.avg_ns + risk_stats.avg_ns + gateway_stats.avg_ns;
+    let tick_to_wire = metrics.trace_latency.tick_to_wire.stats().to_nanos(clock);
 
-    println!("\nEnd-to-End:   {} ns average", total_avg_ns);
-    println!("Target:       < 1000 ns (1 µs)");
+    println!("\nTick-to-Wire:  {}", tick_to_wire);
+    println!("Target:        p99 < 1000 ns (1 µs)");
 
-    if total_avg_ns < 1000 {
+    if tick_to_wire.count > 0 && tick_to_wire.p99_ns < 1000 {
         println!("Target achieved!");
     } else {
         println!("Above target");
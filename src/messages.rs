@@ -1,6 +1,12 @@
 use crate::core::types::{Price, Quantity, Timestamp};
 
-pub const MAX_LEVELS: usize = 10;
+// Book depth presets for `OrderBook<DEPTH>` and `MarketEvent<DEPTH>`. Every
+// `BookUpdate` carries 2 x DEPTH cache-line levels, so pick the shallowest
+// one the strategies need.
+pub const BBO: usize = 1;
+pub const DEPTH_10: usize = 10;
+pub const DEPTH_50: usize = 50;
+pub const DEFAULT_DEPTH: usize = DEPTH_10;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -9,6 +15,16 @@ pub enum Side {
     Sell = 1,
 }
 
+// What a `LevelDelta` did to its price level. `Delete` always carries a
+// zero quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LevelAction {
+    New = 0,
+    Change = 1,
+    Delete = 2,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, align(64))]
 pub struct PriceLevel {
@@ -45,9 +61,12 @@ impl PriceLevel {
     }
 }
 
+// `repr(u8)` pins each variant's layout to a `repr(C)` struct led by the
+// tag, which is what lets `book_update_in` build a snapshot in place.
 #[derive(Debug, Clone, Copy)]
-#[repr(C, align(64))]
-pub enum MarketEvent {
+#[repr(u8, align(64))]
+#[allow(clippy::large_enum_variant)]
+pub enum MarketEvent<const DEPTH: usize = DEFAULT_DEPTH> {
     Tick {
         symbol: u32,
         price: Price,
@@ -63,21 +82,50 @@ pub enum MarketEvent {
         timestamp: Timestamp,
     },
 
-    BookUpdate {
+    // One level change. `seq` counts deltas per symbol from 1, so a
+    // consumer that sees a jump has missed one and needs a snapshot.
+    LevelDelta {
         symbol: u32,
-        bids: [PriceLevel; MAX_LEVELS],
-        asks: [PriceLevel; MAX_LEVELS],
+        side: Side,
+        price: Price,
+        qty: Quantity,
+        action: LevelAction,
+        seq: u64,
         timestamp: Timestamp,
     },
+
+    BookUpdate(BookSnapshot<DEPTH>) = BOOK_UPDATE_TAG,
 }
 
-impl MarketEvent {
+const BOOK_UPDATE_TAG: u8 = 3;
+
+// Full snapshot of both sides, reflecting every delta up to and including
+// `seq`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BookSnapshot<const DEPTH: usize = DEFAULT_DEPTH> {
+    pub symbol: u32,
+    pub bids: [PriceLevel; DEPTH],
+    pub asks: [PriceLevel; DEPTH],
+    pub seq: u64,
+    pub timestamp: Timestamp,
+}
+
+// The `repr(u8)` layout of the `BookUpdate` variant.
+#[repr(C)]
+struct BookUpdateRepr<const DEPTH: usize> {
+    tag: u8,
+    snapshot: BookSnapshot<DEPTH>,
+}
+
+impl<const DEPTH: usize> MarketEvent<DEPTH> {
     #[inline(always)]
     pub fn symbol(&self) -> u32 {
         match self {
             MarketEvent::Tick { symbol, .. } => *symbol,
             MarketEvent::Trade { symbol, .. } => *symbol,
-            MarketEvent::BookUpdate { symbol, .. } => *symbol,
+            MarketEvent::LevelDelta { symbol, .. } => *symbol,
+            MarketEvent::BookUpdate(snapshot) => snapshot.symbol,
         }
     }
 
@@ -86,7 +134,50 @@ impl MarketEvent {
         match self {
             MarketEvent::Tick { timestamp, .. } => *timestamp,
             MarketEvent::Trade { timestamp, .. } => *timestamp,
-            MarketEvent::BookUpdate { timestamp, .. } => *timestamp,
+            MarketEvent::LevelDelta { timestamp, .. } => *timestamp,
+            MarketEvent::BookUpdate(snapshot) => snapshot.timestamp,
+        }
+    }
+
+    // Tags `event` as a `BookUpdate` and hands back its payload, so the
+    // levels can be written straight into e.g. a reserved ring slot.
+    /// # Safety
+    ///
+    /// `event` must be valid for writes, and every field of the returned
+    /// snapshot must be written before the event is read.
+    #[inline(always)]
+    pub unsafe fn book_update_in(event: *mut Self) -> *mut BookSnapshot<DEPTH> {
+        let repr = event.cast::<BookUpdateRepr<DEPTH>>();
+        unsafe {
+            std::ptr::addr_of_mut!((*repr).tag).write(BOOK_UPDATE_TAG);
+            std::ptr::addr_of_mut!((*repr).snapshot)
+        }
+    }
+}
+
+// Hop timestamps stamped by each stage as a market data event travels to the
+// wire. `origin` is the market data timestamp; the rest are taken when a stage
+// dequeues its input and when it enqueues its output, so queue residency is
+// `*_in - previous *_out` rather than a sum of per-stage averages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Trace {
+    pub origin: Timestamp,
+    pub strategy_in: Timestamp,
+    pub strategy_out: Timestamp,
+    pub risk_in: Timestamp,
+    pub risk_out: Timestamp,
+}
+
+impl Trace {
+    #[inline(always)]
+    pub const fn from_origin(origin: Timestamp) -> Self {
+        Trace {
+            origin,
+            strategy_in: origin,
+            strategy_out: origin,
+            risk_in: origin,
+            risk_out: origin,
         }
     }
 }
@@ -95,26 +186,41 @@ impl MarketEvent {
 #[repr(C, align(64))]
 pub enum SignalEvent {
     Buy {
+        strategy_id: u32,
         symbol: u32,
         price: Price,
         qty: Quantity,
         timestamp: Timestamp,
+        trace: Trace,
     },
 
     Sell {
+        strategy_id: u32,
         symbol: u32,
         price: Price,
         qty: Quantity,
         timestamp: Timestamp,
+        trace: Trace,
     },
 
     Cancel {
+        strategy_id: u32,
         order_id: u64,
         timestamp: Timestamp,
+        trace: Trace,
     },
 }
 
 impl SignalEvent {
+    #[inline(always)]
+    pub fn strategy_id(&self) -> u32 {
+        match self {
+            SignalEvent::Buy { strategy_id, .. } => *strategy_id,
+            SignalEvent::Sell { strategy_id, .. } => *strategy_id,
+            SignalEvent::Cancel { strategy_id, .. } => *strategy_id,
+        }
+    }
+
     #[inline(always)]
     pub fn timestamp(&self) -> Timestamp {
         match self {
@@ -123,6 +229,24 @@ impl SignalEvent {
             SignalEvent::Cancel { timestamp, .. } => *timestamp,
         }
     }
+
+    #[inline(always)]
+    pub fn trace(&self) -> &Trace {
+        match self {
+            SignalEvent::Buy { trace, .. } => trace,
+            SignalEvent::Sell { trace, .. } => trace,
+            SignalEvent::Cancel { trace, .. } => trace,
+        }
+    }
+
+    #[inline(always)]
+    pub fn trace_mut(&mut self) -> &mut Trace {
+        match self {
+            SignalEvent::Buy { trace, .. } => trace,
+            SignalEvent::Sell { trace, .. } => trace,
+            SignalEvent::Cancel { trace, .. } => trace,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -134,7 +258,7 @@ pub struct Order {
     pub qty: Quantity,
     pub side: Side,
     pub timestamp: Timestamp,
-    _pad: [u8; 27],
+    pub trace: Trace,
 }
 
 impl Order {
@@ -154,9 +278,34 @@ impl Order {
             qty,
             side,
             timestamp,
-            _pad: [0; 27],
+            trace: Trace::from_origin(timestamp),
         }
     }
+
+    // Pulls resting order `id`. Carries no symbol, price or size; a zero
+    // quantity is what marks it as a cancel.
+    #[inline(always)]
+    pub const fn cancel(id: u64, timestamp: Timestamp) -> Self {
+        Order::new(
+            id,
+            0,
+            Price::new(0, 0),
+            Quantity::new(0, 0),
+            Side::Buy,
+            timestamp,
+        )
+    }
+
+    #[inline(always)]
+    pub const fn is_cancel(&self) -> bool {
+        self.qty.raw() == 0
+    }
+
+    #[inline(always)]
+    pub const fn with_trace(mut self, trace: Trace) -> Self {
+        self.trace = trace;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -168,6 +317,39 @@ pub enum RejectReason {
     InvalidQuantity = 3,
     UnknownSymbol = 4,
     InternalError = 5,
+    UnknownStrategy = 6,
+    KillSwitch = 7,
+    OutsideTouchBand = 8,
+}
+
+impl RejectReason {
+    pub const COUNT: usize = 9;
+
+    pub const ALL: [RejectReason; Self::COUNT] = [
+        RejectReason::PositionLimitExceeded,
+        RejectReason::RateLimitExceeded,
+        RejectReason::InvalidPrice,
+        RejectReason::InvalidQuantity,
+        RejectReason::UnknownSymbol,
+        RejectReason::InternalError,
+        RejectReason::UnknownStrategy,
+        RejectReason::KillSwitch,
+        RejectReason::OutsideTouchBand,
+    ];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            RejectReason::PositionLimitExceeded => "position_limit_exceeded",
+            RejectReason::RateLimitExceeded => "rate_limit_exceeded",
+            RejectReason::InvalidPrice => "invalid_price",
+            RejectReason::InvalidQuantity => "invalid_quantity",
+            RejectReason::UnknownSymbol => "unknown_symbol",
+            RejectReason::InternalError => "internal_error",
+            RejectReason::UnknownStrategy => "unknown_strategy",
+            RejectReason::KillSwitch => "kill_switch",
+            RejectReason::OutsideTouchBand => "outside_touch_band",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -181,6 +363,28 @@ pub enum RiskDecision {
     },
 }
 
+impl RiskDecision {
+    #[inline(always)]
+    pub fn trace(&self) -> &Trace {
+        match self {
+            RiskDecision::Approve(order) => &order.trace,
+            RiskDecision::Reject {
+                original_signal, ..
+            } => original_signal.trace(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn trace_mut(&mut self) -> &mut Trace {
+        match self {
+            RiskDecision::Approve(order) => &mut order.trace,
+            RiskDecision::Reject {
+                original_signal, ..
+            } => original_signal.trace_mut(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,9 +402,21 @@ mod tests {
         assert_eq!(level.order_count, 0);
     }
 
+    #[test]
+    fn test_book_update_size_scales_with_depth() {
+        use std::mem::size_of;
+
+        assert!(size_of::<MarketEvent<BBO>>() < size_of::<MarketEvent<DEPTH_10>>());
+        assert!(size_of::<MarketEvent<DEPTH_10>>() < size_of::<MarketEvent<DEPTH_50>>());
+        assert_eq!(
+            size_of::<MarketEvent>(),
+            size_of::<MarketEvent<DEFAULT_DEPTH>>()
+        );
+    }
+
     #[test]
     fn test_market_event_accessors() {
-        let tick = MarketEvent::Tick {
+        let tick: MarketEvent = MarketEvent::Tick {
             symbol: 123,
             price: Price::new(100, 0),
             qty: Quantity::new(10, 0),
@@ -212,6 +428,51 @@ mod tests {
         assert_eq!(tick.timestamp(), Timestamp::from_cycles(1000));
     }
 
+    #[test]
+    fn test_signal_event_accessors() {
+        let signal = SignalEvent::Cancel {
+            strategy_id: 3,
+            order_id: 42,
+            timestamp: Timestamp::from_cycles(1000),
+            trace: Trace::from_origin(Timestamp::from_cycles(900)),
+        };
+
+        assert_eq!(signal.strategy_id(), 3);
+        assert_eq!(signal.timestamp(), Timestamp::from_cycles(1000));
+        assert_eq!(signal.trace().origin, Timestamp::from_cycles(900));
+    }
+
+    #[test]
+    fn test_reject_reason_table() {
+        for (index, reason) in RejectReason::ALL.iter().enumerate() {
+            assert_eq!(*reason as usize, index);
+        }
+        assert_eq!(RejectReason::UnknownStrategy.as_str(), "unknown_strategy");
+    }
+
+    #[test]
+    fn test_risk_decision_trace() {
+        let mut trace = Trace::from_origin(Timestamp::from_cycles(100));
+        trace.risk_in = Timestamp::from_cycles(200);
+
+        let order = Order::new(
+            1,
+            123,
+            Price::new(100, 0),
+            Quantity::new(10, 0),
+            Side::Buy,
+            Timestamp::from_cycles(150),
+        )
+        .with_trace(trace);
+
+        let mut decision = RiskDecision::Approve(order);
+        decision.trace_mut().risk_out = Timestamp::from_cycles(250);
+
+        assert_eq!(decision.trace().origin, Timestamp::from_cycles(100));
+        assert_eq!(decision.trace().risk_in, Timestamp::from_cycles(200));
+        assert_eq!(decision.trace().risk_out, Timestamp::from_cycles(250));
+    }
+
     #[test]
     fn test_order_creation() {
         let order = Order::new(
@@ -229,4 +490,30 @@ mod tests {
         assert_eq!(std::mem::align_of::<Order>(), 64);
         assert!(std::mem::size_of::<Order>() <= 128);
     }
+
+    #[test]
+    fn test_book_update_in_place() {
+        let mut event = std::mem::MaybeUninit::<MarketEvent>::uninit();
+        let event = unsafe {
+            let snapshot = MarketEvent::book_update_in(event.as_mut_ptr());
+            std::ptr::addr_of_mut!((*snapshot).symbol).write(7);
+            std::ptr::addr_of_mut!((*snapshot).bids)
+                .write([PriceLevel::new(Price::new(100, 0), Quantity::new(5, 0)); DEFAULT_DEPTH]);
+            std::ptr::addr_of_mut!((*snapshot).asks).write([PriceLevel::empty(); DEFAULT_DEPTH]);
+            std::ptr::addr_of_mut!((*snapshot).seq).write(42);
+            std::ptr::addr_of_mut!((*snapshot).timestamp).write(Timestamp::from_cycles(9));
+            event.assume_init()
+        };
+
+        match event {
+            MarketEvent::BookUpdate(snapshot) => {
+                assert_eq!(snapshot.symbol, 7);
+                assert_eq!(snapshot.bids[0].price, Price::new(100, 0));
+                assert!(snapshot.asks[0].is_empty());
+                assert_eq!(snapshot.seq, 42);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(event.timestamp(), Timestamp::from_cycles(9));
+    }
 }
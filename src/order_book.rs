@@ -1,24 +1,39 @@
+use crate::core::memory::{Allocator, Buffer};
 use crate::core::types::{Price, Quantity};
-use crate::messages::{MAX_LEVELS, PriceLevel, Side};
+use crate::messages::{BBO, DEFAULT_DEPTH, DEPTH_10, DEPTH_50, LevelAction, PriceLevel, Side};
+use crate::symbols::SymbolTable;
 
+pub type BboBook = OrderBook<BBO>;
+pub type Book10 = OrderBook<DEPTH_10>;
+pub type Book50 = OrderBook<DEPTH_50>;
+
+// Price levels beyond DEPTH on either side are dropped. `OrderBook` with no
+// parameter is `OrderBook<DEFAULT_DEPTH>`, but in expression position the
+// depth must be spelled out or inferred (`Book10::new()`).
 #[derive(Debug, Clone)]
-pub struct OrderBook {
-    bids: [PriceLevel; MAX_LEVELS],
-    asks: [PriceLevel; MAX_LEVELS],
+pub struct OrderBook<const DEPTH: usize = DEFAULT_DEPTH> {
+    bids: [PriceLevel; DEPTH],
+    asks: [PriceLevel; DEPTH],
     bid_depth: usize,
     ask_depth: usize,
 }
 
-impl OrderBook {
-    pub fn new() -> Self {
+impl<const DEPTH: usize> OrderBook<DEPTH> {
+    pub const fn new() -> Self {
+        const { assert!(DEPTH > 0, "book depth must be at least 1") };
         OrderBook {
-            bids: [PriceLevel::empty(); MAX_LEVELS],
-            asks: [PriceLevel::empty(); MAX_LEVELS],
+            bids: [PriceLevel::empty(); DEPTH],
+            asks: [PriceLevel::empty(); DEPTH],
             bid_depth: 0,
             ask_depth: 0,
         }
     }
 
+    // Contiguous empty books, e.g. one per symbol, placed by `allocator`.
+    pub fn array_in(count: usize, allocator: &Allocator) -> Buffer<Self> {
+        allocator.alloc_with(count, |_| Self::new())
+    }
+
     #[inline(always)]
     pub fn best_bid(&self) -> Option<Price> {
         if self.bid_depth > 0 {
@@ -118,11 +133,11 @@ impl OrderBook {
 
     #[inline(always)]
     fn insert_bid(&mut self, pos: usize, price: Price, qty: Quantity) {
-        if self.bid_depth >= MAX_LEVELS {
-            if pos >= MAX_LEVELS {
+        if self.bid_depth >= DEPTH {
+            if pos >= DEPTH {
                 return;
             }
-            self.bid_depth = MAX_LEVELS - 1;
+            self.bid_depth = DEPTH - 1;
         }
 
         for i in (pos..self.bid_depth).rev() {
@@ -135,11 +150,11 @@ impl OrderBook {
 
     #[inline(always)]
     fn insert_ask(&mut self, pos: usize, price: Price, qty: Quantity) {
-        if self.ask_depth >= MAX_LEVELS {
-            if pos >= MAX_LEVELS {
+        if self.ask_depth >= DEPTH {
+            if pos >= DEPTH {
                 return;
             }
-            self.ask_depth = MAX_LEVELS - 1;
+            self.ask_depth = DEPTH - 1;
         }
 
         for i in (pos..self.ask_depth).rev() {
@@ -168,6 +183,14 @@ impl OrderBook {
         self.asks[self.ask_depth] = PriceLevel::empty();
     }
 
+    // Replaces both sides with a full snapshot, e.g. a `BookUpdate`. Each
+    // side ends at its first empty level.
+    #[inline(always)]
+    pub fn set_levels(&mut self, bids: &[PriceLevel], asks: &[PriceLevel]) {
+        self.bid_depth = copy_side(&mut self.bids, bids);
+        self.ask_depth = copy_side(&mut self.asks, asks);
+    }
+
     #[inline(always)]
     pub fn bids(&self) -> &[PriceLevel] {
         &self.bids[..self.bid_depth]
@@ -196,19 +219,334 @@ impl OrderBook {
     }
 }
 
-impl Default for OrderBook {
+// Scale of `OrderBook::imbalance`: +IMBALANCE_SCALE is all bids, -IMBALANCE_SCALE
+// all asks.
+pub const IMBALANCE_SCALE: i64 = 10_000;
+
+// A hypothetical market order walked through one side of the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sweep {
+    // Short of the requested size when the visible depth runs out.
+    pub filled: Quantity,
+    pub avg_price: Price,
+    pub worst_price: Price,
+    // `avg_price` against the touch, positive when worse for the order.
+    pub slippage: Price,
+}
+
+// Signals derived from the visible levels. All arithmetic is on raw fixed
+// point with i128 intermediates; weighted prices are truncated to the price
+// resolution. `side` is always the side of the order being considered, so
+// `Side::Buy` walks the asks.
+impl<const DEPTH: usize> OrderBook<DEPTH> {
+    // Touch prices weighted by the opposite side's size, so it leans towards
+    // the side more likely to be taken out next.
+    #[inline]
+    pub fn microprice(&self) -> Option<Price> {
+        let bid = self.bids().first()?;
+        let ask = self.asks().first()?;
+        let total = bid.qty.raw() as i128 + ask.qty.raw() as i128;
+        if total <= 0 {
+            return self.mid_price();
+        }
+        let weighted = bid.price.raw() as i128 * ask.qty.raw() as i128
+            + ask.price.raw() as i128 * bid.qty.raw() as i128;
+        Some(Price::from_raw((weighted / total) as i64))
+    }
+
+    // (bid size - ask size) / (bid size + ask size) over the best `levels` on
+    // each side, scaled by `IMBALANCE_SCALE`. `None` on an empty book.
+    #[inline]
+    pub fn imbalance(&self, levels: usize) -> Option<i64> {
+        let bid = total_qty(&self.bids[..levels.min(self.bid_depth)]);
+        let ask = total_qty(&self.asks[..levels.min(self.ask_depth)]);
+        let total = bid + ask;
+        if total <= 0 {
+            return None;
+        }
+        Some(((bid - ask) * IMBALANCE_SCALE as i128 / total) as i64)
+    }
+
+    // Size resting on `book_side` no more than `ticks` ticks behind its best
+    // level, best level included. Saturates at the largest `Quantity`.
+    #[inline]
+    pub fn depth_within(&self, book_side: Side, ticks: u32, tick_size: Price) -> Quantity {
+        let levels = self.side(book_side);
+        let Some(best) = levels.first() else {
+            return Quantity::from_raw(0);
+        };
+        let reach = tick_size.raw() as i128 * ticks as i128;
+        let within = levels.iter().take_while(|level| {
+            (level.price.raw() as i128 - best.price.raw() as i128).abs() <= reach
+        });
+        let total = total_qty(within);
+        Quantity::from_raw(i64::try_from(total).unwrap_or(i64::MAX))
+    }
+
+    // Size-weighted price of the best `levels` on `book_side`.
+    #[inline]
+    pub fn depth_weighted_price(&self, book_side: Side, levels: usize) -> Option<Price> {
+        let side = self.side(book_side);
+        weighted_price(&side[..levels.min(side.len())])
+    }
+
+    // Walks the opposite side until `qty` is filled or the levels run out.
+    // `None` for a non-positive size or an empty opposite side.
+    pub fn sweep(&self, side: Side, qty: Quantity) -> Option<Sweep> {
+        let levels = self.side(opposite(side));
+        let touch = levels.first()?.price;
+        if qty.raw() <= 0 {
+            return None;
+        }
+
+        let mut remaining = qty.raw();
+        let mut notional = 0i128;
+        let mut worst_price = touch;
+        for level in levels {
+            if remaining == 0 {
+                break;
+            }
+            let take = remaining.min(level.qty.raw());
+            notional += level.price.raw() as i128 * take as i128;
+            remaining -= take;
+            worst_price = level.price;
+        }
+
+        let filled = qty.raw() - remaining;
+        if filled == 0 {
+            return None;
+        }
+        let avg_price = Price::from_raw((notional / filled as i128) as i64);
+        let slippage = match side {
+            Side::Buy => avg_price - touch,
+            Side::Sell => touch - avg_price,
+        };
+        Some(Sweep {
+            filled: Quantity::from_raw(filled),
+            avg_price,
+            worst_price,
+            slippage,
+        })
+    }
+
+    // Average fill price for the full `qty`, or `None` if the visible depth
+    // can't absorb it.
+    #[inline]
+    pub fn vwap_to_size(&self, side: Side, qty: Quantity) -> Option<Price> {
+        self.sweep(side, qty)
+            .filter(|sweep| sweep.filled == qty)
+            .map(|sweep| sweep.avg_price)
+    }
+
+    // Expected slippage of a market order of `qty` against the touch.
+    #[inline]
+    pub fn slippage(&self, side: Side, qty: Quantity) -> Option<Price> {
+        self.sweep(side, qty).map(|sweep| sweep.slippage)
+    }
+
+    #[inline(always)]
+    fn side(&self, book_side: Side) -> &[PriceLevel] {
+        match book_side {
+            Side::Buy => self.bids(),
+            Side::Sell => self.asks(),
+        }
+    }
+}
+
+#[inline(always)]
+fn opposite(side: Side) -> Side {
+    match side {
+        Side::Buy => Side::Sell,
+        Side::Sell => Side::Buy,
+    }
+}
+
+#[inline(always)]
+fn total_qty<'a>(levels: impl IntoIterator<Item = &'a PriceLevel>) -> i128 {
+    levels
+        .into_iter()
+        .map(|level| level.qty.raw() as i128)
+        .sum()
+}
+
+#[inline(always)]
+fn weighted_price(levels: &[PriceLevel]) -> Option<Price> {
+    let mut notional = 0i128;
+    let mut total = 0i128;
+    for level in levels {
+        notional += level.price.raw() as i128 * level.qty.raw() as i128;
+        total += level.qty.raw() as i128;
+    }
+    if total <= 0 {
+        return None;
+    }
+    Some(Price::from_raw((notional / total) as i64))
+}
+
+impl<const DEPTH: usize> Default for OrderBook<DEPTH> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[inline(always)]
+fn copy_side<const DEPTH: usize>(side: &mut [PriceLevel; DEPTH], levels: &[PriceLevel]) -> usize {
+    let depth = levels
+        .iter()
+        .take(DEPTH)
+        .position(PriceLevel::is_empty)
+        .unwrap_or(levels.len().min(DEPTH));
+
+    side[..depth].copy_from_slice(&levels[..depth]);
+    side[depth..].fill(PriceLevel::empty());
+    depth
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaResult {
+    Applied,
+    // Already reflected by an earlier delta or snapshot.
+    Stale,
+    // A sequence number was skipped; the mirror is out of sync from here.
+    Gap,
+    // Still out of sync, waiting on a `BookUpdate`.
+    AwaitingSnapshot,
+}
+
+// Consumer-side copy of the market data stage's book for one symbol, kept
+// exact by applying `LevelDelta`s in sequence. It starts out of sync and
+// only becomes usable after its first snapshot; a skipped sequence number
+// puts it back out of sync until the next one.
+#[derive(Debug, Clone)]
+pub struct MirrorBook<const DEPTH: usize = DEFAULT_DEPTH> {
+    book: OrderBook<DEPTH>,
+    seq: u64,
+    synced: bool,
+}
+
+impl<const DEPTH: usize> MirrorBook<DEPTH> {
+    pub const fn new() -> Self {
+        MirrorBook {
+            book: OrderBook::new(),
+            seq: 0,
+            synced: false,
+        }
+    }
+
+    pub fn array_in(count: usize, allocator: &Allocator) -> Buffer<Self> {
+        allocator.alloc_with(count, |_| Self::new())
+    }
+
+    #[inline(always)]
+    pub fn book(&self) -> &OrderBook<DEPTH> {
+        &self.book
+    }
+
+    #[inline(always)]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    #[inline(always)]
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    #[inline(always)]
+    pub fn apply_delta(
+        &mut self,
+        side: Side,
+        price: Price,
+        qty: Quantity,
+        action: LevelAction,
+        seq: u64,
+    ) -> DeltaResult {
+        if !self.synced {
+            return DeltaResult::AwaitingSnapshot;
+        }
+        if seq <= self.seq {
+            return DeltaResult::Stale;
+        }
+        if seq != self.seq + 1 {
+            self.synced = false;
+            return DeltaResult::Gap;
+        }
+
+        let qty = match action {
+            LevelAction::Delete => Quantity::from_raw(0),
+            LevelAction::New | LevelAction::Change => qty,
+        };
+        self.book.update_level(side, price, qty);
+        self.seq = seq;
+        DeltaResult::Applied
+    }
+
+    // Resyncs from a snapshot unless the mirror is already at or past `seq`.
+    // Returns whether it was applied.
+    pub fn apply_snapshot(&mut self, bids: &[PriceLevel], asks: &[PriceLevel], seq: u64) -> bool {
+        if self.synced && seq <= self.seq {
+            return false;
+        }
+        self.book.set_levels(bids, asks);
+        self.seq = seq;
+        self.synced = true;
+        true
+    }
+}
+
+impl<const DEPTH: usize> Default for MirrorBook<DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// One book per symbol ID in a single allocation. Capacity is fixed when the
+// set is created; IDs at or past it have no book.
+pub struct BookSet<const DEPTH: usize = DEFAULT_DEPTH> {
+    books: Buffer<OrderBook<DEPTH>>,
+}
+
+impl<const DEPTH: usize> BookSet<DEPTH> {
+    pub fn new(capacity: usize, allocator: &Allocator) -> Self {
+        BookSet {
+            books: OrderBook::array_in(capacity, allocator),
+        }
+    }
+
+    pub fn for_symbols(symbols: &SymbolTable, allocator: &Allocator) -> Self {
+        Self::new(symbols.len(), allocator)
+    }
+
+    #[inline(always)]
+    pub fn get(&self, symbol: u32) -> Option<&OrderBook<DEPTH>> {
+        self.books.get(symbol as usize)
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self, symbol: u32) -> Option<&mut OrderBook<DEPTH>> {
+        self.books.get_mut(symbol as usize)
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &OrderBook<DEPTH>)> {
+        self.books
+            .iter()
+            .enumerate()
+            .map(|(symbol, book)| (symbol as u32, book))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_empty_book() {
-        let book = OrderBook::new();
+        let book = Book10::new();
         assert_eq!(book.best_bid(), None);
         assert_eq!(book.best_ask(), None);
         assert_eq!(book.spread(), None);
@@ -217,7 +555,7 @@ mod tests {
 
     #[test]
     fn test_insert_bids() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         assert_eq!(book.best_bid(), Some(Price::new(100, 0)));
@@ -236,7 +574,7 @@ mod tests {
 
     #[test]
     fn test_insert_asks() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Sell, Price::new(102, 0), Quantity::new(10, 0));
         assert_eq!(book.best_ask(), Some(Price::new(102, 0)));
@@ -253,7 +591,7 @@ mod tests {
 
     #[test]
     fn test_update_existing() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         assert_eq!(book.bids()[0].qty, Quantity::new(10, 0));
@@ -265,7 +603,7 @@ mod tests {
 
     #[test]
     fn test_remove_level() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         book.update_level(Side::Buy, Price::new(99, 0), Quantity::new(5, 0));
@@ -278,7 +616,7 @@ mod tests {
 
     #[test]
     fn test_spread() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         book.update_level(Side::Sell, Price::new(102, 0), Quantity::new(10, 0));
@@ -288,7 +626,7 @@ mod tests {
 
     #[test]
     fn test_mid_price() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         book.update_level(Side::Sell, Price::new(102, 0), Quantity::new(10, 0));
@@ -298,13 +636,13 @@ mod tests {
 
     #[test]
     fn test_max_depth() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         for i in 0..15 {
             book.update_level(Side::Buy, Price::new(100 - i, 0), Quantity::new(10, 0));
         }
 
-        assert_eq!(book.bid_depth, MAX_LEVELS);
+        assert_eq!(book.bid_depth, DEPTH_10);
         assert!(book.is_sorted());
 
         assert_eq!(book.best_bid(), Some(Price::new(100, 0)));
@@ -312,7 +650,7 @@ mod tests {
 
     #[test]
     fn test_complex_operations() {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
         book.update_level(Side::Buy, Price::new(99, 0), Quantity::new(15, 0));
@@ -332,4 +670,192 @@ mod tests {
         assert_eq!(book.bid_depth, 3);
         assert!(book.is_sorted());
     }
+
+    #[test]
+    fn test_set_levels_stops_at_empty() {
+        let mut book = Book10::new();
+        book.update_level(Side::Buy, Price::new(90, 0), Quantity::new(1, 0));
+
+        let mut bids = [PriceLevel::empty(); DEPTH_10];
+        bids[0] = PriceLevel::new(Price::new(100, 0), Quantity::new(5, 0));
+        bids[1] = PriceLevel::new(Price::new(99, 0), Quantity::new(7, 0));
+        book.set_levels(&bids, &[]);
+
+        assert_eq!(book.bids().len(), 2);
+        assert_eq!(book.best_bid(), Some(Price::new(100, 0)));
+        assert_eq!(book.best_ask(), None);
+        assert!(book.is_sorted());
+    }
+
+    #[test]
+    fn test_book_set_indexed_by_symbol() {
+        let mut books = BookSet::<DEPTH_10>::new(3, &Allocator::heap());
+
+        books
+            .get_mut(2)
+            .unwrap()
+            .update_level(Side::Sell, Price::new(101, 0), Quantity::new(4, 0));
+
+        assert_eq!(books.capacity(), 3);
+        assert_eq!(books.get(2).unwrap().best_ask(), Some(Price::new(101, 0)));
+        assert_eq!(books.get(0).unwrap().best_ask(), None);
+        assert!(books.get(3).is_none());
+    }
+
+    #[test]
+    fn test_mirror_follows_deltas_and_resyncs() {
+        let mut source = Book10::new();
+        let mut mirror = MirrorBook::<DEPTH_10>::new();
+        let (bid, ask) = (Price::new(100, 0), Price::new(101, 0));
+
+        let delta = |mirror: &mut MirrorBook<DEPTH_10>, side, price, qty, seq| {
+            mirror.apply_delta(side, price, Quantity::new(qty, 0), LevelAction::New, seq)
+        };
+
+        assert_eq!(
+            delta(&mut mirror, Side::Buy, bid, 10, 1),
+            DeltaResult::AwaitingSnapshot
+        );
+        assert!(mirror.apply_snapshot(source.bids(), source.asks(), 0));
+
+        source.update_level(Side::Buy, bid, Quantity::new(10, 0));
+        source.update_level(Side::Sell, ask, Quantity::new(5, 0));
+        assert_eq!(
+            delta(&mut mirror, Side::Buy, bid, 10, 1),
+            DeltaResult::Applied
+        );
+        assert_eq!(
+            delta(&mut mirror, Side::Sell, ask, 5, 2),
+            DeltaResult::Applied
+        );
+        assert_eq!(
+            delta(&mut mirror, Side::Sell, ask, 5, 2),
+            DeltaResult::Stale
+        );
+        assert_eq!(mirror.book().bids(), source.bids());
+        assert_eq!(mirror.book().asks(), source.asks());
+
+        assert_eq!(
+            mirror.apply_delta(Side::Sell, ask, Quantity::new(0, 0), LevelAction::Delete, 3),
+            DeltaResult::Applied
+        );
+        assert_eq!(mirror.book().best_ask(), None);
+
+        // Seq 4 is lost; nothing applies until a snapshot at or past it.
+        source.update_level(Side::Sell, ask, Quantity::new(7, 0));
+        source.update_level(Side::Buy, bid, Quantity::new(3, 0));
+        assert_eq!(delta(&mut mirror, Side::Buy, bid, 3, 5), DeltaResult::Gap);
+        assert!(!mirror.is_synced());
+        assert!(mirror.apply_snapshot(source.bids(), source.asks(), 5));
+        assert!(!mirror.apply_snapshot(source.bids(), source.asks(), 5));
+        assert_eq!(mirror.book().best_ask(), Some(ask));
+        assert_eq!(mirror.book().bids()[0].qty, Quantity::new(3, 0));
+        assert_eq!(
+            delta(&mut mirror, Side::Buy, bid, 4, 6),
+            DeltaResult::Applied
+        );
+    }
+
+    fn analytics_book() -> Book10 {
+        let mut book = Book10::new();
+        for (price, qty) in [(100, 10), (99, 20), (98, 30)] {
+            book.update_level(Side::Buy, Price::new(price, 0), Quantity::new(qty, 0));
+        }
+        for (price, qty) in [(101, 5), (102, 15), (104, 40)] {
+            book.update_level(Side::Sell, Price::new(price, 0), Quantity::new(qty, 0));
+        }
+        book
+    }
+
+    #[test]
+    fn test_microprice_and_imbalance() {
+        let book = analytics_book();
+
+        // (100 x 5 + 101 x 10) / 15
+        assert_eq!(book.microprice(), Some(Price::from_raw(1_006_666)));
+        assert_eq!(book.imbalance(1), Some(IMBALANCE_SCALE / 3));
+        assert_eq!(book.imbalance(3), Some(0));
+        assert_eq!(Book10::new().imbalance(5), None);
+        assert_eq!(Book10::new().microprice(), None);
+    }
+
+    #[test]
+    fn test_depth_analytics() {
+        let book = analytics_book();
+        let tick = Price::new(1, 0);
+
+        assert_eq!(book.depth_within(Side::Sell, 2, tick), Quantity::new(20, 0));
+        assert_eq!(book.depth_within(Side::Buy, 0, tick), Quantity::new(10, 0));
+        assert_eq!(book.depth_within(Side::Buy, 5, tick), Quantity::new(60, 0));
+        assert_eq!(
+            Book10::new().depth_within(Side::Buy, 5, tick),
+            Quantity::new(0, 0)
+        );
+        assert_eq!(
+            book.depth_weighted_price(Side::Buy, 2),
+            Some(Price::from_raw(993_333))
+        );
+
+        let mut deep = Book10::new();
+        for price in [100, 99] {
+            deep.update_level(
+                Side::Buy,
+                Price::new(price, 0),
+                Quantity::from_raw(i64::MAX),
+            );
+        }
+        assert_eq!(
+            deep.depth_within(Side::Buy, 1, tick),
+            Quantity::from_raw(i64::MAX)
+        );
+        assert_eq!(deep.imbalance(2), Some(IMBALANCE_SCALE));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let book = analytics_book();
+
+        let buy = book.sweep(Side::Buy, Quantity::new(10, 0)).unwrap();
+        assert_eq!(buy.filled, Quantity::new(10, 0));
+        assert_eq!(buy.avg_price, Price::new(101, 5000));
+        assert_eq!(buy.worst_price, Price::new(102, 0));
+        assert_eq!(buy.slippage, Price::new(0, 5000));
+
+        // 10 @ 100, 20 @ 99, 5 @ 98
+        let sell = book.sweep(Side::Sell, Quantity::new(35, 0)).unwrap();
+        assert_eq!(sell.avg_price, Price::from_raw(991_428));
+        assert_eq!(sell.slippage, Price::from_raw(8_572));
+        assert_eq!(
+            book.slippage(Side::Sell, Quantity::new(35, 0)),
+            Some(sell.slippage)
+        );
+
+        let too_big = book.sweep(Side::Buy, Quantity::new(100, 0)).unwrap();
+        assert_eq!(too_big.filled, Quantity::new(60, 0));
+        assert_eq!(too_big.worst_price, Price::new(104, 0));
+        assert_eq!(book.vwap_to_size(Side::Buy, Quantity::new(100, 0)), None);
+        assert_eq!(
+            book.vwap_to_size(Side::Buy, Quantity::new(5, 0)),
+            Some(Price::new(101, 0))
+        );
+        assert_eq!(book.sweep(Side::Buy, Quantity::new(0, 0)), None);
+    }
+
+    #[test]
+    fn test_depth_presets_truncate() {
+        let mut bbo = BboBook::new();
+        let mut deep = Book50::new();
+
+        for i in 0..60 {
+            let price = Price::new(100 - i, 0);
+            bbo.update_level(Side::Buy, price, Quantity::new(1, 0));
+            deep.update_level(Side::Buy, price, Quantity::new(1, 0));
+        }
+
+        assert_eq!(bbo.bids().len(), BBO);
+        assert_eq!(bbo.best_bid(), Some(Price::new(100, 0)));
+        assert_eq!(deep.bids().len(), DEPTH_50);
+        assert_eq!(deep.bids()[DEPTH_50 - 1].price, Price::new(51, 0));
+        assert!(deep.is_sorted());
+    }
 }
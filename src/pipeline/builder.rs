@@ -0,0 +1,653 @@
+use crate::core::metrics::{LatencyStats, tracker};
+use crate::core::{
+    Allocator, FanIn, Placement, QueueDepth, Stage, StageCounts, ThreadProfile, TscClock, Waiter,
+    broadcast, spsc,
+};
+use crate::messages::{DEFAULT_DEPTH, MarketEvent, RiskDecision, SignalEvent};
+use crate::pipeline::exporter::Exporter;
+use crate::pipeline::gateway::{self, GatewayConfig, GatewayCounters, TraceLatency};
+use crate::pipeline::market_data::{self, MarketDataConfig, MarketDataCounters, SnapshotRequests};
+use crate::pipeline::report::StageReport;
+use crate::pipeline::risk::{self, KillSwitch, MAX_STRATEGIES, RiskConfig, RiskCounters};
+use crate::pipeline::strategy::{self, StrategyConfig, StrategyCounters};
+use crate::pipeline::watchdog::{Alert, ExitGuard, Heartbeat, Watchdog, WatchdogHandle};
+use crate::symbols::SymbolTable;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+
+pub struct Pipeline;
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+}
+
+// Wires market data -> strategies -> risk -> gateway. Pipeline-wide settings
+// (`wait`, `profile`, `sample_every`, `placement`, `symbols`) override the
+// matching fields of the stage configs when they are set.
+pub struct PipelineBuilder<const DEPTH: usize = DEFAULT_DEPTH> {
+    market_data: MarketDataConfig,
+    strategies: Vec<StrategyConfig>,
+    risk: RiskConfig,
+    gateway: GatewayConfig,
+    market_data_capacity: usize,
+    market_data_backpressure: bool,
+    signal_capacity: usize,
+    decision_capacity: usize,
+    allocator: Allocator,
+    wait: Option<Waiter>,
+    profile: Option<ThreadProfile>,
+    sample_every: Option<u32>,
+    cpus: Option<[usize; Stage::COUNT]>,
+    symbols: Option<Arc<SymbolTable>>,
+    watchdog: Watchdog,
+}
+
+impl<const DEPTH: usize> Default for PipelineBuilder<DEPTH> {
+    fn default() -> Self {
+        PipelineBuilder {
+            market_data: MarketDataConfig::default(),
+            strategies: Vec::new(),
+            risk: RiskConfig::default(),
+            gateway: GatewayConfig::default(),
+            market_data_capacity: 1024,
+            market_data_backpressure: false,
+            signal_capacity: 1024,
+            decision_capacity: 1024,
+            allocator: Allocator::heap(),
+            wait: None,
+            profile: None,
+            sample_every: None,
+            cpus: None,
+            symbols: None,
+            watchdog: Watchdog::new(),
+        }
+    }
+}
+
+impl<const DEPTH: usize> PipelineBuilder<DEPTH> {
+    // Book depth carried by `BookUpdate` and kept by every stage's books,
+    // e.g. `.depth::<BBO>()` when strategies only look at the touch.
+    pub fn depth<const D: usize>(self) -> PipelineBuilder<D> {
+        PipelineBuilder {
+            market_data: self.market_data,
+            strategies: self.strategies,
+            risk: self.risk,
+            gateway: self.gateway,
+            market_data_capacity: self.market_data_capacity,
+            market_data_backpressure: self.market_data_backpressure,
+            signal_capacity: self.signal_capacity,
+            decision_capacity: self.decision_capacity,
+            allocator: self.allocator,
+            wait: self.wait,
+            profile: self.profile,
+            sample_every: self.sample_every,
+            cpus: self.cpus,
+            symbols: self.symbols,
+            watchdog: self.watchdog,
+        }
+    }
+
+    pub fn market_data(mut self, config: MarketDataConfig) -> Self {
+        self.market_data = config;
+        self
+    }
+
+    // Each call adds a strategy with its own subscriber and signal queue.
+    // Without any, one default strategy is spawned.
+    pub fn strategy(mut self, config: StrategyConfig) -> Self {
+        self.strategies.push(config);
+        self
+    }
+
+    pub fn risk(mut self, config: RiskConfig) -> Self {
+        self.risk = config;
+        self
+    }
+
+    pub fn gateway(mut self, config: GatewayConfig) -> Self {
+        self.gateway = config;
+        self
+    }
+
+    pub fn market_data_capacity(mut self, capacity: usize) -> Self {
+        self.market_data_capacity = capacity;
+        self
+    }
+
+    // Subscribes strategies and risk as gated readers, so market data waits
+    // for the slowest of them instead of lapping it. Off by default: a feed
+    // handler would rather drop to a snapshot than fall behind the venue.
+    pub fn market_data_backpressure(mut self, enabled: bool) -> Self {
+        self.market_data_backpressure = enabled;
+        self
+    }
+
+    pub fn signal_capacity(mut self, capacity: usize) -> Self {
+        self.signal_capacity = capacity;
+        self
+    }
+
+    pub fn decision_capacity(mut self, capacity: usize) -> Self {
+        self.decision_capacity = capacity;
+        self
+    }
+
+    pub fn allocator(mut self, allocator: Allocator) -> Self {
+        self.allocator = allocator;
+        self
+    }
+
+    pub fn wait(mut self, wait: Waiter) -> Self {
+        self.wait = Some(wait);
+        self
+    }
+
+    pub fn profile(mut self, profile: ThreadProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn sample_every(mut self, sample_every: u32) -> Self {
+        self.sample_every = Some(sample_every);
+        self
+    }
+
+    // Extra strategies beyond the first keep the CPU from their own config.
+    pub fn placement(mut self, placement: &Placement) -> Self {
+        self.cpus = Some(Stage::ALL.map(|stage| placement.cpu(stage)));
+        self
+    }
+
+    // One symbol table for market data, every strategy and risk.
+    pub fn symbols(mut self, symbols: Arc<SymbolTable>) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    // Replaces the default supervisor. There is always one: without it a
+    // panicked stage would leave its producer blocked in `push_supervised`
+    // and `join` would never return.
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    pub fn spawn(mut self) -> io::Result<PipelineHandle> {
+        if self.strategies.is_empty() {
+            self.strategies.push(StrategyConfig::default());
+        }
+        if self.strategies.len() > MAX_STRATEGIES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("at most {} strategies are supported", MAX_STRATEGIES),
+            ));
+        }
+        self.apply_overrides();
+
+        // One request table for market data, every strategy and risk, however
+        // their configs were built.
+        let snapshots = Arc::new(SnapshotRequests::new(self.market_data.symbols.len()));
+        self.market_data.snapshots = snapshots.clone();
+        for config in &mut self.strategies {
+            config.snapshots = snapshots.clone();
+        }
+        self.risk.snapshots = snapshots.clone();
+
+        // Likewise one kill switch per pipeline, so a fault here never stops
+        // orders from another pipeline in the same process.
+        let kill_switch = Arc::new(KillSwitch::new());
+        self.risk.kill_switch = kill_switch.clone();
+        self.gateway.kill_switch = kill_switch.clone();
+
+        let mut md_queue =
+            broadcast::channel_in::<MarketEvent<DEPTH>>(self.market_data_capacity, &self.allocator);
+        let backpressure = self.market_data_backpressure;
+        let subscribe = |queue: &mut broadcast::Publisher<MarketEvent<DEPTH>>| {
+            if backpressure {
+                queue.subscribe_gated()
+            } else {
+                queue.subscribe()
+            }
+        };
+        let (risk_out, gateway_in) =
+            spsc::channel_in::<RiskDecision>(self.decision_capacity, &self.allocator);
+
+        let mut strategy_outs = Vec::with_capacity(self.strategies.len());
+        let mut risk_ins = Vec::with_capacity(self.strategies.len());
+        for _ in &self.strategies {
+            let (tx, rx) = spsc::channel_in::<SignalEvent>(self.signal_capacity, &self.allocator);
+            strategy_outs.push(tx);
+            risk_ins.push(rx);
+        }
+
+        let mut metrics = PipelineMetrics {
+            market_data: Arc::new(MarketDataCounters::default()),
+            strategies: self
+                .strategies
+                .iter()
+                .map(|config| (config.strategy_id, Arc::new(StrategyCounters::default())))
+                .collect(),
+            risk: Arc::new(RiskCounters::default()),
+            gateway: Arc::new(GatewayCounters::default()),
+            trace_latency: self.gateway.trace_latency.clone(),
+            heartbeats: Vec::new(),
+            signal_queues: strategy_outs.iter().map(spsc::Producer::depth).collect(),
+            decision_queue: risk_out.depth(),
+        };
+
+        // Consumers are spawned before their producers, so a failed spawn
+        // never leaves an upstream stage blocked on a queue nobody drains.
+        let mut stages = StageThreads::default();
+
+        let gateway_stop = Arc::new(AtomicBool::new(false));
+        let heartbeat = self.gateway.heartbeat.clone();
+        let (config, stop, counters) =
+            (self.gateway, gateway_stop.clone(), metrics.gateway.clone());
+        stages.spawn("hft-gateway", gateway_stop, heartbeat, move || {
+            gateway::run_gateway(config, gateway_in, stop, Some(counters))
+        })?;
+
+        let risk_stop = Arc::new(AtomicBool::new(false));
+        let heartbeat = self.risk.heartbeat.clone();
+        let (config, stop, counters) = (self.risk, risk_stop.clone(), metrics.risk.clone());
+        let market = subscribe(&mut md_queue);
+        stages.spawn("hft-risk", risk_stop, heartbeat, move || {
+            risk::run_risk(
+                config,
+                market,
+                FanIn::new(risk_ins),
+                risk_out,
+                stop,
+                Some(counters),
+            )
+        })?;
+
+        for ((config, output), (_, counters)) in self
+            .strategies
+            .into_iter()
+            .zip(strategy_outs)
+            .zip(metrics.strategies.iter().cloned())
+        {
+            let input = subscribe(&mut md_queue);
+            let strategy_stop = Arc::new(AtomicBool::new(false));
+            let stop = strategy_stop.clone();
+            let name = format!("hft-strategy-{}", config.strategy_id);
+            let heartbeat = config.heartbeat.clone();
+            stages.spawn(&name, strategy_stop, heartbeat, move || {
+                strategy::run_strategy(config, input, output, stop, Some(counters))
+            })?;
+        }
+
+        let md_stop = Arc::new(AtomicBool::new(false));
+        let heartbeat = self.market_data.heartbeat.clone();
+        let (config, stop, counters) = (
+            self.market_data,
+            md_stop.clone(),
+            metrics.market_data.clone(),
+        );
+        stages.spawn("hft-market-data", md_stop, heartbeat, move || {
+            market_data::run_market_data(config, md_queue, stop, Some(counters))
+        })?;
+
+        // Shutdown and join run upstream-first.
+        stages.threads.reverse();
+        metrics.heartbeats = stages
+            .threads
+            .iter()
+            .map(|stage| (stage.name.clone(), stage.heartbeat.clone()))
+            .collect();
+
+        let supervised = stages
+            .threads
+            .iter()
+            .map(|stage| {
+                (
+                    stage.name.clone(),
+                    stage.heartbeat.clone(),
+                    stage.stop.clone(),
+                )
+            })
+            .collect();
+        let watchdog = match self
+            .watchdog
+            .kill_switch(kill_switch.clone())
+            .spawn(supervised)
+        {
+            Ok(handle) => handle,
+            Err(e) => {
+                stages.stop_source();
+                stages.join_upstream_first();
+                return Err(e);
+            }
+        };
+
+        Ok(PipelineHandle {
+            stages: Some(stages),
+            watchdog: Some(watchdog),
+            metrics,
+            snapshots,
+            kill_switch,
+        })
+    }
+
+    fn apply_overrides(&mut self) {
+        if let Some(wait) = self.wait {
+            for config in &mut self.strategies {
+                config.wait = wait;
+            }
+            self.market_data.wait = wait;
+            self.risk.wait = wait;
+            self.gateway.wait = wait;
+        }
+
+        if let Some(ref profile) = self.profile {
+            self.market_data.profile = profile.clone();
+            for config in &mut self.strategies {
+                config.profile = profile.clone();
+            }
+            self.risk.profile = profile.clone();
+            self.gateway.profile = profile.clone();
+        }
+
+        if let Some(sample_every) = self.sample_every {
+            self.market_data.sample_every = sample_every;
+            for config in &mut self.strategies {
+                config.sample_every = sample_every;
+            }
+            self.risk.sample_every = sample_every;
+            self.gateway.sample_every = sample_every;
+        }
+
+        if let Some(cpus) = self.cpus {
+            self.market_data.cpu_id = cpus[Stage::MarketData as usize];
+            self.strategies[0].cpu_id = cpus[Stage::Strategy as usize];
+            self.risk.cpu_id = cpus[Stage::Risk as usize];
+            self.gateway.cpu_id = cpus[Stage::Gateway as usize];
+        }
+
+        if let Some(ref symbols) = self.symbols {
+            self.market_data.symbols = symbols.clone();
+            for config in &mut self.strategies {
+                config.symbols = symbols.clone();
+            }
+            self.risk.symbols = symbols.clone();
+        }
+
+        self.market_data.allocator = self.allocator;
+        for config in &mut self.strategies {
+            config.allocator = self.allocator;
+        }
+        self.risk.allocator = self.allocator;
+    }
+}
+
+struct StageThread {
+    name: String,
+    stop: Arc<AtomicBool>,
+    heartbeat: Arc<Heartbeat>,
+    handle: thread::JoinHandle<StageReport>,
+}
+
+#[derive(Default)]
+struct StageThreads {
+    threads: Vec<StageThread>,
+}
+
+impl StageThreads {
+    fn spawn(
+        &mut self,
+        name: &str,
+        stop: Arc<AtomicBool>,
+        heartbeat: Arc<Heartbeat>,
+        run: impl FnOnce() -> StageReport + Send + 'static,
+    ) -> io::Result<()> {
+        let guard = ExitGuard(heartbeat.clone());
+        let spawned = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let _guard = guard;
+                run()
+            });
+
+        match spawned {
+            Ok(handle) => {
+                self.threads.push(StageThread {
+                    name: name.to_string(),
+                    stop,
+                    heartbeat,
+                    handle,
+                });
+                Ok(())
+            }
+            Err(e) => {
+                // Everything spawned so far is downstream of `name`.
+                self.threads.reverse();
+                self.stop_source();
+                self.join_upstream_first();
+                Err(e)
+            }
+        }
+    }
+
+    fn stop_source(&self) {
+        if let Some(stage) = self.threads.first() {
+            stage.stop.store(true, Ordering::Release);
+            stage.handle.thread().unpark();
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.threads
+            .first()
+            .is_some_and(|stage| stage.stop.load(Ordering::Acquire))
+    }
+
+    // The first stage stops by itself (or via `stop_source`); every later
+    // stage is only told to stop once its producer has exited, so it can
+    // drain what was left in its queue. Returns the reports and the names of
+    // stages that panicked instead of reporting.
+    fn join_upstream_first(&mut self) -> (Vec<StageReport>, Vec<String>) {
+        let mut reports = Vec::with_capacity(self.threads.len());
+        let mut panicked = Vec::new();
+
+        for (i, stage) in self.threads.drain(..).enumerate() {
+            if i > 0 {
+                // Parked stages only notice the flag once woken.
+                stage.stop.store(true, Ordering::Release);
+                stage.handle.thread().unpark();
+            }
+            match stage.handle.join() {
+                Ok(report) => reports.push(report),
+                Err(_) => panicked.push(stage.name),
+            }
+        }
+
+        (reports, panicked)
+    }
+}
+
+// Live view of a running pipeline: per-stage counters, queue fill levels and
+// the stage latency trackers. Cheap to clone into a monitoring thread.
+#[derive(Clone)]
+pub struct PipelineMetrics {
+    pub market_data: Arc<MarketDataCounters>,
+    pub strategies: Vec<(u32, Arc<StrategyCounters>)>,
+    pub risk: Arc<RiskCounters>,
+    pub gateway: Arc<GatewayCounters>,
+    // Queue residency and tick-to-wire of this pipeline's sampled traces.
+    pub trace_latency: Arc<TraceLatency>,
+    // Thread name and heartbeat of every stage, in pipeline order.
+    pub heartbeats: Vec<(String, Arc<Heartbeat>)>,
+    signal_queues: Vec<QueueDepth<SignalEvent>>,
+    decision_queue: QueueDepth<RiskDecision>,
+}
+
+impl PipelineMetrics {
+    // Messages waiting in front of `stage`. Strategies read from the
+    // broadcast ring, which has no backlog to measure.
+    pub fn queue_depth(&self, stage: Stage) -> usize {
+        match stage {
+            Stage::MarketData | Stage::Strategy => 0,
+            Stage::Risk => self.signal_queues.iter().map(QueueDepth::len).sum(),
+            Stage::Gateway => self.decision_queue.len(),
+        }
+    }
+
+    pub fn latency(&self, stage: Stage) -> LatencyStats {
+        tracker(stage).stats()
+    }
+
+    // Strategies are summed; market data counts each tick as processed and
+    // every delta or snapshot it published as sent.
+    pub fn counts(&self, stage: Stage) -> StageCounts {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        match stage {
+            Stage::MarketData => StageCounts {
+                processed: load(&self.market_data.ticks),
+                sent: load(&self.market_data.ticks) + load(&self.market_data.snapshots),
+                rejected: 0,
+            },
+            Stage::Strategy => StageCounts {
+                processed: self.strategies.iter().map(|(_, c)| load(&c.events)).sum(),
+                sent: self.strategies.iter().map(|(_, c)| load(&c.signals)).sum(),
+                rejected: 0,
+            },
+            Stage::Risk => StageCounts {
+                processed: load(&self.risk.signals),
+                sent: load(&self.risk.approved),
+                rejected: load(&self.risk.rejected),
+            },
+            Stage::Gateway => StageCounts {
+                processed: load(&self.gateway.decisions),
+                sent: load(&self.gateway.sent),
+                rejected: load(&self.gateway.rejected),
+            },
+        }
+    }
+
+    pub fn exporter(&self, clock: TscClock) -> Exporter {
+        let mut exporter = Stage::ALL
+            .iter()
+            .fold(Exporter::new(clock), |exporter, &stage| {
+                exporter.stage(stage.as_str(), tracker(stage))
+            })
+            .trace(self.trace_latency.clone());
+
+        for ((strategy_id, counters), depth) in self.strategies.iter().zip(&self.signal_queues) {
+            let name = format!("strategy_{}_to_risk", strategy_id);
+            exporter = exporter
+                .queue(name, depth.clone())
+                .strategy(*strategy_id, counters.clone());
+        }
+
+        exporter
+            .queue("risk_to_gateway", self.decision_queue.clone())
+            .market_data(self.market_data.clone())
+            .risk(self.risk.clone())
+            .gateway(self.gateway.clone())
+    }
+}
+
+pub struct PipelineHandle {
+    stages: Option<StageThreads>,
+    watchdog: Option<WatchdogHandle>,
+    metrics: PipelineMetrics,
+    snapshots: Arc<SnapshotRequests>,
+    kill_switch: Arc<KillSwitch>,
+}
+
+impl PipelineHandle {
+    #[inline]
+    pub fn metrics(&self) -> &PipelineMetrics {
+        &self.metrics
+    }
+
+    // Has market data follow its next delta for `symbol` with a full
+    // `BookUpdate`.
+    pub fn request_snapshot(&self, symbol: u32) {
+        self.snapshots.request(symbol);
+    }
+
+    // This pipeline's kill switch, shared by risk, gateway and the watchdog.
+    #[inline]
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+
+    // Stops market data. Downstream stages keep running until `join` has
+    // let each of them drain its input.
+    pub fn shutdown(&self) {
+        if let Some(ref stages) = self.stages {
+            stages.stop_source();
+        }
+    }
+
+    // True once shutdown has begun, whether from `shutdown` or the watchdog.
+    pub fn is_stopping(&self) -> bool {
+        self.stages.as_ref().is_some_and(StageThreads::is_stopping)
+    }
+
+    // Waits for market data to stop (on its own or after `shutdown`), then
+    // stops and joins every downstream stage in pipeline order. The watchdog
+    // keeps supervising until the last stage has exited.
+    pub fn join(mut self) -> PipelineReport {
+        let mut stages = self.stages.take().expect("pipeline already joined");
+        let (stages, panicked) = stages.join_upstream_first();
+        PipelineReport {
+            stages,
+            panicked,
+            alerts: self
+                .watchdog
+                .take()
+                .map(WatchdogHandle::join)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Drop for PipelineHandle {
+    fn drop(&mut self) {
+        if let Some(mut stages) = self.stages.take() {
+            stages.stop_source();
+            stages.join_upstream_first();
+        }
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.join();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PipelineReport {
+    pub stages: Vec<StageReport>,
+    pub panicked: Vec<String>,
+    pub alerts: Vec<Alert>,
+}
+
+impl PipelineReport {
+    pub fn stage(&self, stage: Stage) -> impl Iterator<Item = &StageReport> {
+        self.stages
+            .iter()
+            .filter(move |report| report.stage == stage)
+    }
+}
+
+impl fmt::Display for PipelineReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.stages {
+            writeln!(f, "{}", report)?;
+        }
+        for name in &self.panicked {
+            writeln!(f, "[{}] panicked", name)?;
+        }
+        for alert in &self.alerts {
+            writeln!(f, "Watchdog: {}", alert)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,466 @@
+use crate::core::{LatencyTracker, QueueDepth, TscClock};
+use crate::messages::RejectReason;
+use crate::pipeline::gateway::{GatewayCounters, TraceLatency};
+use crate::pipeline::market_data::MarketDataCounters;
+use crate::pipeline::risk::RiskCounters;
+use crate::pipeline::strategy::StrategyCounters;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+type QueueProbe = Box<dyn Fn() -> (usize, usize) + Send + Sync>;
+
+// Scrape requests carry no body; anything past this without a blank line is
+// not a request we want to keep reading.
+const MAX_REQUEST: usize = 8192;
+
+// Prometheus text exposition of the engine's trackers and counters. Scrapes
+// are served from a dedicated thread over a blocking listener; the pipeline
+// threads only ever store into the atomics this reads.
+pub struct Exporter {
+    clock: TscClock,
+    stages: Vec<(&'static str, &'static LatencyTracker)>,
+    trace: Option<Arc<TraceLatency>>,
+    queues: Vec<(Cow<'static, str>, QueueProbe)>,
+    market_data: Option<Arc<MarketDataCounters>>,
+    strategies: Vec<(u32, Arc<StrategyCounters>)>,
+    risk: Option<Arc<RiskCounters>>,
+    gateway: Option<Arc<GatewayCounters>>,
+}
+
+impl Exporter {
+    pub fn new(clock: TscClock) -> Self {
+        Exporter {
+            clock,
+            stages: Vec::new(),
+            trace: None,
+            queues: Vec::new(),
+            market_data: None,
+            strategies: Vec::new(),
+            risk: None,
+            gateway: None,
+        }
+    }
+
+    pub fn stage(mut self, name: &'static str, tracker: &'static LatencyTracker) -> Self {
+        self.stages.push((name, tracker));
+        self
+    }
+
+    // Exported as the `tick_to_wire` stage.
+    pub fn trace(mut self, latency: Arc<TraceLatency>) -> Self {
+        self.trace = Some(latency);
+        self
+    }
+
+    fn stages(&self) -> impl Iterator<Item = (&'static str, &LatencyTracker)> {
+        self.stages
+            .iter()
+            .map(|&(stage, tracker)| (stage, tracker))
+            .chain(
+                self.trace
+                    .iter()
+                    .map(|latency| ("tick_to_wire", &latency.tick_to_wire)),
+            )
+    }
+
+    pub fn queue<T: Send + 'static>(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        depth: QueueDepth<T>,
+    ) -> Self {
+        self.queues.push((
+            name.into(),
+            Box::new(move || (depth.len(), depth.capacity())),
+        ));
+        self
+    }
+
+    pub fn market_data(mut self, counters: Arc<MarketDataCounters>) -> Self {
+        self.market_data = Some(counters);
+        self
+    }
+
+    pub fn strategy(mut self, strategy_id: u32, counters: Arc<StrategyCounters>) -> Self {
+        self.strategies.push((strategy_id, counters));
+        self
+    }
+
+    pub fn risk(mut self, counters: Arc<RiskCounters>) -> Self {
+        self.risk = Some(counters);
+        self
+    }
+
+    pub fn gateway(mut self, counters: Arc<GatewayCounters>) -> Self {
+        self.gateway = Some(counters);
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(4096);
+
+        if self.stages().next().is_some() {
+            header(
+                &mut out,
+                "hft_stage_latency_ns",
+                "summary",
+                "Per-stage processing latency in nanoseconds.",
+            );
+            for (stage, tracker) in self.stages() {
+                let stats = tracker.stats();
+                let ns = |cycles: u64| self.clock.cycles_to_nanos(cycles);
+
+                for (quantile, value) in [
+                    ("0.5", stats.p50),
+                    ("0.9", stats.p90),
+                    ("0.99", stats.p99),
+                    ("0.999", stats.p999),
+                    ("0.9999", stats.p9999),
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "hft_stage_latency_ns{{stage=\"{}\",quantile=\"{}\"}} {}",
+                        stage,
+                        quantile,
+                        ns(value)
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "hft_stage_latency_ns_sum{{stage=\"{}\"}} {}",
+                    stage,
+                    ns(stats.sum)
+                );
+                let _ = writeln!(
+                    out,
+                    "hft_stage_latency_ns_count{{stage=\"{}\"}} {}",
+                    stage, stats.count
+                );
+            }
+
+            header(
+                &mut out,
+                "hft_stage_latency_max_ns",
+                "gauge",
+                "Worst per-stage processing latency since start in nanoseconds.",
+            );
+            for (stage, tracker) in self.stages() {
+                let _ = writeln!(
+                    out,
+                    "hft_stage_latency_max_ns{{stage=\"{}\"}} {}",
+                    stage,
+                    self.clock.cycles_to_nanos(tracker.stats().max)
+                );
+            }
+        }
+
+        if !self.queues.is_empty() {
+            header(
+                &mut out,
+                "hft_queue_depth",
+                "gauge",
+                "Messages waiting in an inter-stage queue.",
+            );
+            for (queue, probe) in &self.queues {
+                let _ = writeln!(out, "hft_queue_depth{{queue=\"{}\"}} {}", queue, probe().0);
+            }
+
+            header(
+                &mut out,
+                "hft_queue_capacity",
+                "gauge",
+                "Capacity of an inter-stage queue.",
+            );
+            for (queue, probe) in &self.queues {
+                let _ = writeln!(
+                    out,
+                    "hft_queue_capacity{{queue=\"{}\"}} {}",
+                    queue,
+                    probe().1
+                );
+            }
+        }
+
+        if let Some(ref md) = self.market_data {
+            counter(
+                &mut out,
+                "hft_market_data_ticks_total",
+                "Market data ticks processed.",
+                &md.ticks,
+            );
+            counter(
+                &mut out,
+                "hft_market_data_snapshots_total",
+                "Full book snapshots published, at startup or on request.",
+                &md.snapshots,
+            );
+        }
+
+        if !self.strategies.is_empty() {
+            for (name, help, field) in [
+                (
+                    "hft_strategy_events_total",
+                    "Market events processed by a strategy.",
+                    (|c: &StrategyCounters| &c.events) as fn(&StrategyCounters) -> &AtomicU64,
+                ),
+                (
+                    "hft_strategy_signals_total",
+                    "Signals emitted by a strategy.",
+                    |c: &StrategyCounters| &c.signals,
+                ),
+                (
+                    "hft_strategy_overruns_total",
+                    "Market events a strategy missed after being lapped.",
+                    |c: &StrategyCounters| &c.overruns,
+                ),
+                (
+                    "hft_strategy_book_gaps_total",
+                    "Book delta sequence gaps that forced a snapshot request.",
+                    |c: &StrategyCounters| &c.gaps,
+                ),
+            ] {
+                header(&mut out, name, "counter", help);
+                for (strategy_id, counters) in &self.strategies {
+                    let _ = writeln!(
+                        out,
+                        "{}{{strategy=\"{}\"}} {}",
+                        name,
+                        strategy_id,
+                        field(counters).load(Ordering::Relaxed)
+                    );
+                }
+            }
+        }
+
+        if let Some(ref risk) = self.risk {
+            counter(
+                &mut out,
+                "hft_risk_signals_total",
+                "Signals evaluated by the risk stage.",
+                &risk.signals,
+            );
+            counter(
+                &mut out,
+                "hft_risk_approved_total",
+                "Signals approved by the risk stage.",
+                &risk.approved,
+            );
+            counter(
+                &mut out,
+                "hft_risk_rejected_total",
+                "Signals rejected by the risk stage.",
+                &risk.rejected,
+            );
+
+            header(
+                &mut out,
+                "hft_risk_rejects_total",
+                "counter",
+                "Risk rejects by reason.",
+            );
+            for reason in RejectReason::ALL {
+                let _ = writeln!(
+                    out,
+                    "hft_risk_rejects_total{{reason=\"{}\"}} {}",
+                    reason.as_str(),
+                    risk.rejects_by_reason[reason as usize].load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        if let Some(ref gateway) = self.gateway {
+            counter(
+                &mut out,
+                "hft_gateway_decisions_total",
+                "Risk decisions consumed by the gateway.",
+                &gateway.decisions,
+            );
+            counter(
+                &mut out,
+                "hft_gateway_orders_sent_total",
+                "Orders sent by the gateway.",
+                &gateway.sent,
+            );
+            counter(
+                &mut out,
+                "hft_gateway_rejected_total",
+                "Rejected decisions seen by the gateway.",
+                &gateway.rejected,
+            );
+
+            header(
+                &mut out,
+                "hft_gateway_rejects_total",
+                "counter",
+                "Gateway rejects by reason.",
+            );
+            for reason in RejectReason::ALL {
+                let _ = writeln!(
+                    out,
+                    "hft_gateway_rejects_total{{reason=\"{}\"}} {}",
+                    reason.as_str(),
+                    gateway.rejects_by_reason[reason as usize].load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        out
+    }
+
+    pub fn spawn(
+        self,
+        addr: impl ToSocketAddrs,
+        shutdown: Arc<AtomicBool>,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        self.serve_on(TcpListener::bind(addr)?, shutdown)
+    }
+
+    // Serves scrapes from an already bound listener, e.g. one on port 0 whose
+    // address the caller needs before the thread starts.
+    pub fn serve_on(
+        self,
+        listener: TcpListener,
+        shutdown: Arc<AtomicBool>,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        listener.set_nonblocking(true)?;
+
+        Ok(thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = self.serve(stream);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        }))
+    }
+
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let mut request = Vec::with_capacity(1024);
+        let mut chunk = [0u8; 1024];
+        let complete = loop {
+            if request.windows(4).any(|w| w == b"\r\n\r\n") {
+                break true;
+            }
+            if request.len() >= MAX_REQUEST {
+                break false;
+            }
+            let len = stream.read(&mut chunk)?;
+            if len == 0 {
+                break false;
+            }
+            request.extend_from_slice(&chunk[..len]);
+        };
+        let request = std::str::from_utf8(&request).unwrap_or("");
+
+        let path = request.split_whitespace().nth(1).unwrap_or("");
+        let (status, body) = if !complete {
+            ("400 Bad Request", String::from("bad request\n"))
+        } else if request.starts_with("GET ") && path == "/metrics" {
+            ("200 OK", self.render())
+        } else {
+            ("404 Not Found", String::from("not found\n"))
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::spsc;
+
+    #[test]
+    fn test_render_exposition() {
+        static TRACKER: LatencyTracker = LatencyTracker::new();
+        TRACKER.record(100);
+
+        let (mut tx, _rx) = spsc::channel::<u32>(8);
+        tx.push(1).unwrap();
+
+        let risk = Arc::new(RiskCounters::default());
+        risk.rejected.store(2, Ordering::Relaxed);
+        risk.rejects_by_reason[RejectReason::RateLimitExceeded as usize]
+            .store(2, Ordering::Relaxed);
+
+        let exporter = Exporter::new(TscClock::monotonic())
+            .stage("risk", &TRACKER)
+            .trace(Arc::new(TraceLatency::new()))
+            .queue("strategy_to_risk", tx.depth())
+            .strategy(3, Arc::new(StrategyCounters::default()))
+            .risk(risk);
+
+        let text = exporter.render();
+        assert!(text.contains("# TYPE hft_stage_latency_ns summary"));
+        assert!(text.contains("hft_stage_latency_ns_count{stage=\"risk\"} 1"));
+        assert!(text.contains("hft_stage_latency_ns_count{stage=\"tick_to_wire\"} 0"));
+        assert!(text.contains("hft_queue_depth{queue=\"strategy_to_risk\"} 1"));
+        assert!(text.contains("hft_queue_capacity{queue=\"strategy_to_risk\"} 8"));
+        assert!(text.contains("hft_strategy_signals_total{strategy=\"3\"} 0"));
+        assert!(text.contains("hft_risk_rejected_total 2"));
+        assert!(text.contains("hft_risk_rejects_total{reason=\"rate_limit_exceeded\"} 2"));
+        assert!(text.contains("hft_risk_rejects_total{reason=\"invalid_price\"} 0"));
+    }
+
+    #[test]
+    fn test_serves_metrics_over_tcp() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let gateway = Arc::new(GatewayCounters::default());
+        gateway.sent.store(5, Ordering::Relaxed);
+        gateway.rejects_by_reason[RejectReason::KillSwitch as usize].store(1, Ordering::Relaxed);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = Exporter::new(TscClock::monotonic())
+            .gateway(gateway)
+            .serve_on(listener, shutdown.clone())
+            .unwrap();
+
+        // The request arrives in two segments; the headers must be read whole.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        stream.write_all(b"Host: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("hft_gateway_orders_sent_total 5"));
+        assert!(response.contains("hft_gateway_rejects_total{reason=\"kill_switch\"} 1"));
+
+        shutdown.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
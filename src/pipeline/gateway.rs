@@ -1,63 +1,196 @@
-use crate::core::{LatencyTracker, SpscQueue, pin_to_cpu, rdtsc};
-use crate::messages::{Order, RiskDecision};
+use crate::core::types::Timestamp;
+use crate::core::{
+    Consumer, LatencyTracker, Parker, Stage, StageRecorder, ThreadProfile, WaitStrategy, Waiter,
+    latency_start, rdtsc,
+};
+use crate::messages::{Order, RejectReason, RiskDecision, Trace};
+use crate::pipeline::report::StageReport;
+use crate::pipeline::risk::KillSwitch;
+use crate::pipeline::watchdog::Heartbeat;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub struct GatewayConfig {
     pub cpu_id: usize,
+    pub wait: Waiter,
+    pub sample_every: u32,
+    pub profile: ThreadProfile,
+    pub kill_switch: Arc<KillSwitch>,
+    pub heartbeat: Arc<Heartbeat>,
+    // Where the gateway records the hops of sampled traces; the pipeline
+    // exposes it through `PipelineMetrics::trace_latency`.
+    pub trace_latency: Arc<TraceLatency>,
 }
 
 impl Default for GatewayConfig {
     fn default() -> Self {
-        GatewayConfig { cpu_id: 3 }
+        GatewayConfig {
+            cpu_id: 3,
+            wait: Waiter::busy_spin(),
+            sample_every: 1,
+            profile: ThreadProfile::new(),
+            kill_switch: Arc::default(),
+            heartbeat: Arc::default(),
+            trace_latency: Arc::default(),
+        }
+    }
+}
+
+pub struct TraceLatency {
+    pub md_to_strategy: LatencyTracker,
+    pub strategy_to_risk: LatencyTracker,
+    pub risk_to_gateway: LatencyTracker,
+    pub tick_to_wire: LatencyTracker,
+}
+
+impl TraceLatency {
+    pub const fn new() -> Self {
+        TraceLatency {
+            md_to_strategy: LatencyTracker::new(),
+            strategy_to_risk: LatencyTracker::new(),
+            risk_to_gateway: LatencyTracker::new(),
+            tick_to_wire: LatencyTracker::new(),
+        }
     }
+
+    #[inline(always)]
+    pub fn record_queues(&self, trace: &Trace, gateway_in: Timestamp) {
+        self.md_to_strategy
+            .record(hop(trace.origin, trace.strategy_in));
+        self.strategy_to_risk
+            .record(hop(trace.strategy_out, trace.risk_in));
+        self.risk_to_gateway.record(hop(trace.risk_out, gateway_in));
+    }
+
+    #[inline(always)]
+    pub fn record_wire(&self, trace: &Trace, wire: Timestamp) {
+        self.tick_to_wire.record(hop(trace.origin, wire));
+    }
+}
+
+// Stamps come from different cores; saturate rather than wrap if their TSCs
+// are a few cycles apart.
+#[inline(always)]
+fn hop(from: Timestamp, to: Timestamp) -> u64 {
+    to.cycles().saturating_sub(from.cycles())
+}
+
+impl Default for TraceLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GatewayCounters {
+    pub decisions: AtomicU64,
+    pub sent: AtomicU64,
+    pub rejected: AtomicU64,
+    pub rejects_by_reason: [AtomicU64; RejectReason::COUNT],
 }
 
 pub fn run_gateway(
     config: GatewayConfig,
-    input_queue: Arc<SpscQueue<RiskDecision>>,
+    mut input_queue: Consumer<RiskDecision>,
     shutdown: Arc<AtomicBool>,
-    tracker: Option<Arc<LatencyTracker>>,
-) {
-    pin_to_cpu(config.cpu_id).expect("Failed to pin gateway thread");
+    counters: Option<Arc<GatewayCounters>>,
+) -> StageReport {
+    let profile = config
+        .profile
+        .clone()
+        .name("hft-gateway")
+        .cpu(config.cpu_id)
+        .apply()
+        .expect("Failed to apply gateway thread profile");
 
     let mut decision_count = 0u64;
     let mut sent_count = 0u64;
     let mut rejected_count = 0u64;
+    let mut reject_counts = [0u64; RejectReason::COUNT];
+    let mut drained_count = 0u64;
+    let mut waiter = config.wait;
+    let mut recorder = StageRecorder::new(Stage::Gateway, config.sample_every);
+    let heartbeat = config.heartbeat.clone();
+    if waiter.parks() {
+        input_queue.register_parker(Parker::current());
+    }
 
-    println!("[Gateway] Thread started on CPU {}", config.cpu_id);
+    loop {
+        let draining = shutdown.load(Ordering::Acquire);
+        heartbeat.beat();
 
-    while !shutdown.load(Ordering::Relaxed) {
         if let Some(decision) = input_queue.pop() {
-            let start = rdtsc();
+            let start = latency_start();
+            let sampled = recorder.sample();
+            heartbeat.progress(rdtsc());
+            waiter.reset();
 
             decision_count += 1;
+            if draining {
+                drained_count += 1;
+            }
+
+            if sampled {
+                config.trace_latency.record_queues(decision.trace(), start);
+            }
+
+            let rejected = match decision {
+                // Cancels still go out: they are how resting orders get
+                // pulled once the switch trips.
+                RiskDecision::Approve(order)
+                    if !order.is_cancel() && config.kill_switch.is_tripped() =>
+                {
+                    Some(RejectReason::KillSwitch)
+                }
 
-            match decision {
                 RiskDecision::Approve(order) => {
                     send_order_mock(&order);
                     sent_count += 1;
+
+                    if sampled {
+                        config
+                            .trace_latency
+                            .record_wire(&order.trace, latency_start());
+                    }
+                    None
                 }
 
-                RiskDecision::Reject { reason, .. } => {
-                    rejected_count += 1;
-                    let _ = reason;
+                RiskDecision::Reject { reason, .. } => Some(reason),
+            };
+
+            if let Some(reason) = rejected {
+                rejected_count += 1;
+                reject_counts[reason as usize] += 1;
+
+                if let Some(ref counters) = counters {
+                    counters.rejects_by_reason[reason as usize]
+                        .store(reject_counts[reason as usize], Ordering::Relaxed);
                 }
             }
 
-            if let Some(ref tracker) = tracker {
-                let end = rdtsc();
-                tracker.record(end - start);
+            if let Some(ref counters) = counters {
+                counters.decisions.store(decision_count, Ordering::Relaxed);
+                counters.sent.store(sent_count, Ordering::Relaxed);
+                counters.rejected.store(rejected_count, Ordering::Relaxed);
+            }
+
+            if sampled {
+                recorder.record(start);
             }
+        } else if draining {
+            break;
         } else {
-            std::hint::spin_loop();
+            recorder.flush();
+            waiter.wait();
         }
     }
 
-    println!(
-        "[Gateway] Thread stopping. Processed {} decisions, sent {} orders, rejected {}",
-        decision_count, sent_count, rejected_count
-    );
+    let mut report = StageReport::new(Stage::Gateway, "Gateway", config.cpu_id, profile);
+    report.received = decision_count;
+    report.sent = sent_count;
+    report.rejected = rejected_count;
+    report.drained = drained_count;
+    report
 }
 
 #[inline(always)]
@@ -68,7 +201,7 @@ fn send_order_mock(_order: &Order) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::types::{Price, Quantity, Timestamp};
+    use crate::core::types::{Price, Quantity};
     use crate::messages::Side;
 
     #[test]
@@ -90,4 +223,134 @@ mod tests {
 
         send_order_mock(&order);
     }
+
+    #[test]
+    fn test_drains_queue_after_shutdown() {
+        let (mut tx, rx) = crate::core::spsc::channel::<RiskDecision>(16);
+        for id in 0..5 {
+            let order = Order::new(
+                id,
+                1,
+                Price::new(100, 0),
+                Quantity::new(1, 0),
+                Side::Buy,
+                Timestamp::from_cycles(0),
+            );
+            tx.push(RiskDecision::Approve(order)).unwrap();
+        }
+
+        let config = GatewayConfig {
+            cpu_id: 0,
+            ..Default::default()
+        };
+        let report = run_gateway(config, rx, Arc::new(AtomicBool::new(true)), None);
+
+        assert_eq!(report.stage, Stage::Gateway);
+        assert_eq!(report.received, 5);
+        assert_eq!(report.sent, 5);
+        assert_eq!(report.drained, 5);
+    }
+
+    #[test]
+    fn test_cancel_passes_tripped_kill_switch() {
+        use crate::core::broadcast;
+        use crate::core::fan_in::FanIn;
+        use crate::messages::{MarketEvent, SignalEvent};
+        use crate::pipeline::risk::{RiskConfig, run_risk};
+
+        let kill_switch = Arc::new(KillSwitch::new());
+        kill_switch.trip();
+
+        let origin = Timestamp::from_cycles(0);
+        let (mut signals, signals_rx) = crate::core::spsc::channel::<SignalEvent>(16);
+        signals
+            .push(SignalEvent::Buy {
+                strategy_id: 0,
+                symbol: 0,
+                price: Price::new(100, 0),
+                qty: Quantity::new(1, 0),
+                timestamp: origin,
+                trace: Trace::from_origin(origin),
+            })
+            .unwrap();
+        signals
+            .push(SignalEvent::Cancel {
+                strategy_id: 0,
+                order_id: 42,
+                timestamp: origin,
+                trace: Trace::from_origin(origin),
+            })
+            .unwrap();
+
+        // A queued approval from before the trip is dropped by the gateway.
+        let (mut decisions, decisions_rx) = crate::core::spsc::channel::<RiskDecision>(16);
+        decisions
+            .push(RiskDecision::Approve(Order::new(
+                1,
+                0,
+                Price::new(100, 0),
+                Quantity::new(1, 0),
+                Side::Buy,
+                origin,
+            )))
+            .unwrap();
+
+        let risk = RiskConfig {
+            cpu_id: 0,
+            kill_switch: kill_switch.clone(),
+            ..Default::default()
+        };
+        let market = broadcast::channel::<MarketEvent>(8).subscribe();
+        let risk_report = run_risk(
+            risk,
+            market,
+            FanIn::new(vec![signals_rx]),
+            decisions,
+            Arc::new(AtomicBool::new(true)),
+            None,
+        );
+        assert_eq!(risk_report.rejected, 1);
+        assert_eq!(risk_report.sent, 1);
+
+        let gateway = GatewayConfig {
+            cpu_id: 0,
+            kill_switch,
+            ..Default::default()
+        };
+        let counters = Arc::new(GatewayCounters::default());
+        let report = run_gateway(
+            gateway,
+            decisions_rx,
+            Arc::new(AtomicBool::new(true)),
+            Some(counters.clone()),
+        );
+
+        assert_eq!(report.received, 3);
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.rejected, 2);
+        assert_eq!(
+            counters.rejects_by_reason[RejectReason::KillSwitch as usize].load(Ordering::Relaxed),
+            2
+        );
+    }
+
+    #[test]
+    fn test_trace_latency_records_hops() {
+        let trace = Trace {
+            origin: Timestamp::from_cycles(100),
+            strategy_in: Timestamp::from_cycles(130),
+            strategy_out: Timestamp::from_cycles(150),
+            risk_in: Timestamp::from_cycles(190),
+            risk_out: Timestamp::from_cycles(200),
+        };
+        let latency = TraceLatency::new();
+
+        latency.record_queues(&trace, Timestamp::from_cycles(260));
+        latency.record_wire(&trace, Timestamp::from_cycles(300));
+
+        assert_eq!(latency.md_to_strategy.stats().max, 30);
+        assert_eq!(latency.strategy_to_risk.stats().max, 40);
+        assert_eq!(latency.risk_to_gateway.stats().max, 60);
+        assert_eq!(latency.tick_to_wire.stats().max, 200);
+    }
 }
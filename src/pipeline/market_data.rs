@@ -1,86 +1,269 @@
-use crate::core::types::{Price, Quantity};
-use crate::core::{LatencyTracker, SpscQueue, pin_to_cpu, rdtsc};
-use crate::messages::{MAX_LEVELS, MarketEvent, PriceLevel, Side};
-use crate::order_book::OrderBook;
+use crate::core::types::{Price, Quantity, Timestamp};
+use crate::core::{
+    Allocator, Publisher, Stage, StageRecorder, ThreadProfile, TscClock, Waiter, latency_start,
+    rdtsc,
+};
+use crate::messages::{LevelAction, MarketEvent, PriceLevel, Side};
+use crate::order_book::{BookSet, OrderBook};
+use crate::pipeline::report::StageReport;
+use crate::pipeline::watchdog::{Heartbeat, await_room};
+use crate::symbols::{DEFAULT_TICKER, SymbolTable};
+use std::ptr::addr_of_mut;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+// Symbols whose consumers have asked for a fresh `BookUpdate`, e.g. after a
+// sequence gap. Market data clears a flag when it publishes that snapshot.
+#[derive(Debug)]
+pub struct SnapshotRequests {
+    pending: Box<[AtomicBool]>,
+}
+
+impl SnapshotRequests {
+    pub fn new(symbols: usize) -> Self {
+        SnapshotRequests {
+            pending: (0..symbols).map(|_| AtomicBool::new(false)).collect(),
+        }
+    }
+
+    // Skips the store when already pending, so strategies that keep seeing
+    // deltas while out of sync don't keep dirtying the line.
+    #[inline]
+    pub fn request(&self, symbol: u32) {
+        if let Some(flag) = self.pending.get(symbol as usize)
+            && !flag.load(Ordering::Relaxed)
+        {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[inline(always)]
+    pub fn take(&self, symbol: u32) -> bool {
+        self.pending
+            .get(symbol as usize)
+            .is_some_and(|flag| flag.load(Ordering::Relaxed) && flag.swap(false, Ordering::Relaxed))
+    }
+}
+
+// Paces `SnapshotRequests` for out-of-sync mirrors. A gap always requests
+// one; after that a symbol still awaiting its snapshot asks again only every
+// `every_deltas` deltas or once `timeout` has passed, in case the snapshot
+// was lost to an overrun.
+pub struct SnapshotRetry {
+    // Per symbol: deltas seen and cycle count at the last request.
+    pending: Box<[(u32, u64)]>,
+    every_deltas: u32,
+    timeout_cycles: u64,
+}
+
+impl SnapshotRetry {
+    pub const EVERY_DELTAS: u32 = 1024;
+    pub const TIMEOUT: Duration = Duration::from_millis(10);
+
+    pub fn new(symbols: usize, every_deltas: u32, timeout: Duration) -> Self {
+        SnapshotRetry {
+            pending: vec![(0, 0); symbols].into_boxed_slice(),
+            every_deltas,
+            timeout_cycles: TscClock::global().nanos_to_cycles(timeout.as_nanos() as u64),
+        }
+    }
+
+    // Restarts the pacing for `symbol`; the caller requests the snapshot.
+    #[inline]
+    pub fn on_gap(&mut self, symbol: u32, now: u64) {
+        if let Some(pending) = self.pending.get_mut(symbol as usize) {
+            *pending = (0, now);
+        }
+    }
+
+    #[inline]
+    pub fn on_awaiting(&mut self, symbol: u32, now: u64) -> bool {
+        let Some((deltas, last)) = self.pending.get_mut(symbol as usize) else {
+            return false;
+        };
+
+        *deltas += 1;
+        if *deltas >= self.every_deltas || now.wrapping_sub(*last) >= self.timeout_cycles {
+            *deltas = 0;
+            *last = now;
+            return true;
+        }
+        false
+    }
+}
 
 pub struct MarketDataConfig {
-    pub symbol: u32,
+    pub symbols: Arc<SymbolTable>,
+    pub snapshots: Arc<SnapshotRequests>,
     pub cpu_id: usize,
+    pub sample_every: u32,
+    pub profile: ThreadProfile,
+    pub allocator: Allocator,
+    pub heartbeat: Arc<Heartbeat>,
+    // How to wait on a gated subscriber that has fallen a full ring behind.
+    pub wait: Waiter,
 }
 
 impl Default for MarketDataConfig {
     fn default() -> Self {
         MarketDataConfig {
-            symbol: 1,
+            symbols: Arc::new(SymbolTable::single(DEFAULT_TICKER)),
+            snapshots: Arc::new(SnapshotRequests::new(1)),
             cpu_id: 0,
+            sample_every: 1,
+            profile: ThreadProfile::new(),
+            allocator: Allocator::heap(),
+            heartbeat: Arc::default(),
+            wait: Waiter::default(),
         }
     }
 }
 
-pub fn run_market_data(
+#[derive(Debug, Default)]
+pub struct MarketDataCounters {
+    pub ticks: AtomicU64,
+    pub snapshots: AtomicU64,
+}
+
+pub fn run_market_data<const DEPTH: usize>(
     config: MarketDataConfig,
-    output_queue: Arc<SpscQueue<MarketEvent>>,
+    mut output: Publisher<MarketEvent<DEPTH>>,
     shutdown: Arc<AtomicBool>,
-    tracker: Option<Arc<LatencyTracker>>,
-) {
-    pin_to_cpu(config.cpu_id).expect("Failed to pin market data thread");
+    counters: Option<Arc<MarketDataCounters>>,
+) -> StageReport {
+    let profile = config
+        .profile
+        .clone()
+        .name("hft-market-data")
+        .cpu(config.cpu_id)
+        .apply()
+        .expect("Failed to apply market data thread profile");
 
-    let mut book = OrderBook::new();
+    let mut books = BookSet::<DEPTH>::for_symbols(&config.symbols, &config.allocator);
+    let symbol_count = books.capacity().max(1) as u64;
+    let mut seqs = vec![0u64; books.capacity()].into_boxed_slice();
     let mut tick_count = 0u64;
+    let mut snapshot_count = 0u64;
+    let mut recorder = StageRecorder::new(Stage::MarketData, config.sample_every);
+    let mut waiter = config.wait;
+    let heartbeat = config.heartbeat.clone();
 
-    println!("[MarketData] Thread started on CPU {}", config.cpu_id);
+    // Every consumer starts from a snapshot of the (empty) books; after
+    // that only deltas flow unless one is requested.
+    for (symbol, book) in books.iter() {
+        if !await_room(&mut output, &mut waiter, &heartbeat) {
+            break;
+        }
+        publish_snapshot(&mut output, symbol, book, 0, rdtsc());
+        snapshot_count += 1;
+    }
 
+    // Gated subscribers pace the feed through `await_room`; with none it
+    // never waits and slow readers are lapped instead.
     while !shutdown.load(Ordering::Relaxed) {
-        let start = rdtsc();
-
-        let (price, qty, side) = generate_mock_tick(tick_count);
+        if !await_room(&mut output, &mut waiter, &heartbeat) {
+            break;
+        }
+        let start = latency_start();
 
-        book.update_level(side, price, qty);
+        // Round-robin over the symbol table, each symbol with its own
+        // mock sequence.
+        let symbol = (tick_count % symbol_count) as u32;
+        let sequence = tick_count / symbol_count;
+        let (price, qty, side) = generate_mock_tick(sequence);
 
         let timestamp = rdtsc();
-        let event = if tick_count % 10 == 0 {
-            MarketEvent::BookUpdate {
-                symbol: config.symbol,
-                bids: copy_levels(book.bids()),
-                asks: copy_levels(book.asks()),
-                timestamp,
-            }
-        } else {
-            MarketEvent::Tick {
-                symbol: config.symbol,
+        heartbeat.beat();
+        heartbeat.progress(timestamp);
+
+        if let Some(book) = books.get_mut(symbol) {
+            let action = level_action(book, side, price, qty);
+            book.update_level(side, price, qty);
+
+            let seq = &mut seqs[symbol as usize];
+            *seq += 1;
+            output.publish(MarketEvent::LevelDelta {
+                symbol,
+                side,
                 price,
                 qty,
-                side,
+                action,
+                seq: *seq,
                 timestamp,
-            }
-        };
+            });
 
-        while output_queue.push(event).is_err() {
-            std::hint::spin_loop();
+            if config.snapshots.take(symbol) {
+                if !await_room(&mut output, &mut waiter, &heartbeat) {
+                    break;
+                }
+                publish_snapshot(&mut output, symbol, book, *seq, timestamp);
+                snapshot_count += 1;
+            }
         }
 
-        if let Some(ref tracker) = tracker {
-            let end = rdtsc();
-            tracker.record(end - start);
+        if recorder.sample() {
+            recorder.record(start);
         }
 
         tick_count += 1;
 
-        if tick_count % 1000 == 0 {
-            std::thread::yield_now();
+        if let Some(ref counters) = counters {
+            counters.ticks.store(tick_count, Ordering::Relaxed);
+            counters.snapshots.store(snapshot_count, Ordering::Relaxed);
         }
 
-        if tick_count >= 100_000 {
-            break;
+        if tick_count.is_multiple_of(1000) {
+            std::thread::yield_now();
         }
     }
 
-    println!(
-        "[MarketData] Thread stopping. Processed {} ticks",
-        tick_count
-    );
+    let mut report = StageReport::new(Stage::MarketData, "MarketData", config.cpu_id, profile);
+    report.received = tick_count;
+    report.sent = tick_count + snapshot_count;
+    report
+}
+
+#[inline(always)]
+fn level_action<const DEPTH: usize>(
+    book: &OrderBook<DEPTH>,
+    side: Side,
+    price: Price,
+    qty: Quantity,
+) -> LevelAction {
+    let levels = match side {
+        Side::Buy => book.bids(),
+        Side::Sell => book.asks(),
+    };
+    if qty.raw() == 0 {
+        LevelAction::Delete
+    } else if levels.iter().any(|level| level.price == price) {
+        LevelAction::Change
+    } else {
+        LevelAction::New
+    }
+}
+
+// Builds the `BookUpdate` straight in the ring slot: at 2 x DEPTH cache
+// lines it is too big to assemble on the stack and copy in.
+#[inline(always)]
+fn publish_snapshot<const DEPTH: usize>(
+    output: &mut Publisher<MarketEvent<DEPTH>>,
+    symbol: u32,
+    book: &OrderBook<DEPTH>,
+    seq: u64,
+    timestamp: Timestamp,
+) {
+    let mut slot = output.reserve();
+    unsafe {
+        let snapshot = MarketEvent::book_update_in(slot.as_mut_ptr());
+        addr_of_mut!((*snapshot).symbol).write(symbol);
+        write_levels(addr_of_mut!((*snapshot).bids), book.bids());
+        write_levels(addr_of_mut!((*snapshot).asks), book.asks());
+        addr_of_mut!((*snapshot).seq).write(seq);
+        addr_of_mut!((*snapshot).timestamp).write(timestamp);
+        slot.commit();
+    }
 }
 
 #[inline(always)]
@@ -92,7 +275,7 @@ fn generate_mock_tick(tick_count: u64) -> (Price, Quantity, Side) {
     let price = Price::from_raw(price_raw);
     let qty = Quantity::new(10 + (tick_count % 50) as i64, 0);
 
-    let side = if tick_count % 2 == 0 {
+    let side = if tick_count.is_multiple_of(2) {
         Side::Buy
     } else {
         Side::Sell
@@ -101,17 +284,23 @@ fn generate_mock_tick(tick_count: u64) -> (Price, Quantity, Side) {
     (price, qty, side)
 }
 
+// Safety: `dst` must be valid for writes.
 #[inline(always)]
-fn copy_levels(levels: &[PriceLevel]) -> [PriceLevel; MAX_LEVELS] {
-    let mut result = [PriceLevel::empty(); MAX_LEVELS];
-    let count = levels.len().min(MAX_LEVELS);
-    result[..count].copy_from_slice(&levels[..count]);
-    result
+unsafe fn write_levels<const DEPTH: usize>(dst: *mut [PriceLevel; DEPTH], levels: &[PriceLevel]) {
+    let dst = dst.cast::<PriceLevel>();
+    let count = levels.len().min(DEPTH);
+    unsafe {
+        std::ptr::copy_nonoverlapping(levels.as_ptr(), dst, count);
+        for i in count..DEPTH {
+            dst.add(i).write(PriceLevel::empty());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::messages::DEFAULT_DEPTH;
 
     #[test]
     fn test_mock_tick_generation() {
@@ -125,15 +314,81 @@ mod tests {
     }
 
     #[test]
-    fn test_copy_levels() {
-        let levels = vec![
-            PriceLevel::new(Price::new(100, 0), Quantity::new(10, 0)),
-            PriceLevel::new(Price::new(99, 0), Quantity::new(20, 0)),
-        ];
-
-        let copied = copy_levels(&levels);
-        assert_eq!(copied[0].price, Price::new(100, 0));
-        assert_eq!(copied[1].price, Price::new(99, 0));
-        assert!(copied[2].is_empty());
+    fn test_snapshot_requests() {
+        let requests = SnapshotRequests::new(2);
+        assert!(!requests.take(0));
+
+        requests.request(1);
+        requests.request(1);
+        requests.request(7);
+        assert!(!requests.take(0));
+        assert!(requests.take(1));
+        assert!(!requests.take(1));
+        assert!(!requests.take(7));
+    }
+
+    #[test]
+    fn test_snapshot_retry_is_bounded() {
+        let mut retry = SnapshotRetry::new(2, 4, Duration::from_secs(3600));
+
+        retry.on_gap(0, 100);
+        assert!(!retry.on_awaiting(0, 101));
+        assert!(!retry.on_awaiting(0, 102));
+        assert!(!retry.on_awaiting(0, 103));
+        assert!(retry.on_awaiting(0, 104));
+        assert!(!retry.on_awaiting(0, 105));
+
+        // Past the timeout a single delta is enough.
+        let timeout = retry.timeout_cycles;
+        assert!(retry.on_awaiting(0, 104 + timeout));
+
+        // Symbols are tracked separately, and unknown ones never request.
+        assert!(!retry.on_awaiting(1, 106));
+        assert!(!retry.on_awaiting(7, 106));
+    }
+
+    #[test]
+    fn test_level_action() {
+        let mut book = OrderBook::<DEFAULT_DEPTH>::new();
+        let price = Price::new(100, 0);
+        let qty = Quantity::new(10, 0);
+
+        assert_eq!(level_action(&book, Side::Buy, price, qty), LevelAction::New);
+        book.update_level(Side::Buy, price, qty);
+        assert_eq!(
+            level_action(&book, Side::Buy, price, qty),
+            LevelAction::Change
+        );
+        assert_eq!(
+            level_action(&book, Side::Sell, price, qty),
+            LevelAction::New
+        );
+        assert_eq!(
+            level_action(&book, Side::Buy, price, Quantity::new(0, 0)),
+            LevelAction::Delete
+        );
+    }
+
+    #[test]
+    fn test_publish_snapshot_in_place() {
+        let mut book = OrderBook::<DEFAULT_DEPTH>::new();
+        book.update_level(Side::Buy, Price::new(100, 0), Quantity::new(10, 0));
+        book.update_level(Side::Buy, Price::new(99, 0), Quantity::new(20, 0));
+
+        let mut output = crate::core::broadcast::channel(4);
+        let mut sub = output.subscribe();
+        publish_snapshot(&mut output, 3, &book, 8, Timestamp::from_cycles(1));
+
+        match sub.try_recv() {
+            Ok(MarketEvent::BookUpdate(snapshot)) => {
+                assert_eq!(snapshot.symbol, 3);
+                assert_eq!(snapshot.seq, 8);
+                assert_eq!(snapshot.bids[0].price, Price::new(100, 0));
+                assert_eq!(snapshot.bids[1].price, Price::new(99, 0));
+                assert!(snapshot.bids[2].is_empty());
+                assert!(snapshot.asks[0].is_empty());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
     }
 }
@@ -1,4 +1,11 @@
+pub mod builder;
+pub mod exporter;
 pub mod gateway;
 pub mod market_data;
+pub mod report;
 pub mod risk;
 pub mod strategy;
+pub mod watchdog;
+
+pub use builder::{Pipeline, PipelineBuilder, PipelineHandle, PipelineMetrics, PipelineReport};
+pub use watchdog::{Heartbeat, Watchdog};
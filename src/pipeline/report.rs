@@ -0,0 +1,57 @@
+use crate::core::{Stage, ThreadReport};
+use std::fmt;
+
+// What a stage thread hands back when it exits. `drained` counts messages it
+// processed after shutdown was requested, i.e. the backlog that the old
+// stop-immediately behaviour would have dropped. `missed` covers input lost
+// to overruns and output dropped after a watchdog abort.
+#[derive(Debug)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub label: String,
+    pub cpu_id: usize,
+    pub profile: ThreadReport,
+    pub received: u64,
+    pub sent: u64,
+    pub rejected: u64,
+    pub missed: u64,
+    pub drained: u64,
+}
+
+impl StageReport {
+    pub fn new(
+        stage: Stage,
+        label: impl Into<String>,
+        cpu_id: usize,
+        profile: ThreadReport,
+    ) -> Self {
+        StageReport {
+            stage,
+            label: label.into(),
+            cpu_id,
+            profile,
+            received: 0,
+            sent: 0,
+            rejected: 0,
+            missed: 0,
+            drained: 0,
+        }
+    }
+}
+
+impl fmt::Display for StageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] CPU {}: received {}, sent {}, rejected {}, missed {}, drained {} ({})",
+            self.label,
+            self.cpu_id,
+            self.received,
+            self.sent,
+            self.rejected,
+            self.missed,
+            self.drained,
+            self.profile
+        )
+    }
+}
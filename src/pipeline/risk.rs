@@ -1,13 +1,81 @@
-use crate::core::types::Quantity;
-use crate::core::{LatencyTracker, SpscQueue, pin_to_cpu, rdtsc};
-use crate::messages::{Order, RejectReason, RiskDecision, Side, SignalEvent};
+use crate::core::broadcast::TryRecvError;
+use crate::core::fan_in::FanIn;
+use crate::core::memory::Buffer;
+use crate::core::types::{Price, Quantity};
+use crate::core::{
+    Allocator, Parker, Producer, Stage, StageRecorder, Subscriber, ThreadProfile, TscClock,
+    WaitStrategy, Waiter, latency_start, rdtsc,
+};
+use crate::messages::{
+    BookSnapshot, DEFAULT_DEPTH, MarketEvent, Order, RejectReason, RiskDecision, Side, SignalEvent,
+};
+use crate::order_book::{DeltaResult, MirrorBook, OrderBook};
+use crate::pipeline::market_data::{SnapshotRequests, SnapshotRetry};
+use crate::pipeline::report::StageReport;
+use crate::pipeline::watchdog::{Heartbeat, push_supervised};
+use crate::symbols::{DEFAULT_TICKER, SymbolTable};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
+pub const MAX_STRATEGIES: usize = 16;
+
+// Market events applied per loop iteration, so a busy feed cannot starve the
+// signal queues.
+const MARKET_EVENTS_PER_SIGNAL: usize = 64;
+
+// Stops new orders pipeline-wide: risk rejects every new order and the
+// gateway drops approvals that were already queued; cancels still pass.
+// Stays tripped until `reset`. Each pipeline gets its own from the builder.
+#[derive(Debug, Default)]
+pub struct KillSwitch {
+    tripped: AtomicBool,
+}
+
+impl KillSwitch {
+    pub const fn new() -> Self {
+        KillSwitch {
+            tripped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn trip(&self) {
+        self.tripped.store(true, Ordering::Release);
+    }
+
+    pub fn reset(&self) {
+        self.tripped.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RiskCounters {
+    pub signals: AtomicU64,
+    pub approved: AtomicU64,
+    pub rejected: AtomicU64,
+    pub rejects_by_reason: [AtomicU64; RejectReason::COUNT],
+}
+
 pub struct RiskConfig {
     pub cpu_id: usize,
     pub max_position: Quantity,
     pub max_orders_per_second: u64,
+    // How far through the touch an order may be priced: a buy at most this
+    // far above the best ask, a sell at most this far below the best bid.
+    pub touch_band: Price,
+    pub wait: Waiter,
+    pub sample_every: u32,
+    pub profile: ThreadProfile,
+    pub kill_switch: Arc<KillSwitch>,
+    pub heartbeat: Arc<Heartbeat>,
+    pub symbols: Arc<SymbolTable>,
+    // Shared with market data; set by the pipeline builder.
+    pub snapshots: Arc<SnapshotRequests>,
+    pub allocator: Allocator,
 }
 
 impl Default for RiskConfig {
@@ -16,173 +84,316 @@ impl Default for RiskConfig {
             cpu_id: 2,
             max_position: Quantity::new(1000, 0),
             max_orders_per_second: 100,
+            touch_band: Price::new(1, 0),
+            wait: Waiter::busy_spin(),
+            sample_every: 1,
+            profile: ThreadProfile::new(),
+            kill_switch: Arc::default(),
+            heartbeat: Arc::default(),
+            symbols: Arc::new(SymbolTable::single(DEFAULT_TICKER)),
+            snapshots: Arc::new(SnapshotRequests::new(1)),
+            allocator: Allocator::heap(),
         }
     }
 }
 
-struct RiskState {
-    current_position: Quantity,
+#[derive(Debug, Clone, Copy)]
+struct StrategyRisk {
     order_count_this_second: u64,
     last_reset_time: u64,
-    next_order_id: AtomicU64,
 }
 
-impl RiskState {
-    fn new() -> Self {
-        RiskState {
-            current_position: Quantity::new(0, 0),
+impl StrategyRisk {
+    const fn new() -> Self {
+        StrategyRisk {
             order_count_this_second: 0,
             last_reset_time: 0,
+        }
+    }
+}
+
+struct RiskState<const DEPTH: usize = DEFAULT_DEPTH> {
+    strategies: [StrategyRisk; MAX_STRATEGIES],
+    // Net position per (strategy, symbol), strategy-major.
+    positions: Box<[Quantity]>,
+    symbol_count: usize,
+    // Mirrors of the market data books, for checks against the touch.
+    books: Buffer<MirrorBook<DEPTH>>,
+    retry: SnapshotRetry,
+    next_order_id: AtomicU64,
+    rate_window_cycles: u64,
+}
+
+impl<const DEPTH: usize> RiskState<DEPTH> {
+    fn new(symbol_count: usize, allocator: &Allocator) -> Self {
+        RiskState {
+            strategies: [StrategyRisk::new(); MAX_STRATEGIES],
+            positions: vec![Quantity::new(0, 0); MAX_STRATEGIES * symbol_count].into_boxed_slice(),
+            symbol_count,
+            books: MirrorBook::array_in(symbol_count, allocator),
+            retry: SnapshotRetry::new(
+                symbol_count,
+                SnapshotRetry::EVERY_DELTAS,
+                SnapshotRetry::TIMEOUT,
+            ),
             next_order_id: AtomicU64::new(1),
+            rate_window_cycles: TscClock::global().nanos_to_cycles(1_000_000_000),
+        }
+    }
+
+    fn on_market_event(&mut self, event: MarketEvent<DEPTH>, snapshots: &SnapshotRequests) {
+        let symbol = event.symbol();
+        let Some(mirror) = self.books.get_mut(symbol as usize) else {
+            return;
+        };
+
+        match event {
+            MarketEvent::LevelDelta {
+                side,
+                price,
+                qty,
+                action,
+                seq,
+                ..
+            } => match mirror.apply_delta(side, price, qty, action, seq) {
+                DeltaResult::Applied | DeltaResult::Stale => {}
+                DeltaResult::Gap => {
+                    self.retry.on_gap(symbol, rdtsc().cycles());
+                    snapshots.request(symbol);
+                }
+                DeltaResult::AwaitingSnapshot => {
+                    if self.retry.on_awaiting(symbol, rdtsc().cycles()) {
+                        snapshots.request(symbol);
+                    }
+                }
+            },
+            MarketEvent::BookUpdate(BookSnapshot {
+                bids, asks, seq, ..
+            }) => {
+                mirror.apply_snapshot(&bids, &asks, seq);
+            }
+            MarketEvent::Tick { .. } | MarketEvent::Trade { .. } => {}
         }
     }
 
     fn get_next_order_id(&self) -> u64 {
         self.next_order_id.fetch_add(1, Ordering::Relaxed)
     }
+
+    #[inline(always)]
+    fn evaluate(&mut self, config: &RiskConfig, signal: SignalEvent, now: u64) -> RiskDecision {
+        let strategy_id = signal.strategy_id() as usize;
+        let Some(strategy) = self.strategies.get_mut(strategy_id) else {
+            return RiskDecision::Reject {
+                reason: RejectReason::UnknownStrategy,
+                original_signal: signal,
+            };
+        };
+
+        if now.saturating_sub(strategy.last_reset_time) > self.rate_window_cycles {
+            strategy.order_count_this_second = 0;
+            strategy.last_reset_time = now;
+        }
+
+        let (symbol, price, qty, side) = match signal {
+            SignalEvent::Buy {
+                symbol, price, qty, ..
+            } => (symbol, price, qty, Side::Buy),
+            SignalEvent::Sell {
+                symbol, price, qty, ..
+            } => (symbol, price, qty, Side::Sell),
+            // Cancels only reduce exposure, so they pass even with the kill
+            // switch tripped, when pulling resting orders matters most.
+            SignalEvent::Cancel {
+                order_id,
+                timestamp,
+                trace,
+                ..
+            } => {
+                return RiskDecision::Approve(Order::cancel(order_id, timestamp).with_trace(trace));
+            }
+        };
+
+        let reject = |reason| RiskDecision::Reject {
+            reason,
+            original_signal: signal,
+        };
+
+        if config.kill_switch.is_tripped() {
+            return reject(RejectReason::KillSwitch);
+        }
+
+        let Some(instrument) = config.symbols.get(symbol) else {
+            return reject(RejectReason::UnknownSymbol);
+        };
+        if !instrument.is_valid_quantity(qty) {
+            return reject(RejectReason::InvalidQuantity);
+        }
+        if !instrument.is_valid_price(price) {
+            return reject(RejectReason::InvalidPrice);
+        }
+        // Skipped while the mirror waits for a snapshot; the static band
+        // above still applies.
+        if let Some(mirror) = self.books.get(symbol as usize)
+            && mirror.is_synced()
+            && !within_touch_band(mirror.book(), side, price, config.touch_band)
+        {
+            return reject(RejectReason::OutsideTouchBand);
+        }
+        if strategy.order_count_this_second >= config.max_orders_per_second {
+            return reject(RejectReason::RateLimitExceeded);
+        }
+
+        let position = &mut self.positions[strategy_id * self.symbol_count + symbol as usize];
+        let next = match side {
+            Side::Buy => *position + qty,
+            Side::Sell => *position - qty,
+        };
+        if next.raw().abs() > config.max_position.raw() {
+            return reject(RejectReason::PositionLimitExceeded);
+        }
+
+        *position = next;
+        strategy.order_count_this_second += 1;
+
+        RiskDecision::Approve(
+            Order::new(
+                self.get_next_order_id(),
+                symbol,
+                price,
+                qty,
+                side,
+                signal.timestamp(),
+            )
+            .with_trace(*signal.trace()),
+        )
+    }
 }
 
-pub fn run_risk(
+#[inline(always)]
+fn within_touch_band<const DEPTH: usize>(
+    book: &OrderBook<DEPTH>,
+    side: Side,
+    price: Price,
+    band: Price,
+) -> bool {
+    match side {
+        Side::Buy => book.best_ask().is_none_or(|ask| price <= ask + band),
+        Side::Sell => book.best_bid().is_none_or(|bid| price >= bid - band),
+    }
+}
+
+pub fn run_risk<const DEPTH: usize>(
     config: RiskConfig,
-    input_queue: Arc<SpscQueue<SignalEvent>>,
-    output_queue: Arc<SpscQueue<RiskDecision>>,
+    mut market: Subscriber<MarketEvent<DEPTH>>,
+    mut input_queue: FanIn<SignalEvent>,
+    mut output_queue: Producer<RiskDecision>,
     shutdown: Arc<AtomicBool>,
-    tracker: Option<Arc<LatencyTracker>>,
-) {
-    pin_to_cpu(config.cpu_id).expect("Failed to pin risk thread");
+    counters: Option<Arc<RiskCounters>>,
+) -> StageReport {
+    let profile = config
+        .profile
+        .clone()
+        .name("hft-risk")
+        .cpu(config.cpu_id)
+        .apply()
+        .expect("Failed to apply risk thread profile");
 
-    let mut state = RiskState::new();
+    let mut state = RiskState::<DEPTH>::new(config.symbols.len(), &config.allocator);
     let mut signal_count = 0u64;
     let mut approved_count = 0u64;
     let mut rejected_count = 0u64;
+    let mut reject_counts = [0u64; RejectReason::COUNT];
+    let mut drained_count = 0u64;
+    let mut dropped_count = 0u64;
+    let mut waiter = config.wait;
+    let mut recorder = StageRecorder::new(Stage::Risk, config.sample_every);
+    let heartbeat = config.heartbeat.clone();
+    if waiter.parks() {
+        let parker = Parker::current();
+        market.register_parker(parker.clone());
+        input_queue.register_parker(parker);
+    }
 
-    println!("[Risk] Thread started on CPU {}", config.cpu_id);
+    // `shutdown` is only raised once every strategy has exited, so an empty
+    // fan-in after that point is final.
+    loop {
+        let draining = shutdown.load(Ordering::Acquire);
+        heartbeat.beat();
+
+        for _ in 0..MARKET_EVENTS_PER_SIGNAL {
+            match market.try_recv() {
+                Ok(event) => state.on_market_event(event, &config.snapshots),
+                Err(TryRecvError::Empty) => break,
+                // The mirrors see the skipped sequence numbers and resync.
+                Err(TryRecvError::Overrun { .. }) => {}
+            }
+        }
 
-    while !shutdown.load(Ordering::Relaxed) {
         if let Some(signal) = input_queue.pop() {
-            let start = rdtsc();
+            let start = latency_start();
+            let now = rdtsc();
+            heartbeat.progress(now);
+            waiter.reset();
 
             signal_count += 1;
-
-            let current_time = start.cycles();
-            if current_time - state.last_reset_time > 1_000_000_000 {
-                state.order_count_this_second = 0;
-                state.last_reset_time = current_time;
+            if draining {
+                drained_count += 1;
             }
 
-            let decision = match signal {
-                SignalEvent::Buy {
-                    symbol,
-                    price,
-                    qty,
-                    timestamp,
-                } => {
-                    if state.order_count_this_second >= config.max_orders_per_second {
-                        rejected_count += 1;
-                        RiskDecision::Reject {
-                            reason: RejectReason::RateLimitExceeded,
-                            original_signal: signal,
-                        }
-                    } else if state.current_position + qty > config.max_position {
-                        rejected_count += 1;
-                        RiskDecision::Reject {
-                            reason: RejectReason::PositionLimitExceeded,
-                            original_signal: signal,
-                        }
-                    } else {
-                        let order = Order::new(
-                            state.get_next_order_id(),
-                            symbol,
-                            price,
-                            qty,
-                            Side::Buy,
-                            timestamp,
-                        );
-
-                        state.current_position = state.current_position + qty;
-                        state.order_count_this_second += 1;
-                        approved_count += 1;
-
-                        RiskDecision::Approve(order)
-                    }
-                }
+            let mut decision = state.evaluate(&config, signal, now.cycles());
+            decision.trace_mut().risk_in = start;
 
-                SignalEvent::Sell {
-                    symbol,
-                    price,
-                    qty,
-                    timestamp,
-                } => {
-                    if state.order_count_this_second >= config.max_orders_per_second {
-                        rejected_count += 1;
-                        RiskDecision::Reject {
-                            reason: RejectReason::RateLimitExceeded,
-                            original_signal: signal,
-                        }
-                    } else if state.current_position - qty < Quantity::new(-1000, 0) {
-                        rejected_count += 1;
-                        RiskDecision::Reject {
-                            reason: RejectReason::PositionLimitExceeded,
-                            original_signal: signal,
-                        }
-                    } else {
-                        let order = Order::new(
-                            state.get_next_order_id(),
-                            symbol,
-                            price,
-                            qty,
-                            Side::Sell,
-                            timestamp,
-                        );
-
-                        state.current_position = state.current_position - qty;
-                        state.order_count_this_second += 1;
-                        approved_count += 1;
-
-                        RiskDecision::Approve(order)
+            match decision {
+                RiskDecision::Approve(_) => approved_count += 1,
+                RiskDecision::Reject { reason, .. } => {
+                    rejected_count += 1;
+                    reject_counts[reason as usize] += 1;
+
+                    if let Some(ref counters) = counters {
+                        counters.rejects_by_reason[reason as usize]
+                            .store(reject_counts[reason as usize], Ordering::Relaxed);
                     }
                 }
+            }
 
-                SignalEvent::Cancel {
-                    order_id,
-                    timestamp,
-                } => {
-                    let order = Order::new(
-                        order_id,
-                        0,
-                        crate::core::types::Price::new(0, 0),
-                        Quantity::new(0, 0),
-                        Side::Buy,
-                        timestamp,
-                    );
-
-                    approved_count += 1;
-                    RiskDecision::Approve(order)
-                }
-            };
+            if let Some(ref counters) = counters {
+                counters.signals.store(signal_count, Ordering::Relaxed);
+                counters.approved.store(approved_count, Ordering::Relaxed);
+                counters.rejected.store(rejected_count, Ordering::Relaxed);
+            }
 
-            while output_queue.push(decision).is_err() {
-                std::hint::spin_loop();
+            decision.trace_mut().risk_out = latency_start();
+            if !push_supervised(&mut output_queue, decision, &mut waiter, &heartbeat) {
+                dropped_count += 1;
             }
 
-            if let Some(ref tracker) = tracker {
-                let end = rdtsc();
-                tracker.record(end - start);
+            if recorder.sample() {
+                recorder.record(start);
             }
+        } else if draining {
+            break;
         } else {
-            std::hint::spin_loop();
+            recorder.flush();
+            waiter.wait();
         }
     }
 
-    println!(
-        "[Risk] Thread stopping. Processed {} signals, approved {}, rejected {}",
-        signal_count, approved_count, rejected_count
-    );
+    let mut report = StageReport::new(Stage::Risk, "Risk", config.cpu_id, profile);
+    report.received = signal_count;
+    report.sent = approved_count;
+    report.rejected = rejected_count;
+    report.missed = dropped_count;
+    report.drained = drained_count;
+    report
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::types::Timestamp;
+    use crate::messages::Trace;
+    use crate::symbols::Instrument;
 
     #[test]
     fn test_risk_config_default() {
@@ -192,13 +403,265 @@ mod tests {
         assert_eq!(config.max_orders_per_second, 100);
     }
 
+    fn new_state(symbol_count: usize) -> RiskState {
+        RiskState::new(symbol_count, &Allocator::heap())
+    }
+
     #[test]
     fn test_risk_state() {
-        let state = RiskState::new();
+        let state = new_state(1);
         let id1 = state.get_next_order_id();
         let id2 = state.get_next_order_id();
 
         assert_eq!(id1, 1);
         assert_eq!(id2, 2);
     }
+
+    fn buy(strategy_id: u32, qty: i64) -> SignalEvent {
+        SignalEvent::Buy {
+            strategy_id,
+            symbol: 0,
+            price: Price::new(100, 0),
+            qty: Quantity::new(qty, 0),
+            timestamp: Timestamp::from_cycles(0),
+            trace: Trace::from_origin(Timestamp::from_cycles(0)),
+        }
+    }
+
+    #[test]
+    fn test_position_limit_per_strategy() {
+        let config = RiskConfig::default();
+        let mut state = new_state(config.symbols.len());
+
+        assert!(matches!(
+            state.evaluate(&config, buy(0, 1000), 0),
+            RiskDecision::Approve(_)
+        ));
+        assert!(matches!(
+            state.evaluate(&config, buy(0, 1), 0),
+            RiskDecision::Reject {
+                reason: RejectReason::PositionLimitExceeded,
+                ..
+            }
+        ));
+        assert!(matches!(
+            state.evaluate(&config, buy(1, 1000), 0),
+            RiskDecision::Approve(_)
+        ));
+    }
+
+    #[test]
+    fn test_rate_limit_per_strategy() {
+        let config = RiskConfig {
+            max_orders_per_second: 2,
+            ..RiskConfig::default()
+        };
+        let mut state = new_state(config.symbols.len());
+
+        for _ in 0..2 {
+            assert!(matches!(
+                state.evaluate(&config, buy(0, 1), 0),
+                RiskDecision::Approve(_)
+            ));
+        }
+        assert!(matches!(
+            state.evaluate(&config, buy(0, 1), 0),
+            RiskDecision::Reject {
+                reason: RejectReason::RateLimitExceeded,
+                ..
+            }
+        ));
+        assert!(matches!(
+            state.evaluate(&config, buy(1, 1), 0),
+            RiskDecision::Approve(_)
+        ));
+    }
+
+    #[test]
+    fn test_unknown_strategy_rejected() {
+        let config = RiskConfig::default();
+        let mut state = new_state(config.symbols.len());
+
+        assert!(matches!(
+            state.evaluate(&config, buy(MAX_STRATEGIES as u32, 1), 0),
+            RiskDecision::Reject {
+                reason: RejectReason::UnknownStrategy,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_kill_switch_rejects_new_orders_only() {
+        let config = RiskConfig::default();
+        let mut state = new_state(config.symbols.len());
+
+        config.kill_switch.trip();
+        assert!(matches!(
+            state.evaluate(&config, buy(0, 1), 0),
+            RiskDecision::Reject {
+                reason: RejectReason::KillSwitch,
+                ..
+            }
+        ));
+        let cancel = SignalEvent::Cancel {
+            strategy_id: 0,
+            order_id: 7,
+            timestamp: Timestamp::from_cycles(0),
+            trace: Trace::from_origin(Timestamp::from_cycles(0)),
+        };
+        assert!(matches!(
+            state.evaluate(&config, cancel, 0),
+            RiskDecision::Approve(order) if order.is_cancel() && order.id == 7
+        ));
+
+        config.kill_switch.reset();
+        assert!(matches!(
+            state.evaluate(&config, buy(0, 1), 0),
+            RiskDecision::Approve(_)
+        ));
+    }
+
+    #[test]
+    fn test_touch_band_follows_mirror() {
+        use crate::messages::PriceLevel;
+
+        let config = RiskConfig {
+            touch_band: Price::new(0, 5000),
+            ..RiskConfig::default()
+        };
+        let mut state = new_state(config.symbols.len());
+        let signal = |side, price| {
+            let (timestamp, trace) = (
+                Timestamp::from_cycles(0),
+                Trace::from_origin(Timestamp::from_cycles(0)),
+            );
+            match side {
+                Side::Buy => SignalEvent::Buy {
+                    strategy_id: 0,
+                    symbol: 0,
+                    price,
+                    qty: Quantity::new(1, 0),
+                    timestamp,
+                    trace,
+                },
+                Side::Sell => SignalEvent::Sell {
+                    strategy_id: 0,
+                    symbol: 0,
+                    price,
+                    qty: Quantity::new(1, 0),
+                    timestamp,
+                    trace,
+                },
+            }
+        };
+        let reason = |decision| match decision {
+            RiskDecision::Reject { reason, .. } => Some(reason),
+            RiskDecision::Approve(_) => None,
+        };
+
+        // No snapshot yet, so only the static checks apply.
+        assert_eq!(
+            reason(state.evaluate(&config, signal(Side::Buy, Price::new(150, 0)), 0)),
+            None
+        );
+
+        let mut bids = [PriceLevel::empty(); DEFAULT_DEPTH];
+        let mut asks = [PriceLevel::empty(); DEFAULT_DEPTH];
+        bids[0] = PriceLevel::new(Price::new(99, 7500), Quantity::new(10, 0));
+        asks[0] = PriceLevel::new(Price::new(100, 2500), Quantity::new(10, 0));
+        state.on_market_event(
+            MarketEvent::BookUpdate(BookSnapshot {
+                symbol: 0,
+                bids,
+                asks,
+                seq: 1,
+                timestamp: Timestamp::from_cycles(0),
+            }),
+            &config.snapshots,
+        );
+
+        let cases = [
+            (Side::Buy, Price::new(100, 7500), None),
+            (
+                Side::Buy,
+                Price::new(101, 0),
+                Some(RejectReason::OutsideTouchBand),
+            ),
+            (Side::Sell, Price::new(99, 2500), None),
+            (
+                Side::Sell,
+                Price::new(99, 0),
+                Some(RejectReason::OutsideTouchBand),
+            ),
+        ];
+        for (side, price, expected) in cases {
+            assert_eq!(
+                reason(state.evaluate(&config, signal(side, price), 0)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_reference_data_checks() {
+        let mut symbols = SymbolTable::new();
+        symbols
+            .insert(
+                Instrument::new("ES")
+                    .tick_size(Price::new(0, 2500))
+                    .lot_size(Quantity::new(5, 0))
+                    .price_band(Price::new(50, 0), Price::new(150, 0)),
+            )
+            .unwrap();
+        let config = RiskConfig {
+            symbols: Arc::new(symbols),
+            ..RiskConfig::default()
+        };
+        let mut state = new_state(config.symbols.len());
+
+        let with = |symbol, price, qty| match buy(0, qty) {
+            SignalEvent::Buy {
+                strategy_id,
+                timestamp,
+                trace,
+                ..
+            } => SignalEvent::Buy {
+                strategy_id,
+                symbol,
+                price,
+                qty: Quantity::new(qty, 0),
+                timestamp,
+                trace,
+            },
+            _ => unreachable!(),
+        };
+        let reason = |decision| match decision {
+            RiskDecision::Reject { reason, .. } => Some(reason),
+            RiskDecision::Approve(_) => None,
+        };
+
+        let cases = [
+            (
+                with(1, Price::new(100, 0), 5),
+                Some(RejectReason::UnknownSymbol),
+            ),
+            (
+                with(0, Price::new(100, 0), 3),
+                Some(RejectReason::InvalidQuantity),
+            ),
+            (
+                with(0, Price::new(100, 1000), 5),
+                Some(RejectReason::InvalidPrice),
+            ),
+            (
+                with(0, Price::new(200, 0), 5),
+                Some(RejectReason::InvalidPrice),
+            ),
+            (with(0, Price::new(100, 2500), 5), None),
+        ];
+        for (signal, expected) in cases {
+            assert_eq!(reason(state.evaluate(&config, signal, 0)), expected);
+        }
+    }
 }
@@ -1,121 +1,223 @@
+use crate::core::broadcast::TryRecvError;
 use crate::core::types::{Price, Quantity};
-use crate::core::{LatencyTracker, SpscQueue, pin_to_cpu, rdtsc};
-use crate::messages::{MarketEvent, Side, SignalEvent};
+use crate::core::{
+    Allocator, Parker, Producer, Stage, StageRecorder, Subscriber, ThreadProfile, WaitStrategy,
+    Waiter, latency_start, rdtsc,
+};
+use crate::messages::{BookSnapshot, MarketEvent, SignalEvent, Trace};
+use crate::order_book::{DeltaResult, MirrorBook};
+use crate::pipeline::market_data::{SnapshotRequests, SnapshotRetry};
+use crate::pipeline::report::StageReport;
+use crate::pipeline::watchdog::{Heartbeat, push_supervised};
+use crate::symbols::{DEFAULT_TICKER, SymbolTable};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 pub struct StrategyConfig {
+    pub strategy_id: u32,
     pub cpu_id: usize,
     pub spread_threshold: Price,
+    pub wait: Waiter,
+    pub sample_every: u32,
+    pub profile: ThreadProfile,
+    pub heartbeat: Arc<Heartbeat>,
+    pub symbols: Arc<SymbolTable>,
+    // Shared with market data; set by the pipeline builder.
+    pub snapshots: Arc<SnapshotRequests>,
+    pub allocator: Allocator,
 }
 
 impl Default for StrategyConfig {
     fn default() -> Self {
         StrategyConfig {
+            strategy_id: 0,
             cpu_id: 1,
             spread_threshold: Price::new(0, 5000),
+            wait: Waiter::busy_spin(),
+            sample_every: 1,
+            profile: ThreadProfile::new(),
+            heartbeat: Arc::default(),
+            symbols: Arc::new(SymbolTable::single(DEFAULT_TICKER)),
+            snapshots: Arc::new(SnapshotRequests::new(1)),
+            allocator: Allocator::heap(),
         }
     }
 }
 
-pub fn run_strategy(
+#[derive(Debug, Default)]
+pub struct StrategyCounters {
+    pub events: AtomicU64,
+    pub signals: AtomicU64,
+    pub overruns: AtomicU64,
+    pub gaps: AtomicU64,
+}
+
+pub fn run_strategy<const DEPTH: usize>(
     config: StrategyConfig,
-    input_queue: Arc<SpscQueue<MarketEvent>>,
-    output_queue: Arc<SpscQueue<SignalEvent>>,
+    mut input_queue: Subscriber<MarketEvent<DEPTH>>,
+    mut output_queue: Producer<SignalEvent>,
     shutdown: Arc<AtomicBool>,
-    tracker: Option<Arc<LatencyTracker>>,
-) {
-    pin_to_cpu(config.cpu_id).expect("Failed to pin strategy thread");
+    counters: Option<Arc<StrategyCounters>>,
+) -> StageReport {
+    let profile = config
+        .profile
+        .clone()
+        .name(format!("hft-strategy-{}", config.strategy_id))
+        .cpu(config.cpu_id)
+        .apply()
+        .expect("Failed to apply strategy thread profile");
 
     let mut event_count = 0u64;
     let mut signal_count = 0u64;
+    let mut overrun_count = 0u64;
+    let mut drained_count = 0u64;
+    let mut dropped_count = 0u64;
+    let mut gap_count = 0u64;
+    let mut waiter = config.wait;
+    let mut recorder = StageRecorder::new(Stage::Strategy, config.sample_every);
+    let heartbeat = config.heartbeat.clone();
+    if waiter.parks() {
+        input_queue.register_parker(Parker::current());
+    }
 
-    println!("[Strategy] Thread started on CPU {}", config.cpu_id);
-
-    let mut best_bid: Option<Price> = None;
-    let mut best_ask: Option<Price> = None;
-
-    while !shutdown.load(Ordering::Relaxed) {
-        if let Some(event) = input_queue.pop() {
-            let start = rdtsc();
-
-            event_count += 1;
-
-            match event {
-                MarketEvent::Tick {
-                    symbol,
-                    price,
-                    qty: _,
-                    side,
-                    timestamp: _,
-                } => {
-                    match side {
-                        Side::Buy => {
-                            if best_bid.is_none() || price > best_bid.unwrap() {
-                                best_bid = Some(price);
-                            }
-                        }
-                        Side::Sell => {
-                            if best_ask.is_none() || price < best_ask.unwrap() {
-                                best_ask = Some(price);
-                            }
-                        }
-                    }
+    // Mirrors of the market data books, one per symbol.
+    let mut books = MirrorBook::<DEPTH>::array_in(config.symbols.len(), &config.allocator);
+    let mut retry = SnapshotRetry::new(
+        config.symbols.len(),
+        SnapshotRetry::EVERY_DELTAS,
+        SnapshotRetry::TIMEOUT,
+    );
+
+    // `shutdown` means market data has stopped publishing: keep going until
+    // the ring has nothing left for this subscriber.
+    loop {
+        let draining = shutdown.load(Ordering::Acquire);
+        heartbeat.beat();
+
+        let event = match input_queue.try_recv() {
+            Ok(event) => event,
+            Err(TryRecvError::Empty) if draining => break,
+            Err(TryRecvError::Empty) => {
+                recorder.flush();
+                waiter.wait();
+                continue;
+            }
+            Err(TryRecvError::Overrun { missed }) => {
+                overrun_count += missed;
+                if let Some(ref counters) = counters {
+                    counters.overruns.store(overrun_count, Ordering::Relaxed);
+                }
+                continue;
+            }
+        };
 
-                    if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
-                        let spread = ask - bid;
-
-                        if spread <= config.spread_threshold {
-                            let signal = if event_count % 2 == 0 {
-                                SignalEvent::Buy {
-                                    symbol,
-                                    price: ask,
-                                    qty: Quantity::new(10, 0),
-                                    timestamp: rdtsc(),
-                                }
-                            } else {
-                                SignalEvent::Sell {
-                                    symbol,
-                                    price: bid,
-                                    qty: Quantity::new(10, 0),
-                                    timestamp: rdtsc(),
-                                }
-                            };
-
-                            while output_queue.push(signal).is_err() {
-                                std::hint::spin_loop();
+        let start = latency_start();
+        heartbeat.progress(rdtsc());
+        waiter.reset();
+
+        event_count += 1;
+        if draining {
+            drained_count += 1;
+        }
+
+        let symbol = event.symbol();
+        let Some(mirror) = books.get_mut(symbol as usize) else {
+            continue;
+        };
+
+        match event {
+            MarketEvent::LevelDelta {
+                side,
+                price,
+                qty,
+                action,
+                seq,
+                timestamp,
+                ..
+            } => match mirror.apply_delta(side, price, qty, action, seq) {
+                DeltaResult::Applied => {
+                    let book = mirror.book();
+                    if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask())
+                        && ask - bid <= config.spread_threshold
+                    {
+                        let trace = Trace {
+                            strategy_in: start,
+                            ..Trace::from_origin(timestamp)
+                        };
+
+                        let mut signal = if event_count.is_multiple_of(2) {
+                            SignalEvent::Buy {
+                                strategy_id: config.strategy_id,
+                                symbol,
+                                price: ask,
+                                qty: Quantity::new(10, 0),
+                                timestamp: rdtsc(),
+                                trace,
                             }
+                        } else {
+                            SignalEvent::Sell {
+                                strategy_id: config.strategy_id,
+                                symbol,
+                                price: bid,
+                                qty: Quantity::new(10, 0),
+                                timestamp: rdtsc(),
+                                trace,
+                            }
+                        };
 
+                        signal.trace_mut().strategy_out = latency_start();
+                        if push_supervised(&mut output_queue, signal, &mut waiter, &heartbeat) {
                             signal_count += 1;
+                        } else {
+                            dropped_count += 1;
                         }
                     }
                 }
-
-                MarketEvent::BookUpdate { bids, asks, .. } => {
-                    if !bids[0].is_empty() {
-                        best_bid = Some(bids[0].price);
-                    }
-                    if !asks[0].is_empty() {
-                        best_ask = Some(asks[0].price);
+                DeltaResult::Stale => {}
+                DeltaResult::Gap => {
+                    gap_count += 1;
+                    retry.on_gap(symbol, rdtsc().cycles());
+                    config.snapshots.request(symbol);
+                }
+                DeltaResult::AwaitingSnapshot => {
+                    if retry.on_awaiting(symbol, rdtsc().cycles()) {
+                        config.snapshots.request(symbol);
                     }
                 }
+            },
 
-                MarketEvent::Trade { .. } => {}
+            MarketEvent::BookUpdate(BookSnapshot {
+                bids, asks, seq, ..
+            }) => {
+                mirror.apply_snapshot(&bids, &asks, seq);
             }
 
-            if let Some(ref tracker) = tracker {
-                let end = rdtsc();
-                tracker.record(end - start);
-            }
-        } else {
-            std::hint::spin_loop();
+            // Unsequenced, so they never touch the mirror.
+            MarketEvent::Tick { .. } | MarketEvent::Trade { .. } => {}
+        }
+
+        if let Some(ref counters) = counters {
+            counters.events.store(event_count, Ordering::Relaxed);
+            counters.signals.store(signal_count, Ordering::Relaxed);
+            counters.gaps.store(gap_count, Ordering::Relaxed);
+        }
+
+        if recorder.sample() {
+            recorder.record(start);
         }
     }
 
-    println!(
-        "[Strategy] Thread stopping. Processed {} events, generated {} signals",
-        event_count, signal_count
+    let mut report = StageReport::new(
+        Stage::Strategy,
+        format!("Strategy {}", config.strategy_id),
+        config.cpu_id,
+        profile,
     );
+    report.received = event_count;
+    report.sent = signal_count;
+    report.missed = overrun_count + dropped_count;
+    report.drained = drained_count;
+    report
 }
 
 #[cfg(test)]
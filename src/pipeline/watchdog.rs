@@ -0,0 +1,530 @@
+use crate::core::types::Timestamp;
+use crate::core::{Producer, Publisher, TscClock, WaitStrategy, Waiter, rdtsc};
+use crate::pipeline::risk::KillSwitch;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RUNNING: u8 = 0;
+const EXITED: u8 = 1;
+const PANICKED: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageState {
+    Running,
+    Exited,
+    Panicked,
+}
+
+// Written by one stage thread, read by the watchdog. `beats` advances on
+// every loop iteration, busy or idle, so a stage that stops beating is stuck
+// somewhere outside its loop; `last_progress` only moves when a message is
+// handled. `blocked_since` is set while the stage waits on a full output
+// queue.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+pub struct Heartbeat {
+    beats: AtomicU64,
+    last_progress: AtomicU64,
+    blocked_since: AtomicU64,
+    state: AtomicU8,
+    abort: AtomicBool,
+}
+
+impl Heartbeat {
+    pub const fn new() -> Self {
+        Heartbeat {
+            beats: AtomicU64::new(0),
+            last_progress: AtomicU64::new(0),
+            blocked_since: AtomicU64::new(0),
+            state: AtomicU8::new(RUNNING),
+            abort: AtomicBool::new(false),
+        }
+    }
+
+    // Single writer, so a load/store pair instead of a locked add.
+    #[inline(always)]
+    pub fn beat(&self) {
+        let beats = self.beats.load(Ordering::Relaxed);
+        self.beats.store(beats.wrapping_add(1), Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn progress(&self, now: Timestamp) {
+        self.last_progress.store(now.cycles(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn beats(&self) -> u64 {
+        self.beats.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn last_progress(&self) -> Timestamp {
+        Timestamp::from_cycles(self.last_progress.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub fn blocked_since(&self) -> Option<Timestamp> {
+        match self.blocked_since.load(Ordering::Relaxed) {
+            0 => None,
+            cycles => Some(Timestamp::from_cycles(cycles)),
+        }
+    }
+
+    pub fn state(&self) -> StageState {
+        match self.state.load(Ordering::Acquire) {
+            RUNNING => StageState::Running,
+            EXITED => StageState::Exited,
+            _ => StageState::Panicked,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_aborted(&self) -> bool {
+        self.abort.load(Ordering::Relaxed)
+    }
+
+    fn abort(&self) {
+        self.abort.store(true, Ordering::Relaxed);
+    }
+}
+
+// Marks the heartbeat exited or panicked when the stage thread unwinds out of
+// its run function, whichever way it leaves.
+pub(crate) struct ExitGuard(pub Arc<Heartbeat>);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        let state = if thread::panicking() {
+            PANICKED
+        } else {
+            EXITED
+        };
+        self.0.state.store(state, Ordering::Release);
+    }
+}
+
+// Pushes `item`, waiting while `queue` is full and beating so the wait reads
+// as backpressure rather than a stall. Returns false, dropping `item`, if the
+// watchdog aborts the stage in the meantime.
+#[inline(always)]
+pub fn push_supervised<T: Copy>(
+    queue: &mut Producer<T>,
+    item: T,
+    waiter: &mut Waiter,
+    heartbeat: &Heartbeat,
+) -> bool {
+    if queue.push(item).is_ok() {
+        return true;
+    }
+
+    heartbeat
+        .blocked_since
+        .store(rdtsc().cycles().max(1), Ordering::Relaxed);
+
+    let pushed = loop {
+        if heartbeat.is_aborted() {
+            break false;
+        }
+        waiter.wait();
+        heartbeat.beat();
+        if queue.push(item).is_ok() {
+            break true;
+        }
+    };
+
+    heartbeat.blocked_since.store(0, Ordering::Relaxed);
+    pushed
+}
+
+// The broadcast side of `push_supervised`: waits until `publisher` can write
+// without lapping a gated subscriber. Returns false if the watchdog aborts
+// the stage in the meantime.
+#[inline(always)]
+pub fn await_room<T: Copy>(
+    publisher: &mut Publisher<T>,
+    waiter: &mut Waiter,
+    heartbeat: &Heartbeat,
+) -> bool {
+    if publisher.has_room() {
+        return true;
+    }
+
+    heartbeat
+        .blocked_since
+        .store(rdtsc().cycles().max(1), Ordering::Relaxed);
+
+    let room = loop {
+        if heartbeat.is_aborted() {
+            break false;
+        }
+        waiter.wait();
+        heartbeat.beat();
+        if publisher.has_room() {
+            break true;
+        }
+    };
+
+    heartbeat.blocked_since.store(0, Ordering::Relaxed);
+    room
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Panicked,
+    Stalled,
+    QueueFull,
+}
+
+impl Fault {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Fault::Panicked => "panicked",
+            Fault::Stalled => "stalled",
+            Fault::QueueFull => "blocked on a full queue",
+        }
+    }
+}
+
+// Escalating: each response also does everything before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Response {
+    Alert,
+    TripKillSwitch,
+    // Aborts every stage: blocked pushes give up and each stage exits once
+    // its input is empty, so `PipelineHandle::join` cannot hang on them.
+    Shutdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub stage: String,
+    pub fault: Fault,
+    pub after: Duration,
+    pub response: Response,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.stage, self.fault.as_str())?;
+        if self.fault != Fault::Panicked {
+            write!(f, " for {:?}", self.after)?;
+        }
+        match self.response {
+            Response::Alert => Ok(()),
+            Response::TripKillSwitch => write!(f, ", kill switch tripped"),
+            Response::Shutdown => write!(f, ", kill switch tripped, pipeline shutting down"),
+        }
+    }
+}
+
+type AlertSink = Box<dyn Fn(&Alert) + Send>;
+
+// Supervisor settings for `PipelineBuilder::watchdog`. By default a panic
+// shuts the pipeline down and stalls or full queues only alert.
+pub struct Watchdog {
+    poll_interval: Duration,
+    stall_timeout: Duration,
+    queue_full_timeout: Duration,
+    responses: [Response; 3],
+    kill_switch: Arc<KillSwitch>,
+    on_alert: AlertSink,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Watchdog {
+            poll_interval: Duration::from_millis(10),
+            stall_timeout: Duration::from_millis(500),
+            queue_full_timeout: Duration::from_secs(1),
+            responses: [Response::Shutdown, Response::Alert, Response::Alert],
+            kill_switch: Arc::default(),
+            on_alert: Box::new(|alert| eprintln!("Watchdog: {}", alert)),
+        }
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    pub fn queue_full_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_full_timeout = timeout;
+        self
+    }
+
+    pub fn respond(mut self, fault: Fault, response: Response) -> Self {
+        self.responses[fault as usize] = response;
+        self
+    }
+
+    // Set by `PipelineBuilder` to the pipeline's own switch.
+    pub fn kill_switch(mut self, kill_switch: Arc<KillSwitch>) -> Self {
+        self.kill_switch = kill_switch;
+        self
+    }
+
+    pub fn on_alert(mut self, on_alert: impl Fn(&Alert) + Send + 'static) -> Self {
+        self.on_alert = Box::new(on_alert);
+        self
+    }
+
+    // `stages` are (name, heartbeat, stop flag) in pipeline order.
+    pub(crate) fn spawn(
+        self,
+        stages: Vec<(String, Arc<Heartbeat>, Arc<AtomicBool>)>,
+    ) -> io::Result<WatchdogHandle> {
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = done.clone();
+        let thread = thread::Builder::new()
+            .name("hft-watchdog".to_string())
+            .spawn(move || self.run(stages, &stop))?;
+
+        Ok(WatchdogHandle { done, thread })
+    }
+
+    fn run(
+        self,
+        stages: Vec<(String, Arc<Heartbeat>, Arc<AtomicBool>)>,
+        done: &AtomicBool,
+    ) -> Vec<Alert> {
+        let clock = TscClock::global();
+        let started = Instant::now();
+        let mut watches: Vec<Watch> = stages
+            .iter()
+            .map(|(_, heartbeat, _)| Watch::new(heartbeat.beats(), started))
+            .collect();
+        let mut alerts = Vec::new();
+
+        while !done.load(Ordering::Acquire) {
+            thread::sleep(self.poll_interval);
+            let now = Instant::now();
+            let cycles = rdtsc().cycles();
+
+            for ((name, heartbeat, _), watch) in stages.iter().zip(&mut watches) {
+                let fault = match heartbeat.state() {
+                    StageState::Exited => None,
+                    StageState::Panicked => (!watch.panicked).then(|| {
+                        watch.panicked = true;
+                        (Fault::Panicked, Duration::ZERO)
+                    }),
+                    StageState::Running => watch.check(
+                        heartbeat,
+                        now,
+                        cycles,
+                        clock,
+                        self.stall_timeout,
+                        self.queue_full_timeout,
+                    ),
+                };
+
+                if let Some((fault, after)) = fault {
+                    let alert = Alert {
+                        stage: name.clone(),
+                        fault,
+                        after,
+                        response: self.responses[fault as usize],
+                    };
+                    self.respond_to(&alert, &stages);
+                    alerts.push(alert);
+                }
+            }
+        }
+
+        alerts
+    }
+
+    fn respond_to(&self, alert: &Alert, stages: &[(String, Arc<Heartbeat>, Arc<AtomicBool>)]) {
+        (self.on_alert)(alert);
+
+        if alert.response >= Response::TripKillSwitch {
+            self.kill_switch.trip();
+        }
+        if alert.response >= Response::Shutdown {
+            for (_, heartbeat, stop) in stages {
+                heartbeat.abort();
+                stop.store(true, Ordering::Release);
+            }
+        }
+    }
+}
+
+// What the watchdog last saw of one stage. Each fault is reported once per
+// episode: a stall until the stage beats again, a full queue until that
+// particular wait ends.
+struct Watch {
+    beats: u64,
+    beat_at: Instant,
+    stalled: bool,
+    blocked_reported: u64,
+    panicked: bool,
+}
+
+impl Watch {
+    fn new(beats: u64, now: Instant) -> Self {
+        Watch {
+            beats,
+            beat_at: now,
+            stalled: false,
+            blocked_reported: 0,
+            panicked: false,
+        }
+    }
+
+    fn check(
+        &mut self,
+        heartbeat: &Heartbeat,
+        now: Instant,
+        cycles: u64,
+        clock: &TscClock,
+        stall_timeout: Duration,
+        queue_full_timeout: Duration,
+    ) -> Option<(Fault, Duration)> {
+        let beats = heartbeat.beats();
+        if beats != self.beats {
+            self.beats = beats;
+            self.beat_at = now;
+            self.stalled = false;
+        } else if !self.stalled && now - self.beat_at >= stall_timeout {
+            self.stalled = true;
+            return Some((Fault::Stalled, now - self.beat_at));
+        }
+
+        let since = heartbeat.blocked_since()?.cycles();
+        let blocked = Duration::from_nanos(clock.cycles_to_nanos(cycles.saturating_sub(since)));
+        if since != self.blocked_reported && blocked >= queue_full_timeout {
+            self.blocked_reported = since;
+            return Some((Fault::QueueFull, blocked));
+        }
+
+        None
+    }
+}
+
+pub(crate) struct WatchdogHandle {
+    done: Arc<AtomicBool>,
+    thread: thread::JoinHandle<Vec<Alert>>,
+}
+
+impl WatchdogHandle {
+    pub(crate) fn join(self) -> Vec<Alert> {
+        self.done.store(true, Ordering::Release);
+        self.thread.join().expect("watchdog panicked")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{broadcast, spsc};
+
+    fn stage(name: &str) -> (String, Arc<Heartbeat>, Arc<AtomicBool>) {
+        (
+            name.to_string(),
+            Arc::new(Heartbeat::new()),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    #[test]
+    fn test_aborted_push_gives_up() {
+        let (mut tx, _rx) = spsc::channel::<u64>(2);
+        let heartbeat = Heartbeat::new();
+        let mut waiter = Waiter::busy_spin();
+
+        while tx.push(0).is_ok() {}
+        heartbeat.abort();
+
+        assert!(!push_supervised(&mut tx, 1, &mut waiter, &heartbeat));
+        assert_eq!(heartbeat.blocked_since(), None);
+    }
+
+    #[test]
+    fn test_aborted_publish_gives_up() {
+        let mut publisher = broadcast::channel::<u64>(2);
+        let _gated = publisher.subscribe_gated();
+        let heartbeat = Heartbeat::new();
+        let mut waiter = Waiter::busy_spin();
+
+        while publisher.has_room() {
+            publisher.publish(0);
+        }
+        heartbeat.abort();
+
+        assert!(!await_room(&mut publisher, &mut waiter, &heartbeat));
+        assert_eq!(heartbeat.blocked_since(), None);
+    }
+
+    #[test]
+    fn test_panic_trips_kill_switch_and_stops_stages() {
+        let kill_switch = Arc::new(KillSwitch::new());
+        let stages = vec![stage("hft-market-data"), stage("hft-gateway")];
+        let heartbeat = stages[1].1.clone();
+        thread::spawn(move || {
+            let _guard = ExitGuard(heartbeat);
+            panic!("gateway failure");
+        })
+        .join()
+        .unwrap_err();
+
+        let watchdog = Watchdog::new()
+            .poll_interval(Duration::from_millis(1))
+            .kill_switch(kill_switch.clone())
+            .on_alert(|_| {})
+            .spawn(stages.clone())
+            .unwrap();
+        while !stages[0].2.load(Ordering::Acquire) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let alerts = watchdog.join();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].stage, "hft-gateway");
+        assert_eq!(alerts[0].fault, Fault::Panicked);
+        assert!(kill_switch.is_tripped());
+        assert!(
+            stages
+                .iter()
+                .all(|(_, heartbeat, _)| heartbeat.is_aborted())
+        );
+    }
+
+    #[test]
+    fn test_stall_reported_once() {
+        let kill_switch = Arc::new(KillSwitch::new());
+        let stages = vec![stage("hft-risk")];
+        let watchdog = Watchdog::new()
+            .poll_interval(Duration::from_millis(1))
+            .stall_timeout(Duration::from_millis(5))
+            .respond(Fault::Stalled, Response::TripKillSwitch)
+            .kill_switch(kill_switch.clone())
+            .on_alert(|_| {})
+            .spawn(stages.clone())
+            .unwrap();
+        while !kill_switch.is_tripped() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(20));
+        let alerts = watchdog.join();
+
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].fault, Fault::Stalled);
+        assert!(alerts[0].after >= Duration::from_millis(5));
+        assert!(!stages[0].2.load(Ordering::Acquire));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::core::types::{Price, Quantity};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+pub const DEFAULT_TICKER: &str = "DEMO";
+
+// ISO 4217 code, stored inline rather than as a `String`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+
+    pub const fn new(code: [u8; 3]) -> Self {
+        Currency(code)
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Static reference data for one tradable instrument. Prices must sit on the
+// tick grid inside `[band_low, band_high]`; quantities must be whole lots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    pub ticker: String,
+    pub tick_size: Price,
+    pub lot_size: Quantity,
+    pub band_low: Price,
+    pub band_high: Price,
+    pub currency: Currency,
+}
+
+impl Instrument {
+    pub fn new(ticker: impl Into<String>) -> Self {
+        Instrument {
+            ticker: ticker.into(),
+            tick_size: Price::from_raw(1),
+            lot_size: Quantity::from_raw(1),
+            band_low: Price::from_raw(1),
+            band_high: Price::from_raw(i64::MAX),
+            currency: Currency::USD,
+        }
+    }
+
+    pub fn tick_size(mut self, tick_size: Price) -> Self {
+        self.tick_size = tick_size;
+        self
+    }
+
+    pub fn lot_size(mut self, lot_size: Quantity) -> Self {
+        self.lot_size = lot_size;
+        self
+    }
+
+    pub fn price_band(mut self, low: Price, high: Price) -> Self {
+        self.band_low = low;
+        self.band_high = high;
+        self
+    }
+
+    pub fn currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    #[inline(always)]
+    pub fn is_valid_price(&self, price: Price) -> bool {
+        price >= self.band_low && price <= self.band_high && price.raw() % self.tick_size.raw() == 0
+    }
+
+    #[inline(always)]
+    pub fn is_valid_quantity(&self, qty: Quantity) -> bool {
+        qty.raw() > 0 && qty.raw() % self.lot_size.raw() == 0
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: &str| {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: {}", self.ticker, msg),
+            ))
+        };
+
+        if self.ticker.is_empty() {
+            return invalid("empty ticker");
+        }
+        if self.tick_size.raw() <= 0 {
+            return invalid("tick size must be positive");
+        }
+        if self.lot_size.raw() <= 0 {
+            return invalid("lot size must be positive");
+        }
+        if self.band_low > self.band_high {
+            return invalid("price band is inverted");
+        }
+        Ok(())
+    }
+}
+
+// Maps exchange tickers to dense `u32` symbol IDs, assigned in insertion
+// order, so per-symbol state can live in flat arrays indexed by ID. Built once
+// before the pipeline starts and shared read-only behind an `Arc`.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    instruments: Vec<Instrument>,
+    ids: HashMap<String, u32>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // One instrument with default reference data; the stage configs start
+    // from this so a bare pipeline still has something to trade.
+    pub fn single(ticker: &str) -> Self {
+        let mut table = SymbolTable::new();
+        table
+            .insert(Instrument::new(ticker))
+            .expect("valid default instrument");
+        table
+    }
+
+    pub fn insert(&mut self, instrument: Instrument) -> io::Result<u32> {
+        instrument.validate()?;
+
+        if self.ids.contains_key(&instrument.ticker) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("duplicate ticker {}", instrument.ticker),
+            ));
+        }
+        let id = u32::try_from(self.instruments.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "symbol table full"))?;
+
+        self.ids.insert(instrument.ticker.clone(), id);
+        self.instruments.push(instrument);
+        Ok(id)
+    }
+
+    #[inline]
+    pub fn id(&self, ticker: &str) -> Option<u32> {
+        self.ids.get(ticker).copied()
+    }
+
+    #[inline(always)]
+    pub fn get(&self, symbol: u32) -> Option<&Instrument> {
+        self.instruments.get(symbol as usize)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.instruments.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Instrument)> {
+        self.instruments
+            .iter()
+            .enumerate()
+            .map(|(id, instrument)| (id as u32, instrument))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dense_ids() {
+        let mut table = SymbolTable::new();
+        let aapl = table.insert(Instrument::new("AAPL")).unwrap();
+        let msft = table.insert(Instrument::new("MSFT")).unwrap();
+
+        assert_eq!((aapl, msft), (0, 1));
+        assert_eq!(table.id("MSFT"), Some(1));
+        assert_eq!(table.get(0).unwrap().ticker, "AAPL");
+        assert_eq!(table.id("TSLA"), None);
+        assert!(table.get(2).is_none());
+
+        let err = table.insert(Instrument::new("AAPL")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_instrument_validation() {
+        let es = Instrument::new("ES")
+            .tick_size(Price::new(0, 2500))
+            .lot_size(Quantity::new(1, 0))
+            .price_band(Price::new(4000, 0), Price::new(6000, 0))
+            .currency(Currency::new(*b"USD"));
+
+        assert!(es.is_valid_price(Price::new(5000, 2500)));
+        assert!(!es.is_valid_price(Price::new(5000, 1000)));
+        assert!(!es.is_valid_price(Price::new(7000, 0)));
+        assert!(es.is_valid_quantity(Quantity::new(3, 0)));
+        assert!(!es.is_valid_quantity(Quantity::new(0, 5000)));
+        assert_eq!(es.currency.to_string(), "USD");
+
+        let inverted = Instrument::new("BAD").price_band(Price::new(2, 0), Price::new(1, 0));
+        assert!(SymbolTable::new().insert(inverted).is_err());
+    }
+}
@@ -1,14 +1,20 @@
-use hft_engine::OrderBook;
-use hft_engine::core::spsc::SpscQueue;
+use hft_engine::Book10;
 use hft_engine::core::types::{Price, Quantity, Timestamp};
+use hft_engine::core::{CpuTopology, Stage, Waiter, spsc};
 use hft_engine::messages::{
-    MarketEvent, Order, PriceLevel, RejectReason, RiskDecision, Side, SignalEvent,
+    BBO, BookSnapshot, MarketEvent, Order, PriceLevel, RejectReason, RiskDecision, Side,
+    SignalEvent, Trace,
 };
+use hft_engine::pipeline::Pipeline;
+use hft_engine::pipeline::gateway::GatewayConfig;
+use hft_engine::pipeline::market_data::MarketDataConfig;
+use hft_engine::pipeline::risk::RiskConfig;
+use hft_engine::pipeline::strategy::StrategyConfig;
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const SYMBOL: u32 = 1;
 
@@ -16,9 +22,9 @@ const SYMBOL: u32 = 1;
 fn test_full_pipeline_100k_ticks() {
     const TICK_COUNT: u64 = 1000;
 
-    let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(4096));
-    let strategy_to_risk = Arc::new(SpscQueue::<SignalEvent>::new(4096));
-    let risk_to_gateway = Arc::new(SpscQueue::<RiskDecision>::new(4096));
+    let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(4096);
+    let (mut strategy_tx, mut risk_rx) = spsc::channel::<SignalEvent>(4096);
+    let (mut risk_tx, mut gateway_rx) = spsc::channel::<RiskDecision>(4096);
 
     let running = Arc::new(AtomicBool::new(true));
     let ticks_sent = Arc::new(AtomicU64::new(0));
@@ -26,12 +32,11 @@ fn test_full_pipeline_100k_ticks() {
     let orders_approved = Arc::new(AtomicU64::new(0));
     let orders_sent = Arc::new(AtomicU64::new(0));
 
-    let r2g = risk_to_gateway.clone();
     let run3 = running.clone();
     let sent = orders_sent.clone();
     let gateway_handle = thread::spawn(move || {
         loop {
-            if let Some(decision) = r2g.pop() {
+            if let Some(decision) = gateway_rx.pop() {
                 match decision {
                     RiskDecision::Approve(order) => {
                         assert!(order.qty.raw() > 0, "Order has zero quantity");
@@ -48,8 +53,6 @@ fn test_full_pipeline_100k_ticks() {
         }
     });
 
-    let s2r = strategy_to_risk.clone();
-    let r2g = risk_to_gateway.clone();
     let run2 = running.clone();
     let approved = orders_approved.clone();
     let risk_handle = thread::spawn(move || {
@@ -57,7 +60,7 @@ fn test_full_pipeline_100k_ticks() {
         let mut order_id = 1u64;
 
         loop {
-            if let Some(signal) = s2r.pop() {
+            if let Some(signal) = risk_rx.pop() {
                 let (qty, side, price, timestamp) = match signal {
                     SignalEvent::Buy {
                         qty,
@@ -93,7 +96,7 @@ fn test_full_pipeline_100k_ticks() {
 
                 order_id += 1;
 
-                while r2g.push(decision).is_err() {
+                while risk_tx.push(decision).is_err() {
                     std::hint::spin_loop();
                 }
             } else if !run2.load(Ordering::Relaxed) {
@@ -104,19 +107,17 @@ fn test_full_pipeline_100k_ticks() {
         }
     });
 
-    let m2s = market_to_strategy.clone();
-    let s2r = strategy_to_risk.clone();
     let run1 = running.clone();
     let signals = signals_generated.clone();
     let strategy_handle = thread::spawn(move || {
         loop {
-            if let Some(event) = m2s.pop() {
-                if let MarketEvent::BookUpdate {
+            if let Some(event) = strategy_rx.pop() {
+                if let MarketEvent::BookUpdate(BookSnapshot {
                     bids,
                     asks,
                     timestamp,
                     ..
-                } = event
+                }) = event
                 {
                     let best_bid = bids
                         .iter()
@@ -132,15 +133,17 @@ fn test_full_pipeline_100k_ticks() {
 
                         if spread < 5 {
                             let signal = SignalEvent::Buy {
+                                strategy_id: 0,
                                 symbol: SYMBOL,
                                 price: bid,
                                 qty: Quantity::new(10, 0),
                                 timestamp,
+                                trace: Trace::from_origin(timestamp),
                             };
 
                             signals.fetch_add(1, Ordering::Relaxed);
 
-                            while s2r.push(signal).is_err() {
+                            while strategy_tx.push(signal).is_err() {
                                 std::hint::spin_loop();
                             }
                         }
@@ -154,10 +157,9 @@ fn test_full_pipeline_100k_ticks() {
         }
     });
 
-    let m2s = market_to_strategy.clone();
     let ticks = ticks_sent.clone();
     let market_handle = thread::spawn(move || {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         for i in 0..TICK_COUNT {
             let spread_offset = if i % 100 < 20 { 1 } else { 10 };
@@ -175,14 +177,15 @@ fn test_full_pipeline_100k_ticks() {
             bids[..bid_slice.len()].copy_from_slice(bid_slice);
             asks[..ask_slice.len()].copy_from_slice(ask_slice);
 
-            let event = MarketEvent::BookUpdate {
+            let event = MarketEvent::BookUpdate(BookSnapshot {
                 symbol: SYMBOL,
                 bids,
                 asks,
+                seq: 0,
                 timestamp: Timestamp::from_cycles(unsafe { core::arch::x86_64::_rdtsc() }),
-            };
+            });
 
-            while m2s.push(event).is_err() {
+            while market_tx.push(event).is_err() {
                 std::hint::spin_loop();
             }
 
@@ -225,24 +228,23 @@ fn test_full_pipeline_100k_ticks() {
 
 #[test]
 fn test_pipeline_message_correctness() {
-    let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(16));
-    let strategy_to_risk = Arc::new(SpscQueue::<SignalEvent>::new(16));
-    let risk_to_gateway = Arc::new(SpscQueue::<RiskDecision>::new(16));
+    let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(16);
+    let (mut strategy_tx, mut risk_rx) = spsc::channel::<SignalEvent>(16);
+    let (mut risk_tx, mut gateway_rx) = spsc::channel::<RiskDecision>(16);
 
     let running = Arc::new(AtomicBool::new(true));
 
-    let r2g = risk_to_gateway.clone();
     let run3 = running.clone();
     let gateway_handle = thread::spawn(move || {
         let mut orders = Vec::new();
 
         while run3.load(Ordering::Relaxed) {
-            if let Some(decision) = r2g.pop() {
-                if let RiskDecision::Approve(order) = decision {
-                    orders.push(order);
-                    if orders.len() >= 10 {
-                        break;
-                    }
+            if let Some(decision) = gateway_rx.pop()
+                && let RiskDecision::Approve(order) = decision
+            {
+                orders.push(order);
+                if orders.len() >= 10 {
+                    break;
                 }
             }
         }
@@ -250,14 +252,12 @@ fn test_pipeline_message_correctness() {
         orders
     });
 
-    let s2r = strategy_to_risk.clone();
-    let r2g = risk_to_gateway.clone();
     let run2 = running.clone();
     let risk_handle = thread::spawn(move || {
         let mut order_id = 1u64;
 
         while run2.load(Ordering::Relaxed) {
-            if let Some(signal) = s2r.pop() {
+            if let Some(signal) = risk_rx.pop() {
                 let (qty, side, price, timestamp) = match signal {
                     SignalEvent::Buy {
                         qty,
@@ -277,43 +277,40 @@ fn test_pipeline_message_correctness() {
                 let order = Order::new(order_id, SYMBOL, price, qty, side, timestamp);
                 order_id += 1;
 
-                while r2g.push(RiskDecision::Approve(order)).is_err() {
+                while risk_tx.push(RiskDecision::Approve(order)).is_err() {
                     std::hint::spin_loop();
                 }
             }
         }
     });
 
-    let m2s = market_to_strategy.clone();
-    let s2r = strategy_to_risk.clone();
     let run1 = running.clone();
     let strategy_handle = thread::spawn(move || {
         while run1.load(Ordering::Relaxed) {
-            if let Some(event) = m2s.pop() {
-                if let MarketEvent::BookUpdate {
+            if let Some(event) = strategy_rx.pop()
+                && let MarketEvent::BookUpdate(BookSnapshot {
                     bids, timestamp, ..
-                } = event
-                {
-                    if let Some(bid_level) = bids.iter().find(|l| l.qty.raw() > 0) {
-                        let signal = SignalEvent::Buy {
-                            symbol: SYMBOL,
-                            price: bid_level.price,
-                            qty: Quantity::new(10, 0),
-                            timestamp,
-                        };
+                }) = event
+                && let Some(bid_level) = bids.iter().find(|l| l.qty.raw() > 0)
+            {
+                let signal = SignalEvent::Buy {
+                    strategy_id: 0,
+                    symbol: SYMBOL,
+                    price: bid_level.price,
+                    qty: Quantity::new(10, 0),
+                    timestamp,
+                    trace: Trace::from_origin(timestamp),
+                };
 
-                        while s2r.push(signal).is_err() {
-                            std::hint::spin_loop();
-                        }
-                    }
+                while strategy_tx.push(signal).is_err() {
+                    std::hint::spin_loop();
                 }
             }
         }
     });
 
-    let m2s = market_to_strategy.clone();
     let market_handle = thread::spawn(move || {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         for i in 0..10 {
             let price = Price::new(10000 + i, 0);
@@ -326,14 +323,15 @@ fn test_pipeline_message_correctness() {
             bids[..bid_slice.len()].copy_from_slice(bid_slice);
             asks[..ask_slice.len()].copy_from_slice(ask_slice);
 
-            let event = MarketEvent::BookUpdate {
+            let event = MarketEvent::BookUpdate(BookSnapshot {
                 symbol: SYMBOL,
                 bids,
                 asks,
+                seq: 0,
                 timestamp: Timestamp::from_cycles(unsafe { core::arch::x86_64::_rdtsc() }),
-            };
+            });
 
-            while m2s.push(event).is_err() {
+            while market_tx.push(event).is_err() {
                 std::hint::spin_loop();
             }
 
@@ -369,14 +367,13 @@ fn test_pipeline_message_correctness() {
 
 #[test]
 fn test_pipeline_risk_rejection() {
-    let market_to_strategy = Arc::new(SpscQueue::<MarketEvent>::new(16));
-    let strategy_to_risk = Arc::new(SpscQueue::<SignalEvent>::new(16));
-    let risk_to_gateway = Arc::new(SpscQueue::<RiskDecision>::new(16));
+    let (mut market_tx, mut strategy_rx) = spsc::channel::<MarketEvent>(16);
+    let (mut strategy_tx, mut risk_rx) = spsc::channel::<SignalEvent>(16);
+    let (mut risk_tx, mut gateway_rx) = spsc::channel::<RiskDecision>(16);
 
     let running = Arc::new(AtomicBool::new(true));
     let rejections = Arc::new(AtomicU64::new(0));
 
-    let r2g = risk_to_gateway.clone();
     let run3 = running.clone();
     let rej = rejections.clone();
     let gateway_handle = thread::spawn(move || {
@@ -384,7 +381,7 @@ fn test_pipeline_risk_rejection() {
         let mut rejected = 0;
 
         while run3.load(Ordering::Relaxed) {
-            if let Some(decision) = r2g.pop() {
+            if let Some(decision) = gateway_rx.pop() {
                 match decision {
                     RiskDecision::Approve(_) => approved += 1,
                     RiskDecision::Reject { .. } => rejected += 1,
@@ -399,15 +396,13 @@ fn test_pipeline_risk_rejection() {
         rej.store(rejected, Ordering::Relaxed);
     });
 
-    let s2r = strategy_to_risk.clone();
-    let r2g = risk_to_gateway.clone();
     let run2 = running.clone();
     let risk_handle = thread::spawn(move || {
         let mut order_id = 1u64;
         let mut count = 0;
 
         while run2.load(Ordering::Relaxed) {
-            if let Some(signal) = s2r.pop() {
+            if let Some(signal) = risk_rx.pop() {
                 let decision = if count % 2 == 0 {
                     let (qty, side, price, timestamp) = match signal {
                         SignalEvent::Buy {
@@ -436,50 +431,47 @@ fn test_pipeline_risk_rejection() {
                 order_id += 1;
                 count += 1;
 
-                while r2g.push(decision).is_err() {
+                while risk_tx.push(decision).is_err() {
                     std::hint::spin_loop();
                 }
             }
         }
     });
 
-    let m2s = market_to_strategy.clone();
-    let s2r = strategy_to_risk.clone();
     let run1 = running.clone();
     let strategy_handle = thread::spawn(move || {
         let mut count = 0;
 
         while run1.load(Ordering::Relaxed) {
-            if let Some(event) = m2s.pop() {
-                if let MarketEvent::BookUpdate {
+            if let Some(event) = strategy_rx.pop()
+                && let MarketEvent::BookUpdate(BookSnapshot {
                     bids, timestamp, ..
-                } = event
-                {
-                    if let Some(bid_level) = bids.iter().find(|l| l.qty.raw() > 0) {
-                        let signal = SignalEvent::Buy {
-                            symbol: SYMBOL,
-                            price: bid_level.price,
-                            qty: Quantity::new(10, 0),
-                            timestamp,
-                        };
+                }) = event
+                && let Some(bid_level) = bids.iter().find(|l| l.qty.raw() > 0)
+            {
+                let signal = SignalEvent::Buy {
+                    strategy_id: 0,
+                    symbol: SYMBOL,
+                    price: bid_level.price,
+                    qty: Quantity::new(10, 0),
+                    timestamp,
+                    trace: Trace::from_origin(timestamp),
+                };
 
-                        while s2r.push(signal).is_err() {
-                            std::hint::spin_loop();
-                        }
+                while strategy_tx.push(signal).is_err() {
+                    std::hint::spin_loop();
+                }
 
-                        count += 1;
-                        if count >= 20 {
-                            break;
-                        }
-                    }
+                count += 1;
+                if count >= 20 {
+                    break;
                 }
             }
         }
     });
 
-    let m2s = market_to_strategy.clone();
     let market_handle = thread::spawn(move || {
-        let mut book = OrderBook::new();
+        let mut book = Book10::new();
 
         for i in 0..20 {
             let price = Price::new(10000 + i, 0);
@@ -492,14 +484,15 @@ fn test_pipeline_risk_rejection() {
             bids[..bid_slice.len()].copy_from_slice(bid_slice);
             asks[..ask_slice.len()].copy_from_slice(ask_slice);
 
-            let event = MarketEvent::BookUpdate {
+            let event = MarketEvent::BookUpdate(BookSnapshot {
                 symbol: SYMBOL,
                 bids,
                 asks,
+                seq: 0,
                 timestamp: Timestamp::from_cycles(unsafe { core::arch::x86_64::_rdtsc() }),
-            };
+            });
 
-            while m2s.push(event).is_err() {
+            while market_tx.push(event).is_err() {
                 std::hint::spin_loop();
             }
 
@@ -518,3 +511,112 @@ fn test_pipeline_risk_rejection() {
     let rejected = rejections.load(Ordering::Relaxed);
     assert_eq!(rejected, 10, "Should reject exactly half (10/20) signals");
 }
+
+#[test]
+fn test_pipeline_builder_spawns_and_drains() {
+    let placement = CpuTopology::flat(1).plan();
+    let pipeline = Pipeline::builder()
+        .placement(&placement)
+        .spawn()
+        .expect("Failed to spawn pipeline");
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(pipeline.metrics().market_data.ticks.load(Ordering::Relaxed) > 0);
+
+    pipeline.shutdown();
+    let report = pipeline.join();
+
+    for stage in Stage::ALL {
+        assert_eq!(report.stage(stage).count(), 1, "{:?}", stage);
+    }
+    let gateway = report.stage(Stage::Gateway).next().unwrap();
+    assert_eq!(gateway.cpu_id, 0);
+}
+
+#[test]
+fn test_parked_pipeline_wakes_and_joins() {
+    let pipeline = Pipeline::builder()
+        .placement(&CpuTopology::flat(1).plan())
+        .wait(Waiter::spin_then_park(64, Duration::from_secs(30)))
+        .spawn()
+        .expect("Failed to spawn pipeline");
+
+    thread::sleep(Duration::from_millis(50));
+    pipeline.shutdown();
+
+    // Parked stages are woken by their producers and by `join`, not by the
+    // 30s timeout.
+    let start = Instant::now();
+    let report = pipeline.join();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(report.panicked.is_empty());
+    assert!(report.stage(Stage::Strategy).next().unwrap().received > 0);
+}
+
+#[test]
+fn test_pipeline_builder_bbo_depth() {
+    let pipeline = Pipeline::builder()
+        .placement(&CpuTopology::flat(1).plan())
+        .depth::<BBO>()
+        .spawn()
+        .expect("Failed to spawn pipeline");
+
+    pipeline.shutdown();
+    let report = pipeline.join();
+
+    assert_eq!(report.stages.len(), Stage::COUNT);
+    assert!(report.panicked.is_empty());
+}
+
+#[test]
+fn test_pipelines_have_separate_kill_switches() {
+    let placement = CpuTopology::flat(1).plan();
+    let first = Pipeline::builder()
+        .placement(&placement)
+        .spawn()
+        .expect("Failed to spawn pipeline");
+    let second = Pipeline::builder()
+        .placement(&placement)
+        .spawn()
+        .expect("Failed to spawn pipeline");
+
+    first.kill_switch().trip();
+    assert!(first.kill_switch().is_tripped());
+    assert!(!second.kill_switch().is_tripped());
+
+    first.shutdown();
+    second.shutdown();
+    assert!(first.join().panicked.is_empty());
+    assert!(second.join().panicked.is_empty());
+}
+
+#[test]
+fn test_downstream_panic_does_not_hang_join() {
+    // Pinning to a CPU that doesn't exist makes the gateway's thread setup
+    // panic, leaving risk to fill the decision queue with nobody draining it.
+    let pipeline = Pipeline::builder()
+        .market_data(MarketDataConfig {
+            cpu_id: 0,
+            ..Default::default()
+        })
+        .strategy(StrategyConfig {
+            cpu_id: 0,
+            ..Default::default()
+        })
+        .risk(RiskConfig {
+            cpu_id: 0,
+            ..Default::default()
+        })
+        .gateway(GatewayConfig {
+            cpu_id: 100_000,
+            ..Default::default()
+        })
+        .decision_capacity(4)
+        .spawn()
+        .expect("Failed to spawn pipeline");
+
+    let report = pipeline.join();
+
+    assert_eq!(report.panicked, ["hft-gateway"]);
+    assert_eq!(report.stages.len(), Stage::COUNT - 1);
+}